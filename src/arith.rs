@@ -0,0 +1,521 @@
+//! Integer arithmetic for `$((...))` and `let`
+//!
+//! Expressions use 64-bit signed integers and C's operators and
+//! precedence: `+ - * / %`, `<< >>`, `& ^ | ~`, comparisons, `! && ||`,
+//! `?:`, `=` with the compound assignments, `++`/`--` and `,`. Numbers are
+//! decimal, octal with a leading `0`, or hexadecimal with `0x`. A name
+//! stands for the variable's value, itself read as an expression, and 0
+//! when unset or empty. Overflow is an error instead of wrapping around.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::shell::Shell;
+
+/// How deeply variables may refer to other variables, as in `a=b b=a`
+const MAX_VARIABLE_DEPTH: usize = 32;
+
+/// How deeply parentheses, unary operators, assignments and `?:` may nest
+/// in one expression, or arithmetic expansions inside each other, as each
+/// level takes stack to parse and evaluate
+pub(crate) const MAX_NESTING_DEPTH: usize = 64;
+
+/// Why an expression has no value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArithError {
+    DivisionByZero,
+    /// The result, or a shift count, does not fit in 64 bits
+    Overflow,
+    /// A token the grammar does not allow there; `None` at the end
+    Unexpected(Option<String>),
+    InvalidNumber(String),
+    /// `=`, `+=`, `++` and the like on something other than a name
+    NotAVariable,
+    /// Variables whose values keep referring to each other
+    TooDeep(String),
+    /// More than `MAX_NESTING_DEPTH` levels of nesting
+    NestedTooDeep,
+}
+
+impl fmt::Display for ArithError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArithError::DivisionByZero => write!(f, "division by zero"),
+            ArithError::Overflow => write!(f, "arithmetic overflow"),
+            ArithError::Unexpected(Some(token)) => write!(f, "syntax error near `{}'", token),
+            ArithError::Unexpected(None) => write!(f, "syntax error: operand expected"),
+            ArithError::InvalidNumber(number) => write!(f, "invalid number `{}'", number),
+            ArithError::NotAVariable => write!(f, "attempted assignment to non-variable"),
+            ArithError::TooDeep(name) => write!(f, "{}: expression recursion level exceeded", name),
+            ArithError::NestedTooDeep => write!(f, "expression nesting too deep"),
+        }
+    }
+}
+
+impl std::error::Error for ArithError {}
+
+/// Evaluate an expression, assigning to the shell's variables as it says
+/// An empty expression is 0.
+pub fn evaluate(expression: &str, shell: &mut Shell) -> Result<i64, ArithError> {
+    evaluate_at(expression, shell, 0)
+}
+
+fn evaluate_at(expression: &str, shell: &mut Shell, depth: usize) -> Result<i64, ArithError> {
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        depth: 0,
+    };
+    let expr = parser.parse_comma()?;
+    if let Some(token) = parser.tokens.get(parser.position) {
+        return Err(ArithError::Unexpected(Some(token.to_string())));
+    }
+    Evaluator { shell, depth }.eval(&expr)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Operator(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{}", number),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(operator) => write!(f, "{}", operator),
+        }
+    }
+}
+
+/// Operators, longest first so that `<<=` is not read as `<` `<=`
+const OPERATORS: [&str; 35] = [
+    "<<=", ">>=", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "&=",
+    "^=", "|=", "++", "--", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=",
+];
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, ArithError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = expression.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            tokens.push(Token::Number(read_number(&mut chars)?));
+        } else if c == '_' || c.is_ascii_alphabetic() {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+                name.push(c);
+            }
+            tokens.push(Token::Name(name));
+        } else if c == '(' || c == ')' || c == ',' {
+            chars.next();
+            tokens.push(Token::Operator(match c {
+                '(' => "(",
+                ')' => ")",
+                _ => ",",
+            }));
+        } else {
+            let rest: String = chars.clone().take(3).collect();
+            let Some(&operator) = OPERATORS.iter().find(|operator| rest.starts_with(*operator)) else {
+                return Err(ArithError::Unexpected(Some(c.to_string())));
+            };
+            // `++` and `--` only go with a name, so `1--1` is `1 - -1`
+            let operator = match operator {
+                "++" | "--" if !next_to_name(&tokens, &rest) => &operator[..1],
+                operator => operator,
+            };
+            for _ in 0..operator.len() {
+                chars.next();
+            }
+            tokens.push(Token::Operator(operator));
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Whether a `++` or `--` at the start of `rest` follows or precedes a name
+fn next_to_name(tokens: &[Token], rest: &str) -> bool {
+    matches!(tokens.last(), Some(Token::Name(_)))
+        || rest[2..].starts_with(|c: char| c == '_' || c.is_ascii_alphabetic())
+}
+
+/// Read a decimal, octal (`017`) or hexadecimal (`0x1f`) number
+fn read_number(chars: &mut Peekable<Chars>) -> Result<i64, ArithError> {
+    let mut text = String::new();
+    while let Some(c) = chars.next_if(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+        text.push(c);
+    }
+    parse_number(&text)
+}
+
+fn parse_number(text: &str) -> Result<i64, ArithError> {
+    let (digits, radix) = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (hex, 16)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (&text[1..], 8)
+    } else {
+        (text, 10)
+    };
+
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return Err(ArithError::InvalidNumber(text.to_string()));
+    }
+    i64::from_str_radix(digits, radix).map_err(|_| ArithError::Overflow)
+}
+
+/// A parsed expression; parsing it all first lets `&&`, `||` and `?:`
+/// skip the side effects of the part they do not evaluate
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Variable(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// `name = value`, or `name op= value` with the operator
+    Assign(String, Option<&'static str>, Box<Expr>),
+    /// `++name` and the like: the change, and whether the new value is the
+    /// result
+    Increment(String, i64, bool),
+}
+
+/// Binary operators from the loosest to the tightest binding
+const LEVELS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    /// How many of the rules that contain themselves are open
+    depth: usize,
+}
+
+impl Parser {
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) => Some(operator),
+            _ => None,
+        }
+    }
+
+    fn next_if_operator(&mut self, operators: &[&str]) -> Option<&'static str> {
+        let operator = self.peek_operator().filter(|operator| operators.contains(operator))?;
+        self.position += 1;
+        Some(operator)
+    }
+
+    fn expect(&mut self, operator: &str) -> Result<(), ArithError> {
+        match self.next_if_operator(&[operator]) {
+            Some(_) => Ok(()),
+            None => Err(self.unexpected()),
+        }
+    }
+
+    fn unexpected(&self) -> ArithError {
+        ArithError::Unexpected(self.tokens.get(self.position).map(Token::to_string))
+    }
+
+    /// Parse with `rule` one level deeper, where the grammar recurses
+    fn nested(&mut self, rule: fn(&mut Self) -> Result<Expr, ArithError>) -> Result<Expr, ArithError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(ArithError::NestedTooDeep);
+        }
+        self.depth += 1;
+        let expr = rule(self);
+        self.depth -= 1;
+        expr
+    }
+
+    /// comma := assignment (',' assignment)*
+    fn parse_comma(&mut self) -> Result<Expr, ArithError> {
+        let mut expr = self.parse_assignment()?;
+        while self.next_if_operator(&[","]).is_some() {
+            expr = Expr::Binary(",", Box::new(expr), Box::new(self.parse_assignment()?));
+        }
+        Ok(expr)
+    }
+
+    /// assignment := conditional | name assign_op assignment
+    fn parse_assignment(&mut self) -> Result<Expr, ArithError> {
+        let target = self.parse_conditional()?;
+        let assignments = ["=", "+=", "-=", "*=", "/=", "%=", "<<=", ">>=", "&=", "^=", "|="];
+        let Some(operator) = self.next_if_operator(&assignments) else {
+            return Ok(target);
+        };
+        let Expr::Variable(name) = target else {
+            return Err(ArithError::NotAVariable);
+        };
+
+        let value = self.nested(Self::parse_assignment)?;
+        let operator = match operator {
+            "=" => None,
+            compound => Some(&compound[..compound.len() - 1]),
+        };
+        Ok(Expr::Assign(name, operator, Box::new(value)))
+    }
+
+    /// conditional := binary ['?' comma ':' conditional]
+    fn parse_conditional(&mut self) -> Result<Expr, ArithError> {
+        let condition = self.parse_binary(0)?;
+        if self.next_if_operator(&["?"]).is_none() {
+            return Ok(condition);
+        }
+        let then = self.nested(Self::parse_comma)?;
+        self.expect(":")?;
+        let otherwise = self.nested(Self::parse_conditional)?;
+        Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)))
+    }
+
+    /// Left-associative binary operators, by precedence level
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, ArithError> {
+        let Some(operators) = LEVELS.get(level) else {
+            return self.parse_unary();
+        };
+        let mut expr = self.parse_binary(level + 1)?;
+        while let Some(operator) = self.next_if_operator(operators) {
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.parse_binary(level + 1)?));
+        }
+        Ok(expr)
+    }
+
+    /// unary := ('+' | '-' | '!' | '~') unary | ('++' | '--') name | postfix
+    fn parse_unary(&mut self) -> Result<Expr, ArithError> {
+        if let Some(operator) = self.next_if_operator(&["++", "--"]) {
+            return match self.nested(Self::parse_unary)? {
+                Expr::Variable(name) => Ok(Expr::Increment(name, step(operator), true)),
+                _ => Err(ArithError::NotAVariable),
+            };
+        }
+        if let Some(operator) = self.next_if_operator(&["+", "-", "!", "~"]) {
+            return Ok(Expr::Unary(operator, Box::new(self.nested(Self::parse_unary)?)));
+        }
+        self.parse_postfix()
+    }
+
+    /// postfix := primary [('++' | '--')]
+    fn parse_postfix(&mut self) -> Result<Expr, ArithError> {
+        let expr = self.parse_primary()?;
+        match self.next_if_operator(&["++", "--"]) {
+            Some(operator) => match expr {
+                Expr::Variable(name) => Ok(Expr::Increment(name, step(operator), false)),
+                _ => Err(ArithError::NotAVariable),
+            },
+            None => Ok(expr),
+        }
+    }
+
+    /// primary := number | name | '(' comma ')'
+    fn parse_primary(&mut self) -> Result<Expr, ArithError> {
+        let expr = match self.tokens.get(self.position) {
+            Some(Token::Number(number)) => Expr::Number(*number),
+            Some(Token::Name(name)) => Expr::Variable(name.clone()),
+            Some(Token::Operator("(")) => {
+                self.position += 1;
+                let expr = self.nested(Self::parse_comma)?;
+                self.expect(")")?;
+                return Ok(expr);
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.position += 1;
+        Ok(expr)
+    }
+}
+
+fn step(operator: &str) -> i64 {
+    if operator == "++" {
+        1
+    } else {
+        -1
+    }
+}
+
+struct Evaluator<'a> {
+    shell: &'a mut Shell,
+    /// How many variables are being read on the way here
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        match expr {
+            Expr::Number(number) => Ok(*number),
+            Expr::Variable(name) => self.variable(name),
+            Expr::Unary(operator, operand) => {
+                let value = self.eval(operand)?;
+                match *operator {
+                    "-" => value.checked_neg().ok_or(ArithError::Overflow),
+                    "!" => Ok((value == 0) as i64),
+                    "~" => Ok(!value),
+                    _ => Ok(value),
+                }
+            }
+            Expr::Binary("&&", left, right) => {
+                Ok((self.eval(left)? != 0 && self.eval(right)? != 0) as i64)
+            }
+            Expr::Binary("||", left, right) => {
+                Ok((self.eval(left)? != 0 || self.eval(right)? != 0) as i64)
+            }
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                apply(operator, left, right)
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.eval(condition)? != 0 {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Assign(name, operator, value) => {
+                let mut value = self.eval(value)?;
+                if let Some(operator) = operator {
+                    value = apply(operator, self.variable(name)?, value)?;
+                }
+                self.shell.set_var(name, &value.to_string());
+                Ok(value)
+            }
+            Expr::Increment(name, change, prefix) => {
+                let old = self.variable(name)?;
+                let new = old.checked_add(*change).ok_or(ArithError::Overflow)?;
+                self.shell.set_var(name, &new.to_string());
+                Ok(if *prefix { new } else { old })
+            }
+        }
+    }
+
+    /// A variable's value: 0 when unset or empty, otherwise its text
+    /// evaluated as an expression
+    fn variable(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.shell.var(name).unwrap_or("").trim().to_string();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(number) = value.parse::<i64>() {
+            return Ok(number);
+        }
+        if self.depth >= MAX_VARIABLE_DEPTH {
+            return Err(ArithError::TooDeep(name.to_string()));
+        }
+        evaluate_at(&value, self.shell, self.depth + 1)
+    }
+}
+
+/// Apply a binary operator, checking for overflow and division by zero
+fn apply(operator: &str, left: i64, right: i64) -> Result<i64, ArithError> {
+    let checked = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return Err(ArithError::DivisionByZero),
+        "/" => left.checked_div(right),
+        "%" => left.checked_rem(right),
+        "<<" => u32::try_from(right).ok().and_then(|shift| left.checked_shl(shift)),
+        ">>" => u32::try_from(right).ok().and_then(|shift| left.checked_shr(shift)),
+        "&" => Some(left & right),
+        "^" => Some(left ^ right),
+        "|" => Some(left | right),
+        "==" => Some((left == right) as i64),
+        "!=" => Some((left != right) as i64),
+        "<" => Some((left < right) as i64),
+        "<=" => Some((left <= right) as i64),
+        ">" => Some((left > right) as i64),
+        ">=" => Some((left >= right) as i64),
+        // `,`: the value of the right-hand side
+        _ => Some(right),
+    };
+    checked.ok_or(ArithError::Overflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<i64, ArithError> {
+        evaluate(expression, &mut Shell::new())
+    }
+
+    #[test]
+    fn test_operators_and_precedence() {
+        assert_eq!(eval("1 + 2 * 3 - 4 / 2"), Ok(5));
+        assert_eq!(eval("(1 + 2) * 3 % 4"), Ok(1));
+        assert_eq!(eval("-7 / 2"), Ok(-3));
+        assert_eq!(eval("1 << 4 | 3 & 6 ^ 1"), Ok(19));
+        assert_eq!(eval("~0 + !5 + !0"), Ok(0));
+        assert_eq!(eval("2 < 3 && 3 <= 3 || 1 / 0"), Ok(1));
+        assert_eq!(eval("1 == 2 ? 10 : 2 != 2 ? 20 : 30"), Ok(30));
+        assert_eq!(eval("1--1"), Ok(2));
+        assert_eq!(eval("0x1F + 017 + 0"), Ok(46));
+        assert_eq!(eval(""), Ok(0));
+    }
+
+    #[test]
+    fn test_variables_and_assignments() {
+        let mut shell = Shell::new();
+        shell.set_var("count", "4");
+        shell.set_var("expr", "count * 2");
+        shell.unset_var("zero_shell_unset");
+
+        assert_eq!(evaluate("count += 3, count", &mut shell), Ok(7));
+        assert_eq!(evaluate("expr + zero_shell_unset", &mut shell), Ok(14));
+        assert_eq!(evaluate("count++ + ++count", &mut shell), Ok(16));
+        assert_eq!(shell.var("count"), Some("9"));
+        assert_eq!(evaluate("a = b = 5, a <<= 1", &mut shell), Ok(10));
+        assert_eq!(shell.var("b"), Some("5"));
+        // Only the branch taken has side effects
+        assert_eq!(evaluate("0 && (a = 1), 1 ? a : (a = 2)", &mut shell), Ok(10));
+
+        shell.set_var("loop", "loop + 1");
+        assert_eq!(evaluate("loop", &mut shell), Err(ArithError::TooDeep("loop".to_string())));
+        assert_eq!(evaluate("1 = 2", &mut shell), Err(ArithError::NotAVariable));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("5 / (2 - 2)"), Err(ArithError::DivisionByZero));
+        assert_eq!(eval("5 % 0"), Err(ArithError::DivisionByZero));
+        assert_eq!(eval("9223372036854775807 + 1"), Err(ArithError::Overflow));
+        assert_eq!(eval("-9223372036854775807 - 2"), Err(ArithError::Overflow));
+        assert_eq!(eval("1 << 64"), Err(ArithError::Overflow));
+        assert_eq!(eval("99999999999999999999"), Err(ArithError::Overflow));
+        assert_eq!(eval("09"), Err(ArithError::InvalidNumber("09".to_string())));
+        assert_eq!(eval("1 +"), Err(ArithError::Unexpected(None)));
+        assert_eq!(eval("(1"), Err(ArithError::Unexpected(None)));
+        assert_eq!(eval("1 2"), Err(ArithError::Unexpected(Some("2".to_string()))));
+        assert_eq!(eval("1 $ 2"), Err(ArithError::Unexpected(Some("$".to_string()))));
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(eval(&nested(50)), Ok(1));
+        assert_eq!(eval(&nested(10000)), Err(ArithError::NestedTooDeep));
+        assert_eq!(eval(&format!("{}1", "- ".repeat(60))), Ok(1));
+        assert_eq!(eval(&format!("{}1", "- ".repeat(10000))), Err(ArithError::NestedTooDeep));
+        assert_eq!(eval(&format!("{}1", "a = ".repeat(10000))), Err(ArithError::NestedTooDeep));
+        assert_eq!(eval(&format!("{}1", "1 ? 1 : ".repeat(10000))), Err(ArithError::NestedTooDeep));
+        assert_eq!(ArithError::NestedTooDeep.to_string(), "expression nesting too deep");
+    }
+}
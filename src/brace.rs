@@ -0,0 +1,293 @@
+//! Brace expansion: `{a,b,c}` and sequences such as `{1..10}`
+//!
+//! It works on words as written, before any other expansion, and gives
+//! back words as written, so quotes and substitutions in them are left for
+//! `expand`. Quoted or escaped braces and commas, and those inside `${...}`,
+//! `$(...)` or backquotes, are plain characters.
+
+/// The most words a single sequence may produce; a longer one, likely a
+/// typo, is left as it is rather than filling the memory
+const MAX_SEQUENCE_LENGTH: usize = 1 << 16;
+
+/// How deeply braces may nest, each level taking stack to expand
+const MAX_DEPTH: usize = 64;
+
+/// The most words one word may expand to, as `{a,b}{a,b}...` doubles them
+/// with every pair of braces
+const MAX_WORDS: usize = 1 << 16;
+
+/// The braces nest too deeply or give too many words to expand
+struct TooLarge;
+
+/// Expand the braces in a word, left to right
+///
+/// `pre{a,b}post` becomes `preapost` and `prebpost`; alternatives may hold
+/// braces of their own, and several braces in one word combine, so
+/// `{a,b}{1,2}` gives four words. `{x..y}` and `{x..y..step}` count
+/// between two integers or two letters, up or down; when either integer is
+/// written with a leading zero, all of them are padded to the same width,
+/// as in `{01..10}`. Braces without a top-level comma or a valid sequence,
+/// like `{}` or `{a}`, stay as they are, and so does the whole word when
+/// its braces nest more than `MAX_DEPTH` deep or make over `MAX_WORDS`
+/// words.
+pub(crate) fn expand(word: &str) -> Vec<String> {
+    expand_at(word, 0).unwrap_or_else(|TooLarge| vec![word.to_string()])
+}
+
+/// `expand` for a part of a word that follows or sits inside `depth` pairs
+/// of braces
+fn expand_at(word: &str, depth: usize) -> Result<Vec<String>, TooLarge> {
+    if depth > MAX_DEPTH {
+        return Err(TooLarge);
+    }
+    let chars: Vec<char> = word.chars().collect();
+
+    let mut i = 0;
+    while i < chars.len() {
+        if let Some(next) = skip_quoted(&chars, i) {
+            i = next;
+            continue;
+        }
+        if chars[i] == '{' {
+            if let Some(close) = matching_brace(&chars, i) {
+                if let Some(alternatives) = alternatives(&chars[i + 1..close], depth)? {
+                    let preamble: String = chars[..i].iter().collect();
+                    let rest: String = chars[close + 1..].iter().collect();
+                    let rest = expand_at(&rest, depth + 1)?;
+                    if alternatives.len() * rest.len() > MAX_WORDS {
+                        return Err(TooLarge);
+                    }
+                    let mut words = Vec::new();
+                    for alternative in &alternatives {
+                        for rest in &rest {
+                            words.push(format!("{}{}{}", preamble, alternative, rest));
+                        }
+                    }
+                    return Ok(words);
+                }
+            }
+        }
+        i += 1;
+    }
+    Ok(vec![word.to_string()])
+}
+
+/// The words the inside of a pair of braces stands for, or `None` when it
+/// is neither a comma-separated list nor a sequence
+fn alternatives(body: &[char], depth: usize) -> Result<Option<Vec<String>>, TooLarge> {
+    let mut commas = Vec::new();
+    let mut level = 0;
+    let mut i = 0;
+    while i < body.len() {
+        if let Some(next) = skip_quoted(body, i) {
+            i = next;
+            continue;
+        }
+        match body[i] {
+            '{' => level += 1,
+            '}' => level -= 1,
+            ',' if level == 0 => commas.push(i),
+            _ => {}
+        }
+        i += 1;
+    }
+
+    if commas.is_empty() {
+        let body: String = body.iter().collect();
+        return Ok(sequence(&body));
+    }
+
+    let mut words = Vec::new();
+    let mut start = 0;
+    for end in commas.into_iter().chain([body.len()]) {
+        let alternative: String = body[start..end].iter().collect();
+        words.extend(expand_at(&alternative, depth + 1)?);
+        if words.len() > MAX_WORDS {
+            return Err(TooLarge);
+        }
+        start = end + 1;
+    }
+    Ok(Some(words))
+}
+
+/// The index of the `}` closing the `{` at `open`, skipping nested pairs
+fn matching_brace(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        if let Some(next) = skip_quoted(chars, i) {
+            i = next;
+            continue;
+        }
+        match chars[i] {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The index just past the quoted string, escaped character, `${...}`,
+/// `$(...)` or backquoted command starting at `i`, if one starts there
+fn skip_quoted(chars: &[char], i: usize) -> Option<usize> {
+    let closing = |from: usize, close: char| {
+        let mut j = from;
+        while j < chars.len() {
+            match chars[j] {
+                '\\' if close != '\'' => j += 2,
+                c if c == close => return j + 1,
+                _ => j += 1,
+            }
+        }
+        chars.len()
+    };
+
+    match chars[i] {
+        '\\' => Some((i + 2).min(chars.len())),
+        '\'' | '"' | '`' => Some(closing(i + 1, chars[i])),
+        '$' => {
+            let (open, close) = match chars.get(i + 1) {
+                Some('{') => ('{', '}'),
+                Some('(') => ('(', ')'),
+                _ => return None,
+            };
+            let mut depth = 0;
+            let mut j = i + 1;
+            while j < chars.len() {
+                if let Some(next) = skip_quoted(chars, j) {
+                    j = next;
+                    continue;
+                }
+                if chars[j] == open {
+                    depth += 1;
+                } else if chars[j] == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(j + 1);
+                    }
+                }
+                j += 1;
+            }
+            Some(chars.len())
+        }
+        _ => None,
+    }
+}
+
+/// The words of a sequence `x..y` or `x..y..step`
+fn sequence(body: &str) -> Option<Vec<String>> {
+    let parts: Vec<&str> = body.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, None),
+        [start, end, step] => (start, end, Some(step.parse::<i64>().ok()?)),
+        _ => return None,
+    };
+    // The direction comes from the ends, so only the size of the step counts
+    let step = step.map_or(1, i64::unsigned_abs).max(1);
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |number: &str| {
+            let digits = number.trim_start_matches(['-', '+']);
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        let format = |n: i64| {
+            if n < 0 {
+                // The minus sign counts towards the width
+                format!("-{:0width$}", n.unsigned_abs(), width = width.saturating_sub(1))
+            } else {
+                format!("{:0width$}", n)
+            }
+        };
+        return Some(steps(first, last, step)?.map(format).collect());
+    }
+
+    let letter = |text: &str| {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if c.is_ascii_alphabetic() => Some(c as i64),
+            _ => None,
+        }
+    };
+    let (first, last) = (letter(start)?, letter(end)?);
+    let letters = steps(first, last, step)?;
+    Some(letters.map(|c| (c as u8 as char).to_string()).collect())
+}
+
+/// Count from `first` to `last`, in whichever direction that is
+fn steps(first: i64, last: i64, step: u64) -> Option<impl Iterator<Item = i64>> {
+    let count = (first.abs_diff(last) / step).checked_add(1)?;
+    if count > MAX_SEQUENCE_LENGTH as u64 {
+        return None;
+    }
+    let step = step as i128 * if first <= last { 1 } else { -1 };
+    Some((0..count as i128).map(move |i| (first as i128 + i * step) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(word: &str) -> Vec<String> {
+        expand(word)
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(words("logs/{a,b,c}"), vec!["logs/a", "logs/b", "logs/c"]);
+        assert_eq!(words("x{a,b{1,2},}y"), vec!["xay", "xb1y", "xb2y", "xy"]);
+        assert_eq!(words("{a,b}{1,2}"), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(words("{a}{b,c}"), vec!["{a}b", "{a}c"]);
+        assert_eq!(words("cp file{,.bak}"), vec!["cp file", "cp file.bak"]);
+    }
+
+    #[test]
+    fn test_sequences() {
+        assert_eq!(words("{1..5}"), vec!["1", "2", "3", "4", "5"]);
+        assert_eq!(words("{3..-1}"), vec!["3", "2", "1", "0", "-1"]);
+        assert_eq!(words("{01..03}"), vec!["01", "02", "03"]);
+        assert_eq!(words("{-01..1}"), vec!["-01", "000", "001"]);
+        assert_eq!(words("{0..10..4}"), vec!["0", "4", "8"]);
+        assert_eq!(words("{e..a..-2}"), vec!["e", "c", "a"]);
+        assert_eq!(words("v{1..2}.{x,y}"), vec!["v1.x", "v1.y", "v2.x", "v2.y"]);
+    }
+
+    #[test]
+    fn test_words_left_alone() {
+        let whole_range = "{-9223372036854775808..9223372036854775807}";
+        for word in ["{}", "{a}", "a,b", "{1..}", "{a..9}", "{1..2..x}", "{a,b", "a}b,c{"] {
+            assert_eq!(words(word), vec![word], "{:?}", word);
+        }
+        for word in ["{1..99999999}", whole_range] {
+            assert_eq!(words(word), vec![word], "{:?}", word);
+        }
+        for word in ["'{a,b}'", "\\{a,b}", "{a\\,b}", "\"{1..3}\"", "${x,y}", "$(echo {a,b})", "`echo {a,b}`"] {
+            assert_eq!(words(word), vec![word], "{:?}", word);
+        }
+        assert_eq!(words("{'a b',\"$x\"}"), vec!["'a b'", "\"$x\""]);
+    }
+
+    #[test]
+    fn test_limits() {
+        let nested = |depth: usize| format!("{}z{}", "{a,".repeat(depth), "}".repeat(depth));
+        assert_eq!(words(&nested(MAX_DEPTH / 2)).len(), MAX_DEPTH / 2 + 1);
+        let word = nested(5000);
+        assert_eq!(words(&word), vec![word]);
+
+        assert_eq!(words(&"{a,b}".repeat(16)).len(), MAX_WORDS);
+        let word = "{a,b}".repeat(5000);
+        assert_eq!(words(&word), vec![word]);
+        assert_eq!(words("{1..300}{1..300}"), vec!["{1..300}{1..300}"]);
+    }
+}
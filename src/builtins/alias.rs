@@ -0,0 +1,106 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::parser::quote;
+
+/// Define or show aliases
+/// Usage: alias [-p] [name[=value] ...]
+pub struct Alias;
+
+impl Builtin for Alias {
+    fn name(&self) -> &'static str {
+        "alias"
+    }
+
+    fn usage(&self) -> &'static str {
+        "alias [-p] [name[=value] ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Define aliases, or show them (all of them without arguments)"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-p"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let args = match args.first() {
+            Some(&"-p") => &args[1..],
+            _ => args,
+        };
+
+        if args.is_empty() {
+            for (name, value) in ctx.shell.aliases() {
+                let _ = writeln!(ctx.io.stdout, "alias {}={}", name, quote(value));
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in args {
+            match arg.split_once('=') {
+                Some((name, value)) if is_alias_name(name) => {
+                    ctx.shell.aliases_mut().insert(name.to_string(), value.to_string());
+                }
+                Some((name, _)) => {
+                    let _ = writeln!(ctx.io.stderr, "alias: `{}': invalid alias name", name);
+                    status = 1;
+                }
+                None => match ctx.shell.aliases().get(*arg) {
+                    Some(value) => {
+                        let _ = writeln!(ctx.io.stdout, "alias {}={}", arg, quote(value));
+                    }
+                    None => {
+                        let _ = writeln!(ctx.io.stderr, "alias: {}: not found", arg);
+                        status = 1;
+                    }
+                },
+            }
+        }
+
+        status
+    }
+}
+
+/// Whether `name` can be an alias: a word the tokenizer reads back as
+/// written, so without quotes, `$`, `/` or operator characters
+fn is_alias_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_!%,@.-+:".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_alias_definitions_and_expansion() {
+        let mut shell = Shell::new();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_stderr(Output::Buffer(err.clone()));
+
+        assert_eq!(shell.run_line("alias say='echo said:' twice='say say'"), 0);
+        assert_eq!(shell.run_line("say hi; twice"), 0);
+        // The alias is only defined once the line is parsed
+        assert_eq!(shell.run_line("alias late='echo late'; late"), 127);
+        assert_eq!(shell.run_line("'say' quoted"), 127);
+        assert_eq!(shell.run_line("alias say"), 0);
+        assert_eq!(
+            out.to_string_lossy(),
+            "said: hi\nsaid: say\nalias say='echo said:'\n"
+        );
+
+        out.take();
+        assert_eq!(shell.run_line("unalias say late && alias"), 0);
+        assert_eq!(out.to_string_lossy(), "alias twice='say say'\n");
+        assert_eq!(shell.run_line("unalias say"), 1);
+        assert_eq!(shell.run_line("alias a/b=x"), 1);
+        assert_eq!(shell.run_line("unalias -a"), 0);
+        assert!(shell.aliases().is_empty());
+    }
+}
@@ -0,0 +1,60 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::jobs::JobState;
+
+/// Resume stopped jobs in the background
+/// Usage: bg [%job ...]
+pub struct Bg;
+
+impl Builtin for Bg {
+    fn name(&self) -> &'static str {
+        "bg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "bg [%job ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Resume stopped jobs in the background"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if !ctx.shell.jobs().has_control() {
+            let _ = writeln!(ctx.io.stderr, "bg: no job control");
+            return 1;
+        }
+        let specs: Vec<Option<&str>> = match args {
+            [] => vec![None],
+            _ => args.iter().map(|spec| Some(*spec)).collect(),
+        };
+
+        let jobs = ctx.shell.jobs_mut();
+        jobs.reap();
+
+        let mut status = 0;
+        for spec in specs {
+            let id = match jobs.find(spec) {
+                Ok(id) => id,
+                Err(message) => {
+                    let _ = writeln!(ctx.io.stderr, "bg: {}", message);
+                    status = 1;
+                    continue;
+                }
+            };
+
+            let state = jobs.get(id).map(|job| job.state());
+            if let Some(JobState::Done(_)) = state {
+                let _ = writeln!(ctx.io.stderr, "bg: job has terminated");
+                status = 1;
+            } else if jobs.background(id) {
+                let command = jobs.get(id).map(|job| job.command()).unwrap_or_default();
+                let _ = writeln!(ctx.io.stdout, "[{}]{} {} &", id, jobs.marker(id), command);
+            } else {
+                let _ = writeln!(ctx.io.stderr, "bg: job {} already in background", id);
+            }
+        }
+        status
+    }
+}
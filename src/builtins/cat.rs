@@ -1,55 +1,89 @@
 use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
+use std::io::{self, Read, Write};
 use std::path::Path;
 
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::{signals, streams};
+
 /// Concatenate and display file contents
 /// Usage: cat [file1] [file2] ...
-/// If no files specified, reads from stdin (not implemented yet)
-pub fn execute(args: &[&str]) {
-    if args.is_empty() {
-        eprintln!("cat: no files specified");
-        return;
+/// With no files, or for `-`, reads from stdin
+pub struct Cat;
+
+impl Builtin for Cat {
+    fn name(&self) -> &'static str {
+        "cat"
     }
-    
-    for filename in args {
-        if let Err(e) = cat_file(filename) {
-            eprintln!("cat: {}: {}", filename, e);
+
+    fn usage(&self) -> &'static str {
+        "cat [file ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Concatenate and display file contents"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let args = if args.is_empty() { &["-"] } else { args };
+
+        let mut status = 0;
+        for filename in args {
+            let result = match *filename {
+                "-" => copy(&mut ctx.io.stdin, &mut ctx.io.stdout).map_err(Into::into),
+                _ => cat_file(&ctx.shell.resolve(filename), &mut ctx.io.stdout),
+            };
+            match result {
+                Ok(()) => {}
+                Err(e) if streams::is_broken_pipe(&*e) => return 1,
+                Err(e) => {
+                    let _ = writeln!(ctx.io.stderr, "cat: {}: {}", filename, e);
+                    status = 1;
+                }
+            }
+            if signals::interrupted() {
+                return signals::INTERRUPT_STATUS;
+            }
         }
+
+        status
     }
 }
 
-fn cat_file(filename: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(filename);
-    
+fn cat_file(path: &Path, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+
     // Check if file exists and is readable
     if !path.exists() {
-        return Err(format!("No such file or directory").into());
+        return Err("No such file or directory".into());
     }
     
     if !path.is_file() {
-        return Err(format!("Is a directory").into());
+        return Err("Is a directory".into());
     }
     
-    // Open and read the file
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    // Open the file and stream its contents to the output
+    let mut file = File::open(path)?;
+    copy(&mut file, out)?;
     
-    // Stream the file contents to stdout
-    let stdout = io::stdout();
-    let mut stdout_lock = stdout.lock();
-    
-    for line_result in reader.lines() {
-        match line_result {
-            Ok(line) => {
-                writeln!(stdout_lock, "{}", line)?;
-            }
-            Err(e) => {
-                return Err(format!("Error reading file: {}", e).into());
-            }
+    Ok(())
+}
+
+/// Copy everything from `input` to `out` as it arrives, so that lines
+/// typed at the terminal show up one by one
+fn copy(input: &mut dyn Read, out: &mut dyn Write) -> io::Result<()> {
+    let mut buffer = [0; 8192];
+    loop {
+        // Stop early on Ctrl-C, which also interrupts a read from the
+        // terminal; the caller reports it
+        if signals::interrupted() {
+            return Ok(());
+        }
+        match input.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(n) => out.write_all(&buffer[..n])?,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
         }
     }
-    
-    Ok(())
 }
 
 #[cfg(test)]
@@ -60,14 +94,14 @@ mod tests {
     #[test]
     fn test_cat_nonexistent_file() {
         // This test verifies error handling for non-existent files
-        let result = cat_file("nonexistent_file.txt");
+        let result = cat_file(Path::new("nonexistent_file.txt"), &mut Vec::new());
         assert!(result.is_err());
     }
     
     #[test] 
     fn test_cat_directory() {
         // Test that cat properly handles directories
-        let result = cat_file("src");
+        let result = cat_file(Path::new("src"), &mut Vec::new());
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("Is a directory"));
@@ -80,11 +114,39 @@ mod tests {
         let test_content = "Hello, World!\nThis is a test file.\n";
         fs::write("test_cat.txt", test_content).unwrap();
         
-        // Test that cat can read the file (we can't easily test stdout in unit tests)
-        let result = cat_file("test_cat.txt");
+        // Test that cat streams the file to its output
+        let mut output = Vec::new();
+        let result = cat_file(Path::new("test_cat.txt"), &mut output);
         assert!(result.is_ok());
+        assert_eq!(String::from_utf8(output).unwrap(), test_content);
         
         // Clean up
         fs::remove_file("test_cat.txt").unwrap();
     }
+
+    #[test]
+    fn test_cat_reads_stdin() {
+        let mut shell = crate::shell::Shell::new();
+        let out = crate::streams::Buffer::new();
+        shell.set_stdout(crate::streams::Output::Buffer(out.clone()));
+
+        fs::write("test_cat_stdin.txt", "file\n").unwrap();
+        assert_eq!(shell.run_line("echo piped | cat test_cat_stdin.txt - test_cat_stdin.txt"), 0);
+        assert_eq!(shell.run_line("cat < test_cat_stdin.txt"), 0);
+        assert_eq!(out.to_string_lossy(), "file\npiped\nfile\nfile\n");
+        fs::remove_file("test_cat_stdin.txt").unwrap();
+    }
+
+    #[test]
+    fn test_cat_stops_on_interrupt() {
+        let mut shell = crate::shell::Shell::new();
+        let mut io = crate::streams::Io::inherit();
+        let out = crate::streams::Buffer::new();
+        io.stdout = crate::streams::Output::Buffer(out.clone());
+        let mut ctx = Context::new(io, &mut shell);
+
+        let status = signals::with_interrupt(|| Cat.run(&mut ctx, &["Cargo.toml", "Cargo.toml"]));
+        assert_eq!(status, signals::INTERRUPT_STATUS);
+        assert_eq!(out.to_string_lossy(), "");
+    }
 }
@@ -1,46 +1,54 @@
-use std::env;
-use std::path::Path;
-
-/// Change directory - handles cd, cd ~, cd <path>
-pub fn execute(args: &[&str]) {
-    let target_dir = if args.is_empty() {
-        // cd with no args goes to home directory
-        match env::var("HOME") {
-            Ok(home) => home,
-            Err(_) => {
-                eprintln!("cd: HOME environment variable not set");
-                return;
-            }
-        }
-    } else if args[0] == "~" {
-        // cd ~ goes to home directory
-        match env::var("HOME") {
-            Ok(home) => home,
-            Err(_) => {
-                eprintln!("cd: HOME environment variable not set");
-                return;
-            }
-        }
-    } else if args[0].starts_with("~/") {
-        // cd ~/path expands ~ to home directory
-        match env::var("HOME") {
-            Ok(home) => {
-                let path = &args[0][2..]; // Remove "~/"
-                format!("{}/{}", home, path)
+use std::fs;
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::streams;
+
+/// Change directory - handles cd and cd <path>
+pub struct Cd;
+
+impl Builtin for Cd {
+    fn name(&self) -> &'static str {
+        "cd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "cd [dir]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Change the current directory (defaults to $HOME)"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        // `~` and `~/path` were already expanded along with the other words
+        let target_dir = match args.first() {
+            Some(dir) => dir.to_string(),
+            None => match ctx.shell.var("HOME") {
+                Some(home) => home.to_string(),
+                None => {
+                    let _ = writeln!(ctx.io.stderr, "cd: HOME environment variable not set");
+                    return 1;
+                }
+            },
+        };
+
+        // Only the shell's own directory changes, never the process's
+        let path = ctx.shell.resolve(&target_dir);
+        
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.is_dir() => {}
+            Ok(_) => {
+                let _ = writeln!(ctx.io.stderr, "cd: {}: Not a directory", target_dir);
+                return 1;
             }
-            Err(_) => {
-                eprintln!("cd: HOME environment variable not set");
-                return;
+            Err(error) => {
+                let _ = writeln!(ctx.io.stderr, "cd: {}: {}", target_dir, streams::describe(&error));
+                return 1;
             }
         }
-    } else {
-        // cd <path> - use the provided path
-        args[0].to_string()
-    };
-
-    let path = Path::new(&target_dir);
-    
-    if let Err(error) = env::set_current_dir(path) {
-        eprintln!("cd: {}: {}", target_dir, error);
+
+        ctx.shell.set_cwd(path);
+        0
     }
 }
@@ -1,26 +1,49 @@
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 
+use crate::builtins::{Builtin, Context, ExitStatus};
+
 /// Copy files from source to destination
 /// Usage: cp <source> <destination>
 /// Note: Directory copying is not supported in this minimal implementation
-pub fn execute(args: &[&str]) {
-    if args.len() != 2 {
-        eprintln!("cp: usage: cp <source> <destination>");
-        return;
+pub struct Cp;
+
+impl Builtin for Cp {
+    fn name(&self) -> &'static str {
+        "cp"
     }
-    
-    let source = args[0];
-    let destination = args[1];
-    
-    if let Err(e) = copy_file(source, destination) {
-        eprintln!("cp: {}", e);
+
+    fn usage(&self) -> &'static str {
+        "cp <source> <destination>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Copy a file, preserving its permissions"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.len() != 2 {
+            let _ = writeln!(ctx.io.stderr, "cp: usage: cp <source> <destination>");
+            return 1;
+        }
+        
+        let source = args[0];
+        let destination = args[1];
+        
+        if let Err(e) = copy_file(source, destination, ctx.shell.cwd()) {
+            let _ = writeln!(ctx.io.stderr, "cp: {}", e);
+            return 1;
+        }
+
+        0
     }
 }
 
-fn copy_file(source: &str, destination: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let source_path = Path::new(source);
-    let dest_path = Path::new(destination);
+/// Relative paths are taken relative to `cwd`
+fn copy_file(source: &str, destination: &str, cwd: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let source_path = cwd.join(source);
+    let dest_path = cwd.join(destination);
     
     // Validate source file
     if !source_path.exists() {
@@ -46,10 +69,10 @@ fn copy_file(source: &str, destination: &str) -> Result<(), Box<dyn std::error::
     };
     
     // Perform the copy
-    fs::copy(source_path, &final_dest_path)?;
+    fs::copy(&source_path, &final_dest_path)?;
     
     // Preserve file permissions
-    let source_metadata = fs::metadata(source_path)?;
+    let source_metadata = fs::metadata(&source_path)?;
     let permissions = source_metadata.permissions();
     fs::set_permissions(&final_dest_path, permissions)?;
     
@@ -63,7 +86,7 @@ mod tests {
     
     #[test]
     fn test_copy_nonexistent_file() {
-        let result = copy_file("nonexistent.txt", "dest.txt");
+        let result = copy_file("nonexistent.txt", "dest.txt", Path::new("."));
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("No such file or directory"));
@@ -72,7 +95,7 @@ mod tests {
     
     #[test]
     fn test_copy_directory() {
-        let result = copy_file("src", "dest");
+        let result = copy_file("src", "dest", Path::new("."));
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("Is a directory"));
@@ -86,7 +109,7 @@ mod tests {
         fs::write("test_source.txt", test_content).unwrap();
         
         // Copy it
-        let result = copy_file("test_source.txt", "test_dest.txt");
+        let result = copy_file("test_source.txt", "test_dest.txt", Path::new("."));
         assert!(result.is_ok());
         
         // Verify the copy
@@ -108,7 +131,7 @@ mod tests {
         fs::create_dir("test_dir").unwrap();
         
         // Copy file to directory
-        let result = copy_file("test_source_dir.txt", "test_dir");
+        let result = copy_file("test_source_dir.txt", "test_dir", Path::new("."));
         assert!(result.is_ok());
         
         // Verify the copy exists in the directory
@@ -1,12 +1,30 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+
 /// Echo command - prints arguments separated by spaces
 /// Basic implementation without advanced quoting support
-pub fn execute(args: &[&str]) {
-    if args.is_empty() {
-        println!();
-        return;
-    }
-    
-    // Join arguments with single spaces and print
-    let output = args.join(" ");
-    println!("{}", output);
+pub struct Echo;
+
+impl Builtin for Echo {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn usage(&self) -> &'static str {
+        "echo [arg ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Print the arguments separated by spaces"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        // Join arguments with single spaces and print
+        let output = args.join(" ");
+        match writeln!(ctx.io.stdout, "{}", output) {
+            Ok(()) => 0,
+            Err(_) => 1,
+        }
+    }
 }
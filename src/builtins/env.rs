@@ -0,0 +1,93 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::external::{self, ExecError};
+use crate::shell::Variable;
+
+/// Print the environment, or run a program in a modified one
+/// Usage: env [-i] [name=value ...] [command [arg ...]]
+pub struct Env;
+
+impl Builtin for Env {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    fn usage(&self) -> &'static str {
+        "env [-i] [name=value ...] [command [arg ...]]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Print the environment or run a command with extra variables"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-i"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        // The changes only apply to this command, so work on a copy
+        let mut shell = ctx.shell.clone();
+        let mut args = args;
+
+        if args.first() == Some(&"-i") {
+            // Keep the variables (and with them $PATH for the lookup),
+            // but pass none of them on
+            let names: Vec<String> = shell.env().map(|(name, _)| name.to_string()).collect();
+            for name in names {
+                let var = Variable {
+                    exported: false,
+                    ..shell.variables()[&name].clone()
+                };
+                shell.set_variable(&name, var);
+            }
+            args = &args[1..];
+        }
+
+        while let Some((name, value)) = args.first().and_then(|arg| arg.split_once('=')) {
+            shell.set_exported(name, value);
+            args = &args[1..];
+        }
+
+        let Some((command, command_args)) = args.split_first() else {
+            for (name, value) in shell.env() {
+                let _ = writeln!(ctx.io.stdout, "{}={}", name, value);
+            }
+            return 0;
+        };
+
+        match external::spawn(&shell, command, command_args, ctx.io.clone(), None) {
+            Ok(process) => process.wait(),
+            Err(error) => {
+                let _ = writeln!(ctx.io.stderr, "env: {}: {}", command, error);
+                match error {
+                    ExecError::NotFound => 127,
+                    _ => 126,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_env_runs_command_with_extra_variables() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        shell.run_line("env -i ZERO_SHELL_ENV=set sh -c 'echo $ZERO_SHELL_ENV; env | wc -l'");
+        let output = out.to_string_lossy();
+        let lines: Vec<&str> = output.lines().map(str::trim).collect();
+        // sh itself may add PWD, SHLVL and `_`, but nothing is inherited
+        assert_eq!(lines[0], "set");
+        assert!(lines[1].parse::<usize>().unwrap() <= 4);
+        assert_eq!(shell.var("ZERO_SHELL_ENV"), None);
+
+        assert_eq!(shell.run_line("env no_such_command_0shell 2>/dev/null"), 127);
+    }
+}
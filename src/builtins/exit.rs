@@ -0,0 +1,80 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::streams::Io;
+
+/// Exit the shell
+/// Usage: exit [n]
+/// Only asks the shell to stop; inside a pipeline that shell is a copy,
+/// so just the current stage ends
+pub struct Exit;
+
+impl Builtin for Exit {
+    fn name(&self) -> &'static str {
+        "exit"
+    }
+
+    fn usage(&self) -> &'static str {
+        "exit [n]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Exit the shell with status n, or the status of the last command"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        match exit_status(args, &mut ctx.io, ctx.shell.last_status()) {
+            Ok(status) => {
+                ctx.shell.request_exit(status);
+                status
+            }
+            Err(status) => status,
+        }
+    }
+}
+
+/// Work out the status the shell should exit with
+/// Without an argument the status of the last command is used. Returns
+/// `Err` with a failure status when the shell should keep running instead.
+fn exit_status(args: &[&str], io: &mut Io, last_status: ExitStatus) -> Result<ExitStatus, ExitStatus> {
+    match args {
+        [] => Ok(last_status),
+        [code] => match code.parse::<i64>() {
+            // Like other shells, only the low 8 bits reach the parent
+            Ok(code) => Ok((code & 0xff) as ExitStatus),
+            Err(_) => {
+                let _ = writeln!(io.stderr, "exit: {}: numeric argument required", code);
+                Ok(2)
+            }
+        },
+        _ => {
+            let _ = writeln!(io.stderr, "exit: too many arguments");
+            Err(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streams::Output;
+    use std::fs::File;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_exit_status() {
+        let mut io = Io::inherit();
+        assert_eq!(exit_status(&[], &mut io, 3), Ok(3));
+        assert_eq!(exit_status(&["0"], &mut io, 3), Ok(0));
+        assert_eq!(exit_status(&["258"], &mut io, 0), Ok(2));
+        assert_eq!(exit_status(&["-1"], &mut io, 0), Ok(255));
+    }
+
+    #[test]
+    fn test_exit_bad_arguments() {
+        let mut io = Io::inherit();
+        io.stderr = Output::File(Arc::new(File::create("/dev/null").unwrap()));
+        assert_eq!(exit_status(&["abc"], &mut io, 0), Ok(2));
+        assert_eq!(exit_status(&["1", "2"], &mut io, 0), Err(1));
+    }
+}
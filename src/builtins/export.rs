@@ -0,0 +1,94 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::parser::{is_name, quote};
+use crate::shell::Variable;
+
+/// Mark variables for export to the environment of external commands
+/// Usage: export [name[=value] ...]
+/// Without arguments (or with -p) the exported variables are listed
+pub struct Export;
+
+impl Builtin for Export {
+    fn name(&self) -> &'static str {
+        "export"
+    }
+
+    fn usage(&self) -> &'static str {
+        "export [name[=value] ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Set variables and pass them on to external commands"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-p"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.is_empty() || args == ["-p"] {
+            for (name, value) in ctx.shell.env() {
+                let _ = writeln!(ctx.io.stdout, "export {}={}", name, quote(value));
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (*arg, None),
+            };
+
+            if !is_name(name) {
+                let _ = writeln!(ctx.io.stderr, "export: `{}': not a valid identifier", arg);
+                status = 1;
+                continue;
+            }
+
+            match value {
+                Some(value) => ctx.shell.set_exported(name, value),
+                // `export NAME` exports the current value, if there is one
+                None => {
+                    if let Some(var) = ctx.shell.variables().get(name).cloned() {
+                        ctx.shell.set_variable(name, Variable { exported: true, ..var });
+                    }
+                }
+            }
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_export_reaches_children() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        shell.run_line("ZERO_SHELL_A=one; export ZERO_SHELL_A ZERO_SHELL_B='two words'");
+        shell.run_line("sh -c 'echo $ZERO_SHELL_A $ZERO_SHELL_B'");
+        assert_eq!(out.take(), b"one two words\n");
+
+        shell.run_line("export | grep ZERO_SHELL_B");
+        assert_eq!(out.to_string_lossy(), "export ZERO_SHELL_B='two words'\n");
+    }
+
+    #[test]
+    fn test_export_rejects_invalid_names() {
+        let mut shell = Shell::new();
+        let err = Buffer::new();
+        shell.set_stderr(Output::Buffer(err.clone()));
+
+        assert_eq!(shell.run_line("export 1X=2 ZERO_SHELL_OK=1"), 1);
+        assert_eq!(err.to_string_lossy(), "export: `1X=2': not a valid identifier\n");
+        assert_eq!(shell.var("ZERO_SHELL_OK"), Some("1"));
+    }
+}
@@ -0,0 +1,59 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::jobs::JobState;
+
+/// Bring a job to the foreground, resuming it if it was stopped
+/// Usage: fg [%job]
+pub struct Fg;
+
+impl Builtin for Fg {
+    fn name(&self) -> &'static str {
+        "fg"
+    }
+
+    fn usage(&self) -> &'static str {
+        "fg [%job]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Move a job to the foreground"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if !ctx.shell.jobs().has_control() {
+            let _ = writeln!(ctx.io.stderr, "fg: no job control");
+            return 1;
+        }
+        let spec = match args {
+            [] => None,
+            [spec] => Some(*spec),
+            _ => {
+                let _ = writeln!(ctx.io.stderr, "fg: usage: {}", self.usage());
+                return 2;
+            }
+        };
+
+        let jobs = ctx.shell.jobs_mut();
+        jobs.reap();
+        let job = match jobs.find(spec) {
+            Ok(id) => jobs.remove(id),
+            Err(message) => {
+                let _ = writeln!(ctx.io.stderr, "fg: {}", message);
+                return 1;
+            }
+        };
+        let Some(job) = job else {
+            return 1;
+        };
+
+        if let JobState::Done(_) = job.state() {
+            let _ = writeln!(ctx.io.stderr, "fg: job has terminated");
+            return 1;
+        }
+
+        let _ = writeln!(ctx.io.stdout, "{}", job.command());
+        let _ = ctx.io.stdout.flush();
+        ctx.shell.jobs_mut().foreground(job, &mut ctx.io.stderr)
+    }
+}
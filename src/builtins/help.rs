@@ -0,0 +1,50 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+
+/// Show the usage and description of builtin commands
+/// Usage: help [name ...]
+/// Without arguments every registered builtin is listed
+pub struct Help;
+
+impl Builtin for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "help [name ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Show help for builtin commands"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.is_empty() {
+            let usages: Vec<&str> = ctx.shell.registry().iter().map(|b| b.usage()).collect();
+            let width = usages.iter().map(|u| u.len()).max().unwrap_or(0);
+
+            for (builtin, usage) in ctx.shell.registry().iter().zip(&usages) {
+                let _ = writeln!(ctx.io.stdout, "{:width$}  {}", usage, builtin.help());
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for name in args {
+            match ctx.shell.registry().get(name) {
+                Some(builtin) => {
+                    let _ = writeln!(ctx.io.stdout, "{}: {}", builtin.name(), builtin.usage());
+                    let _ = writeln!(ctx.io.stdout, "    {}", builtin.help());
+                }
+                None => {
+                    let _ = writeln!(ctx.io.stderr, "help: no help topics match `{}'", name);
+                    status = 1;
+                }
+            }
+        }
+
+        status
+    }
+}
@@ -0,0 +1,110 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::streams;
+
+/// Show or edit the command history
+/// Usage: history [n] | history -c | history -d <offset>
+pub struct History;
+
+impl Builtin for History {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    fn usage(&self) -> &'static str {
+        "history [n] | history -c | history -d <offset>"
+    }
+
+    fn help(&self) -> &'static str {
+        "List previous commands, clear the list or delete one entry"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-c", "-d"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let result = match args {
+            [] => return list(ctx, None),
+            ["-c"] => ctx.shell.history_mut().clear(),
+            ["-d"] => {
+                let _ = writeln!(ctx.io.stderr, "history: -d: option requires an argument");
+                return 2;
+            }
+            ["-d", offset] => {
+                let deleted = offset
+                    .parse()
+                    .ok()
+                    .map(|number| ctx.shell.history_mut().delete(number));
+                match deleted {
+                    Some(Ok(true)) => Ok(()),
+                    Some(Err(e)) => Err(e),
+                    _ => {
+                        let _ = writeln!(ctx.io.stderr, "history: {}: history position out of range", offset);
+                        return 1;
+                    }
+                }
+            }
+            [count] if !count.starts_with('-') => match count.parse() {
+                Ok(count) => return list(ctx, Some(count)),
+                Err(_) => {
+                    let _ = writeln!(ctx.io.stderr, "history: {}: numeric argument required", count);
+                    return 1;
+                }
+            },
+            _ => {
+                let _ = writeln!(ctx.io.stderr, "history: usage: {}", self.usage());
+                return 2;
+            }
+        };
+
+        match result {
+            Ok(()) => 0,
+            Err(e) => {
+                let _ = writeln!(ctx.io.stderr, "history: {}", streams::describe(&e));
+                1
+            }
+        }
+    }
+}
+
+/// Print the last `count` entries, or all of them, with their numbers
+fn list(ctx: &mut Context, count: Option<usize>) -> ExitStatus {
+    let entries = ctx.shell.history().entries();
+    let skip = entries.len().saturating_sub(count.unwrap_or(entries.len()));
+
+    for (index, entry) in entries.iter().enumerate().skip(skip) {
+        if writeln!(ctx.io.stdout, "{:5}  {}", index + 1, entry).is_err() {
+            return 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_list_and_delete() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        for line in ["ls", "pwd", "echo hi"] {
+            shell.history_mut().add(line).unwrap();
+        }
+
+        shell.run_line("history 2");
+        assert_eq!(out.take(), b"    2  pwd\n    3  echo hi\n");
+
+        assert_eq!(shell.run_line("history -d 1"), 0);
+        assert_eq!(shell.run_line("history -d 9 2>/dev/null"), 1);
+        shell.run_line("history");
+        assert_eq!(out.take(), b"    1  pwd\n    2  echo hi\n");
+
+        assert_eq!(shell.run_line("history -c"), 0);
+        assert!(shell.history().entries().is_empty());
+    }
+}
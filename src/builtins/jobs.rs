@@ -0,0 +1,57 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+
+/// List background and stopped jobs
+/// Usage: jobs [-l | -p]
+pub struct Jobs;
+
+impl Builtin for Jobs {
+    fn name(&self) -> &'static str {
+        "jobs"
+    }
+
+    fn usage(&self) -> &'static str {
+        "jobs [-l | -p]"
+    }
+
+    fn help(&self) -> &'static str {
+        "List background and stopped jobs"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-l", "-p"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let (long, pids_only) = match args {
+            [] => (false, false),
+            ["-l"] => (true, false),
+            ["-p"] => (false, true),
+            _ => {
+                let _ = writeln!(ctx.io.stderr, "jobs: usage: {}", self.usage());
+                return 2;
+            }
+        };
+
+        ctx.shell.jobs_mut().reap();
+        let table = ctx.shell.jobs();
+        let lines: Vec<String> = table
+            .iter()
+            .map(|job| match pids_only {
+                true => job.pids()[0].to_string(),
+                false => table.format(job, long),
+            })
+            .collect();
+
+        // Everything was just shown, so there is nothing left to announce
+        ctx.shell.jobs_mut().take_notifications();
+
+        for line in lines {
+            if writeln!(ctx.io.stdout, "{}", line).is_err() {
+                return 1;
+            }
+        }
+        0
+    }
+}
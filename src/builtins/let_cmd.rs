@@ -0,0 +1,76 @@
+use std::io::Write;
+
+use crate::arith;
+use crate::builtins::{Builtin, Context, ExitStatus};
+
+/// Evaluate arithmetic expressions
+/// Usage: let expression...
+pub struct Let;
+
+impl Builtin for Let {
+    fn name(&self) -> &'static str {
+        "let"
+    }
+
+    fn usage(&self) -> &'static str {
+        "let expression..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Evaluate each arithmetic expression; succeed if the last one is not 0"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.is_empty() {
+            let _ = writeln!(ctx.io.stderr, "let: usage: {}", self.usage());
+            return 2;
+        }
+
+        let mut last = 0;
+        for expression in args {
+            match arith::evaluate(expression, ctx.shell) {
+                Ok(value) => last = value,
+                Err(error) => {
+                    let _ = writeln!(ctx.io.stderr, "let: {}: {}", expression, error);
+                    return 1;
+                }
+            }
+        }
+
+        if last == 0 {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_let() {
+        let mut shell = Shell::new();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_stderr(Output::Buffer(err.clone()));
+
+        assert_eq!(shell.run_line("let 'n = 0x10' n++ 'mask = n | 1'"), 0);
+        assert_eq!(shell.var("n"), Some("17"));
+        assert_eq!(shell.var("mask"), Some("17"));
+
+        let script = "retries=0\nwhile let 'retries < 3'; do let retries+=1; done\necho $retries";
+        assert_eq!(shell.run_script(script), 0);
+        assert_eq!(out.to_string_lossy(), "3\n");
+
+        assert_eq!(shell.run_line("let 'n - 17'"), 1);
+        assert_eq!(shell.run_line("let n/0"), 1);
+        assert_eq!(shell.run_line("let"), 2);
+        assert_eq!(
+            err.to_string_lossy(),
+            "let: n/0: division by zero\nlet: usage: let expression...\n"
+        );
+    }
+}
@@ -0,0 +1,103 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::parser::{is_name, quote};
+
+/// Give variables a value that lasts until the running function returns
+/// Usage: local [name[=value] ...]
+pub struct Local;
+
+impl Builtin for Local {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn usage(&self) -> &'static str {
+        "local [name[=value] ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Make variables local to the running function, or list the local ones"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if ctx.shell.function_depth() == 0 {
+            let _ = writeln!(ctx.io.stderr, "local: can only be used in a function");
+            return 1;
+        }
+
+        if args.is_empty() {
+            for name in ctx.shell.locals() {
+                if let Some(value) = ctx.shell.var(name) {
+                    let _ = writeln!(ctx.io.stdout, "{}={}", name, quote(value));
+                }
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (*arg, None),
+            };
+            if !is_name(name) {
+                let _ = writeln!(ctx.io.stderr, "local: `{}': not a valid identifier", arg);
+                status = 1;
+                continue;
+            }
+
+            ctx.shell.make_local(name);
+            // Without a value the variable starts out unset, as in bash
+            match value {
+                Some(value) => ctx.shell.set_var(name, value),
+                None => ctx.shell.unset_var(name),
+            }
+        }
+
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_functions_with_locals_and_return() {
+        let mut shell = Shell::new();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_stderr(Output::Buffer(err.clone()));
+
+        let script = "\
+x=global
+show() { echo \"$# [$1] x=$x\"; }
+change() {
+  local x=local y
+  y=set; show \"$2\"
+  inner() { x=inner; return 3; echo never; }
+  inner
+  echo \"inner: $? x=$x\"
+}
+change a b
+show
+echo \"y=$y\"";
+        assert_eq!(shell.run_script(script), 0);
+        assert_eq!(shell.var("y"), None);
+        assert_eq!(
+            out.to_string_lossy(),
+            "1 [b] x=local\ninner: 3 x=inner\n0 [] x=global\ny=\n"
+        );
+
+        out.take();
+        assert_eq!(shell.run_line("local z=1"), 1);
+        assert_eq!(shell.run_line("return"), 1);
+        assert_eq!(
+            err.to_string_lossy(),
+            "local: can only be used in a function\n\
+             return: can only `return' from a function or sourced script\n"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::compound::LoopControl;
+
+/// Leave enclosing loops
+/// Usage: break [n]
+pub struct Break;
+
+/// Go on with the next round of an enclosing loop
+/// Usage: continue [n]
+pub struct Continue;
+
+impl Builtin for Break {
+    fn name(&self) -> &'static str {
+        "break"
+    }
+
+    fn usage(&self) -> &'static str {
+        "break [n]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Leave the innermost for, while or until loop, or n of them"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        leave(self.name(), ctx, args, LoopControl::Break)
+    }
+}
+
+impl Builtin for Continue {
+    fn name(&self) -> &'static str {
+        "continue"
+    }
+
+    fn usage(&self) -> &'static str {
+        "continue [n]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Skip to the next round of the innermost loop, or of the nth one"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        leave(self.name(), ctx, args, LoopControl::Continue)
+    }
+}
+
+fn leave(name: &str, ctx: &mut Context, args: &[&str], control: fn(usize) -> LoopControl) -> ExitStatus {
+    let count = match args {
+        [] => 1,
+        [count] => match count.parse::<usize>() {
+            Ok(0) => {
+                let _ = writeln!(ctx.io.stderr, "{}: {}: loop count out of range", name, count);
+                return 1;
+            }
+            Ok(count) => count,
+            Err(_) => {
+                let _ = writeln!(ctx.io.stderr, "{}: {}: numeric argument required", name, count);
+                return 1;
+            }
+        },
+        _ => {
+            let _ = writeln!(ctx.io.stderr, "{}: too many arguments", name);
+            return 1;
+        }
+    };
+
+    let depth = ctx.shell.loop_depth();
+    if depth == 0 {
+        let _ = writeln!(ctx.io.stderr, "{}: only meaningful in a `for', `while', or `until' loop", name);
+        return 0;
+    }
+    // Asking for more loops than there are leaves them all
+    ctx.shell.set_loop_control(control(count.min(depth)));
+    0
+}
@@ -1,8 +1,12 @@
 use std::fs;
+use std::io::Write;
 use std::os::unix::fs::MetadataExt;
 use std::path::Path;
 use std::time::UNIX_EPOCH;
 
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::streams;
+
 #[derive(Default)]
 struct LsFlags {
     show_all: bool,      // -a flag
@@ -11,39 +15,99 @@ struct LsFlags {
 }
 
 /// List directory contents with support for -a, -l, -F flags
-pub fn execute(args: &[&str]) {
-    let mut flags = LsFlags::default();
-    let mut path = ".";  // Default to current directory
-    
-    // Parse arguments
-    for arg in args {
-        if arg.starts_with('-') {
-            // Parse flags
-            for ch in arg.chars().skip(1) {
-                match ch {
-                    'a' => flags.show_all = true,
-                    'l' => flags.long_format = true,
-                    'F' => flags.classify = true,
-                    _ => {
-                        eprintln!("ls: invalid option -- '{}'", ch);
-                        return;
+pub struct Ls;
+
+impl Builtin for Ls {
+    fn name(&self) -> &'static str {
+        "ls"
+    }
+
+    fn usage(&self) -> &'static str {
+        "ls [-a] [-l] [-F] [path ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "List directory contents"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-a", "-l", "-F"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let mut flags = LsFlags::default();
+        let mut paths = Vec::new();
+        
+        // Parse arguments
+        for arg in args {
+            if arg.starts_with('-') {
+                // Parse flags
+                for ch in arg.chars().skip(1) {
+                    match ch {
+                        'a' => flags.show_all = true,
+                        'l' => flags.long_format = true,
+                        'F' => flags.classify = true,
+                        _ => {
+                            let _ = writeln!(ctx.io.stderr, "ls: invalid option -- '{}'", ch);
+                            return 1;
+                        }
                     }
                 }
+            } else {
+                // Path argument
+                paths.push(*arg);
             }
-        } else {
-            // Path argument
-            path = arg;
         }
-    }
-    
-    // List directory contents
-    if let Err(e) = list_directory(path, &flags) {
-        eprintln!("ls: {}: {}", path, e);
+        if paths.is_empty() {
+            paths.push(".");  // Default to current directory
+        }
+        paths.sort();
+
+        // Like ls, files given by name are listed first, then the contents
+        // of each directory
+        let mut status = 0;
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for path in &paths {
+            match fs::metadata(ctx.shell.resolve(path)) {
+                Ok(metadata) if metadata.is_dir() => dirs.push(*path),
+                Ok(metadata) => files.push((path.to_string(), metadata)),
+                Err(e) => {
+                    let _ = writeln!(ctx.io.stderr, "ls: {}: {}", path, streams::describe(&e));
+                    status = 1;
+                }
+            }
+        }
+
+        let mut result = files
+            .iter()
+            .try_for_each(|(name, metadata)| print_entry(name, metadata, &flags, &mut ctx.io.stdout));
+
+        for (index, path) in dirs.iter().enumerate() {
+            if result.is_err() {
+                break;
+            }
+            // Name each directory when there is more than one thing to show
+            if paths.len() > 1 {
+                let separator = if index > 0 || !files.is_empty() { "\n" } else { "" };
+                let _ = writeln!(ctx.io.stdout, "{}{}:", separator, path);
+            }
+            result = list_directory(&ctx.shell.resolve(path), &flags, &mut ctx.io.stdout);
+            if let Err(e) = &result {
+                if !streams::is_broken_pipe(&**e) {
+                    let _ = writeln!(ctx.io.stderr, "ls: {}: {}", path, e);
+                }
+            }
+        }
+
+        match result {
+            Ok(()) => status,
+            Err(_) => 1,
+        }
     }
 }
 
-fn list_directory(path: &str, flags: &LsFlags) -> Result<(), Box<dyn std::error::Error>> {
-    let dir_path = Path::new(path);
+fn list_directory(dir_path: &Path, flags: &LsFlags, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
     let entries = fs::read_dir(dir_path)?;
     
     let mut items = Vec::new();
@@ -60,36 +124,41 @@ fn list_directory(path: &str, flags: &LsFlags) -> Result<(), Box<dyn std::error:
         }
         
         let metadata = entry.metadata()?;
-        items.push((name.to_string(), entry.path(), metadata));
+        items.push((name.to_string(), metadata));
     }
     
     // Sort by name
     items.sort_by(|a, b| a.0.cmp(&b.0));
     
     // Display entries
-    for (name, path, metadata) in items {
-        if flags.long_format {
-            print_long_format(&name, &path, &metadata)?;
-        } else {
-            let mut display_name = name;
-            
-            // Add classifier suffix if -F flag is set
-            if flags.classify {
-                if metadata.is_dir() {
-                    display_name.push('/');
-                } else if is_executable(&metadata) {
-                    display_name.push('*');
-                }
-            }
-            
-            println!("{}", display_name);
+    for (name, metadata) in items {
+        print_entry(&name, &metadata, flags, out)?;
+    }
+    
+    Ok(())
+}
+
+fn print_entry(name: &str, metadata: &fs::Metadata, flags: &LsFlags, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
+    if flags.long_format {
+        return print_long_format(name, metadata, out);
+    }
+
+    let mut display_name = name.to_string();
+    
+    // Add classifier suffix if -F flag is set
+    if flags.classify {
+        if metadata.is_dir() {
+            display_name.push('/');
+        } else if is_executable(metadata) {
+            display_name.push('*');
         }
     }
     
+    writeln!(out, "{}", display_name)?;
     Ok(())
 }
 
-fn print_long_format(name: &str, _path: &Path, metadata: &fs::Metadata) -> Result<(), Box<dyn std::error::Error>> {
+fn print_long_format(name: &str, metadata: &fs::Metadata, out: &mut dyn Write) -> Result<(), Box<dyn std::error::Error>> {
     // Format: mode links uid:gid size mtime name
     let mode_str = format_mode(metadata.mode());
     let nlink = metadata.nlink();
@@ -107,8 +176,8 @@ fn print_long_format(name: &str, _path: &Path, metadata: &fs::Metadata) -> Resul
         display_name.push('*');
     }
     
-    println!("{} {:3} {}:{} {:8} {} {}", 
-             mode_str, nlink, uid, gid, size, mtime, display_name);
+    writeln!(out, "{} {:3} {}:{} {:8} {} {}", 
+             mode_str, nlink, uid, gid, size, mtime, display_name)?;
     
     Ok(())
 }
@@ -186,4 +255,21 @@ mod tests {
         let exec_mode = 0o100755; // -rwxr-xr-x
         assert_eq!(format_mode(exec_mode), "-rwxr-xr-x");
     }
+
+    #[test]
+    fn test_files_and_directories() {
+        fs::create_dir_all("test_ls_dir/sub").unwrap();
+        fs::write("test_ls_dir/b.txt", "").unwrap();
+        fs::write("test_ls_dir/sub/inner", "").unwrap();
+
+        let mut shell = crate::shell::Shell::new();
+        let out = crate::streams::Buffer::new();
+        shell.set_stdout(crate::streams::Output::Buffer(out.clone()));
+        shell.set_cwd("test_ls_dir");
+
+        assert_eq!(shell.run_line("ls -F sub b.txt"), 0);
+        assert_eq!(out.to_string_lossy(), "b.txt\n\nsub:\ninner\n");
+
+        fs::remove_dir_all("test_ls_dir").unwrap();
+    }
 }
@@ -1,26 +1,81 @@
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 
+use crate::builtins::{Builtin, Context, ExitStatus};
+
 /// Create directories
-/// Usage: mkdir <dir1> [dir2] ...
-/// Note: Parent directory creation (-p flag) is not supported in this minimal implementation
-pub fn execute(args: &[&str]) {
-    if args.is_empty() {
-        eprintln!("mkdir: missing operand");
-        return;
+/// Usage: mkdir [-p] <dir1> [dir2] ...
+/// -p: Create missing parent directories, and accept existing directories
+pub struct Mkdir;
+
+impl Builtin for Mkdir {
+    fn name(&self) -> &'static str {
+        "mkdir"
     }
-    
-    // Create each directory
-    for dir_name in args {
-        if let Err(e) = create_directory(dir_name) {
-            eprintln!("mkdir: {}: {}", dir_name, e);
+
+    fn usage(&self) -> &'static str {
+        "mkdir [-p] <dir> ..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Create directories"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-p"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.is_empty() {
+            let _ = writeln!(ctx.io.stderr, "mkdir: missing operand");
+            return 1;
+        }
+        
+        let mut parents = false;
+        let mut dirs = Vec::new();
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(options) if !options.is_empty() => {
+                    for ch in options.chars() {
+                        match ch {
+                            'p' => parents = true,
+                            _ => {
+                                let _ = writeln!(ctx.io.stderr, "mkdir: invalid option -- '{}'", ch);
+                                return 1;
+                            }
+                        }
+                    }
+                }
+                _ => dirs.push(*arg),
+            }
         }
+        if dirs.is_empty() {
+            let _ = writeln!(ctx.io.stderr, "mkdir: missing operand");
+            return 1;
+        }
+
+        // Create each directory
+        let mut status = 0;
+        for dir_name in dirs {
+            if let Err(e) = create_directory(&ctx.shell.resolve(dir_name), parents) {
+                let _ = writeln!(ctx.io.stderr, "mkdir: {}: {}", dir_name, e);
+                status = 1;
+            }
+        }
+
+        status
     }
 }
 
-fn create_directory(dir_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(dir_name);
-    
+fn create_directory(path: &Path, parents: bool) -> Result<(), Box<dyn std::error::Error>> {
+
+    // With -p, missing parents are made and an existing directory is fine
+    if parents {
+        fs::create_dir_all(path)?;
+        return Ok(());
+    }
+
     // Check if directory already exists
     if path.exists() {
         if path.is_dir() {
@@ -46,7 +101,7 @@ mod tests {
         let dir_name = "test_mkdir_dir";
         
         // Create directory
-        let result = create_directory(dir_name);
+        let result = create_directory(Path::new(dir_name), false);
         assert!(result.is_ok());
         
         // Verify it exists and is a directory
@@ -66,7 +121,7 @@ mod tests {
         fs::create_dir(dir_name).unwrap();
         
         // Try to create it again
-        let result = create_directory(dir_name);
+        let result = create_directory(Path::new(dir_name), false);
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("File exists"));
@@ -84,7 +139,7 @@ mod tests {
         fs::write(file_name, "content").unwrap();
         
         // Try to create directory with same name
-        let result = create_directory(file_name);
+        let result = create_directory(Path::new(file_name), false);
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("File exists"));
@@ -100,8 +155,8 @@ mod tests {
         let dir2 = "test_mkdir_multi2";
         
         // Create both directories
-        let result1 = create_directory(dir1);
-        let result2 = create_directory(dir2);
+        let result1 = create_directory(Path::new(dir1), false);
+        let result2 = create_directory(Path::new(dir2), false);
         
         assert!(result1.is_ok());
         assert!(result2.is_ok());
@@ -118,8 +173,18 @@ mod tests {
     #[test]
     fn test_create_directory_invalid_parent() {
         // Try to create directory with non-existent parent
-        let result = create_directory("nonexistent_parent/new_dir");
+        let result = create_directory(Path::new("nonexistent_parent/new_dir"), false);
         assert!(result.is_err());
         // The exact error message depends on the OS, but it should fail
     }
+
+    #[test]
+    fn test_create_parents_with_braces() {
+        let mut shell = crate::shell::Shell::new();
+        assert_eq!(shell.run_line("mkdir -p test_mkdir_parents/{a,b/c} test_mkdir_parents/a"), 0);
+        assert!(Path::new("test_mkdir_parents/a").is_dir());
+        assert!(Path::new("test_mkdir_parents/b/c").is_dir());
+
+        fs::remove_dir_all("test_mkdir_parents").unwrap();
+    }
 }
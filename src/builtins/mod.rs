@@ -7,3 +7,215 @@ pub mod cp;
 pub mod rm;
 pub mod mv;
 pub mod mkdir;
+pub mod exit;
+pub mod help;
+pub mod type_cmd;
+pub mod export;
+pub mod unset;
+pub mod env;
+pub mod set;
+pub mod history;
+pub mod jobs;
+pub mod fg;
+pub mod bg;
+pub mod wait;
+pub mod source;
+pub mod alias;
+pub mod unalias;
+pub mod loops;
+pub mod local;
+pub mod return_cmd;
+pub mod let_cmd;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::shell::Shell;
+use crate::streams::Io;
+
+/// Status a command finishes with: 0 for success, anything else for failure
+pub type ExitStatus = i32;
+
+/// A command implemented inside the shell
+///
+/// Builtins may run on a pipeline thread, so they must be `Send + Sync`.
+/// They talk to the outside world only through the `Context` they are given.
+pub trait Builtin: Send + Sync {
+    /// The name the command is invoked by
+    fn name(&self) -> &'static str;
+
+    /// A one-line synopsis, such as `rm [-r] <file> ...`
+    fn usage(&self) -> &'static str;
+
+    /// A one-line description shown by `help`
+    fn help(&self) -> &'static str;
+
+    /// The options the command accepts, offered by tab completion
+    fn flags(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Run the command with its arguments (the name is not included)
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus;
+}
+
+/// Everything a builtin can see and change while it runs
+pub struct Context<'a> {
+    /// The command's standard streams, after pipes and redirections
+    pub io: Io,
+    /// The shell the command runs in: working directory, variables,
+    /// registered builtins and the status of the previous command
+    pub shell: &'a mut Shell,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(io: Io, shell: &'a mut Shell) -> Self {
+        Context { io, shell }
+    }
+}
+
+/// The set of builtins the shell looks commands up in
+#[derive(Clone, Default)]
+pub struct Registry {
+    builtins: BTreeMap<&'static str, Arc<dyn Builtin>>,
+}
+
+impl Registry {
+    /// A registry with no builtins at all
+    pub fn new() -> Self {
+        Registry::default()
+    }
+
+    /// A registry holding every builtin that ships with the shell
+    pub fn with_defaults() -> Self {
+        let mut registry = Registry::new();
+        registry.register(exit::Exit);
+        registry.register(pwd::Pwd);
+        registry.register(cd::Cd);
+        registry.register(echo::Echo);
+        registry.register(ls::Ls);
+        registry.register(cat::Cat);
+        registry.register(cp::Cp);
+        registry.register(rm::Rm);
+        registry.register(mv::Mv);
+        registry.register(mkdir::Mkdir);
+        registry.register(help::Help);
+        registry.register(type_cmd::Type);
+        registry.register(export::Export);
+        registry.register(unset::Unset);
+        registry.register(env::Env);
+        registry.register(set::Set);
+        registry.register(history::History);
+        registry.register(jobs::Jobs);
+        registry.register(fg::Fg);
+        registry.register(bg::Bg);
+        registry.register(wait::Wait);
+        registry.register(source::Source);
+        registry.register(source::Dot);
+        registry.register(alias::Alias);
+        registry.register(unalias::Unalias);
+        registry.register(loops::Break);
+        registry.register(loops::Continue);
+        registry.register(local::Local);
+        registry.register(return_cmd::Return);
+        registry.register(let_cmd::Let);
+        registry
+    }
+
+    /// Add a builtin, replacing any existing one with the same name
+    pub fn register(&mut self, builtin: impl Builtin + 'static) {
+        self.builtins.insert(builtin.name(), Arc::new(builtin));
+    }
+
+    /// Look up a builtin by name
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Builtin>> {
+        self.builtins.get(name).cloned()
+    }
+
+    /// Whether a builtin with this name exists
+    pub fn contains(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    /// All builtins, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Builtin> {
+        self.builtins.values().map(|builtin| builtin.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streams::{Buffer, Output};
+    use std::io::Write;
+
+    struct Greet;
+
+    impl Builtin for Greet {
+        fn name(&self) -> &'static str {
+            "greet"
+        }
+
+        fn usage(&self) -> &'static str {
+            "greet <name>"
+        }
+
+        fn help(&self) -> &'static str {
+            "Say hello"
+        }
+
+        fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+            let _ = writeln!(ctx.io.stdout, "hello {}", args.join(" "));
+            0
+        }
+    }
+
+    #[test]
+    fn test_default_builtins() {
+        let registry = Registry::with_defaults();
+        for name in ["exit", "pwd", "cd", "echo", "ls", "cat", "cp", "rm", "mv", "mkdir", "help", "type", "export", "unset", "env", "set", "history"] {
+            assert!(registry.contains(name), "missing builtin {}", name);
+        }
+        assert!(!registry.contains("grep"));
+    }
+
+    #[test]
+    fn test_register_custom_builtin() {
+        let mut registry = Registry::new();
+        registry.register(Greet);
+        assert_eq!(registry.iter().map(|b| b.name()).collect::<Vec<_>>(), vec!["greet"]);
+
+        let mut shell = Shell::new();
+        let mut io = Io::inherit();
+        let out = Buffer::new();
+        io.stdout = Output::Buffer(out.clone());
+        let mut ctx = Context::new(io, &mut shell);
+
+        let builtin = registry.get("greet").unwrap();
+        assert_eq!(builtin.run(&mut ctx, &["world"]), 0);
+        assert_eq!(out.to_string_lossy(), "hello world\n");
+    }
+
+    #[test]
+    fn test_shell_dispatches_registered_builtin() {
+        let mut shell = Shell::new();
+        shell.register(Greet);
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        assert_eq!(shell.run_line("greet there && type greet"), 0);
+        assert_eq!(out.to_string_lossy(), "hello there\ngreet is a shell builtin\n");
+    }
+
+    #[test]
+    fn test_exit_only_requests_termination() {
+        let mut shell = Shell::new();
+        shell.run_line("false");
+        let exit = shell.registry().get("exit").unwrap();
+        let mut ctx = Context::new(Io::inherit(), &mut shell);
+
+        let status = exit.run(&mut ctx, &[]);
+        assert_eq!(status, 1);
+        assert_eq!(shell.exit_requested(), Some(1));
+    }
+}
@@ -1,27 +1,49 @@
 use std::fs;
 use std::path::Path;
-use std::io::ErrorKind;
+use std::io::{ErrorKind, Write};
+
+use crate::builtins::{Builtin, Context, ExitStatus};
 
 /// Move/rename files and directories
 /// Usage: mv <source> <destination>
 /// Handles cross-filesystem moves by falling back to copy+remove
-pub fn execute(args: &[&str]) {
-    if args.len() != 2 {
-        eprintln!("mv: usage: mv <source> <destination>");
-        return;
+pub struct Mv;
+
+impl Builtin for Mv {
+    fn name(&self) -> &'static str {
+        "mv"
     }
-    
-    let source = args[0];
-    let destination = args[1];
-    
-    if let Err(e) = move_file(source, destination) {
-        eprintln!("mv: {}", e);
+
+    fn usage(&self) -> &'static str {
+        "mv <source> <destination>"
+    }
+
+    fn help(&self) -> &'static str {
+        "Move or rename a file or directory"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.len() != 2 {
+            let _ = writeln!(ctx.io.stderr, "mv: usage: mv <source> <destination>");
+            return 1;
+        }
+        
+        let source = args[0];
+        let destination = args[1];
+        
+        if let Err(e) = move_file(source, destination, ctx.shell.cwd()) {
+            let _ = writeln!(ctx.io.stderr, "mv: {}", e);
+            return 1;
+        }
+
+        0
     }
 }
 
-fn move_file(source: &str, destination: &str) -> Result<(), Box<dyn std::error::Error>> {
-    let source_path = Path::new(source);
-    let dest_path = Path::new(destination);
+/// Relative paths are taken relative to `cwd`
+fn move_file(source: &str, destination: &str, cwd: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let source_path = cwd.join(source);
+    let dest_path = cwd.join(destination);
     
     // Validate source
     if !source_path.exists() {
@@ -44,14 +66,14 @@ fn move_file(source: &str, destination: &str) -> Result<(), Box<dyn std::error::
     }
     
     // Try atomic rename first
-    match fs::rename(source_path, &final_dest_path) {
+    match fs::rename(&source_path, &final_dest_path) {
         Ok(()) => Ok(()),
         Err(e) => {
             // Check if this is a cross-device error
             if e.kind() == ErrorKind::CrossesDevices || 
                e.raw_os_error() == Some(18) { // EXDEV on Unix
                 // Fall back to copy + remove
-                copy_and_remove(source_path, &final_dest_path)
+                copy_and_remove(&source_path, &final_dest_path)
             } else {
                 Err(format!("{}: {}", source, e).into())
             }
@@ -90,7 +112,7 @@ mod tests {
     
     #[test]
     fn test_move_nonexistent_file() {
-        let result = move_file("nonexistent.txt", "dest.txt");
+        let result = move_file("nonexistent.txt", "dest.txt", Path::new("."));
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("No such file or directory"));
@@ -104,7 +126,7 @@ mod tests {
         fs::write("test_mv_source.txt", test_content).unwrap();
         
         // Move it
-        let result = move_file("test_mv_source.txt", "test_mv_dest.txt");
+        let result = move_file("test_mv_source.txt", "test_mv_dest.txt", Path::new("."));
         assert!(result.is_ok());
         
         // Verify the move
@@ -128,7 +150,7 @@ mod tests {
         fs::create_dir("test_mv_dir").unwrap();
         
         // Move file to directory
-        let result = move_file("test_mv_source_dir.txt", "test_mv_dir");
+        let result = move_file("test_mv_source_dir.txt", "test_mv_dir", Path::new("."));
         assert!(result.is_ok());
         
         // Verify the move
@@ -151,7 +173,7 @@ mod tests {
         fs::write("test_mv_same.txt", test_content).unwrap();
         
         // Move to itself (should be no-op)
-        let result = move_file("test_mv_same.txt", "test_mv_same.txt");
+        let result = move_file("test_mv_same.txt", "test_mv_same.txt", Path::new("."));
         assert!(result.is_ok());
         
         // Verify file still exists with same content
@@ -1,13 +1,30 @@
-use std::env;
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
 
 /// Print working directory - displays the current directory path
-pub fn execute(_args: &[&str]) {
-    match env::current_dir() {
-        Ok(path) => {
-            println!("{}", path.display());
-        }
-        Err(error) => {
-            eprintln!("pwd: {}", error);
+pub struct Pwd;
+
+impl Builtin for Pwd {
+    fn name(&self) -> &'static str {
+        "pwd"
+    }
+
+    fn usage(&self) -> &'static str {
+        "pwd"
+    }
+
+    fn help(&self) -> &'static str {
+        "Print the current working directory"
+    }
+
+    fn run(&self, ctx: &mut Context, _args: &[&str]) -> ExitStatus {
+        match writeln!(ctx.io.stdout, "{}", ctx.shell.cwd().display()) {
+            Ok(()) => 0,
+            Err(error) => {
+                let _ = writeln!(ctx.io.stderr, "pwd: {}", error);
+                1
+            }
         }
     }
 }
@@ -0,0 +1,50 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+
+/// Leave the running function or sourced file
+/// Usage: return [n]
+pub struct Return;
+
+impl Builtin for Return {
+    fn name(&self) -> &'static str {
+        "return"
+    }
+
+    fn usage(&self) -> &'static str {
+        "return [n]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Leave a function or sourced file with status n, or that of the last command"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if !ctx.shell.can_return() {
+            let _ = writeln!(
+                ctx.io.stderr,
+                "return: can only `return' from a function or sourced script"
+            );
+            return 1;
+        }
+
+        let status = match args {
+            [] => ctx.shell.last_status(),
+            [code] => match code.parse::<i64>() {
+                // Only the low 8 bits are kept, as for `exit`
+                Ok(code) => (code & 0xff) as ExitStatus,
+                Err(_) => {
+                    let _ = writeln!(ctx.io.stderr, "return: {}: numeric argument required", code);
+                    2
+                }
+            },
+            _ => {
+                let _ = writeln!(ctx.io.stderr, "return: too many arguments");
+                return 1;
+            }
+        };
+
+        ctx.shell.set_return(status);
+        status
+    }
+}
@@ -1,6 +1,10 @@
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::signals;
+
 #[derive(Default)]
 struct RmFlags {
     recursive: bool,  // -r flag
@@ -9,50 +13,76 @@ struct RmFlags {
 /// Remove files and directories
 /// Usage: rm [-r] <file1> [file2] ...
 /// -r: Remove directories recursively
-pub fn execute(args: &[&str]) {
-    if args.is_empty() {
-        eprintln!("rm: missing operand");
-        return;
+pub struct Rm;
+
+impl Builtin for Rm {
+    fn name(&self) -> &'static str {
+        "rm"
     }
-    
-    let mut flags = RmFlags::default();
-    let mut files = Vec::new();
-    
-    // Parse arguments
-    for arg in args {
-        if arg.starts_with('-') {
-            // Parse flags
-            for ch in arg.chars().skip(1) {
-                match ch {
-                    'r' => flags.recursive = true,
-                    _ => {
-                        eprintln!("rm: invalid option -- '{}'", ch);
-                        return;
+
+    fn usage(&self) -> &'static str {
+        "rm [-r] <file> ..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Remove files and directories"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-r"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.is_empty() {
+            let _ = writeln!(ctx.io.stderr, "rm: missing operand");
+            return 1;
+        }
+        
+        let mut flags = RmFlags::default();
+        let mut files = Vec::new();
+        
+        // Parse arguments
+        for arg in args {
+            if arg.starts_with('-') {
+                // Parse flags
+                for ch in arg.chars().skip(1) {
+                    match ch {
+                        'r' => flags.recursive = true,
+                        _ => {
+                            let _ = writeln!(ctx.io.stderr, "rm: invalid option -- '{}'", ch);
+                            return 1;
+                        }
                     }
                 }
+            } else {
+                // File/directory argument
+                files.push(*arg);
             }
-        } else {
-            // File/directory argument
-            files.push(*arg);
         }
-    }
-    
-    if files.is_empty() {
-        eprintln!("rm: missing operand");
-        return;
-    }
-    
-    // Remove each file/directory
-    for file in files {
-        if let Err(e) = remove_path(file, &flags) {
-            eprintln!("rm: {}: {}", file, e);
+        
+        if files.is_empty() {
+            let _ = writeln!(ctx.io.stderr, "rm: missing operand");
+            return 1;
+        }
+        
+        // Remove each file/directory
+        let mut status = 0;
+        for file in files {
+            if let Err(e) = remove_path(&ctx.shell.resolve(file), &flags) {
+                let _ = writeln!(ctx.io.stderr, "rm: {}: {}", file, e);
+                status = 1;
+            }
+            if signals::interrupted() {
+                return signals::INTERRUPT_STATUS;
+            }
         }
+
+        status
     }
 }
 
-fn remove_path(path_str: &str, flags: &RmFlags) -> Result<(), Box<dyn std::error::Error>> {
-    let path = Path::new(path_str);
-    
+fn remove_path(path: &Path, flags: &RmFlags) -> Result<(), Box<dyn std::error::Error>> {
+
     if !path.exists() {
         return Err("No such file or directory".into());
     }
@@ -63,7 +93,7 @@ fn remove_path(path_str: &str, flags: &RmFlags) -> Result<(), Box<dyn std::error
     } else if path.is_dir() {
         if flags.recursive {
             // Remove directory recursively
-            fs::remove_dir_all(path)?;
+            remove_tree(path)?;
         } else {
             return Err("Is a directory (use -r to remove directories)".into());
         }
@@ -74,6 +104,31 @@ fn remove_path(path_str: &str, flags: &RmFlags) -> Result<(), Box<dyn std::error
     Ok(())
 }
 
+/// Remove a directory and everything in it, like `fs::remove_dir_all`,
+/// but stopping early on Ctrl-C. Symlinks are removed, not followed.
+fn remove_tree(path: &Path) -> std::io::Result<()> {
+    if fs::symlink_metadata(path)?.file_type().is_symlink() {
+        return fs::remove_file(path);
+    }
+
+    for entry in fs::read_dir(path)? {
+        if signals::interrupted() {
+            return Ok(());
+        }
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            remove_tree(&entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    if signals::interrupted() {
+        return Ok(());
+    }
+    fs::remove_dir(path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -81,7 +136,7 @@ mod tests {
     
     #[test]
     fn test_remove_nonexistent_file() {
-        let result = remove_path("nonexistent.txt", &RmFlags::default());
+        let result = remove_path(Path::new("nonexistent.txt"), &RmFlags::default());
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("No such file or directory"));
@@ -94,7 +149,7 @@ mod tests {
         fs::write("test_rm_file.txt", "test content").unwrap();
         
         // Remove it
-        let result = remove_path("test_rm_file.txt", &RmFlags::default());
+        let result = remove_path(Path::new("test_rm_file.txt"), &RmFlags::default());
         assert!(result.is_ok());
         
         // Verify it's gone
@@ -107,7 +162,7 @@ mod tests {
         fs::create_dir("test_rm_dir").unwrap();
         
         // Try to remove without -r flag
-        let result = remove_path("test_rm_dir", &RmFlags::default());
+        let result = remove_path(Path::new("test_rm_dir"), &RmFlags::default());
         assert!(result.is_err());
         if let Err(e) = result {
             assert!(e.to_string().contains("Is a directory"));
@@ -127,7 +182,7 @@ mod tests {
         
         // Remove recursively
         let flags = RmFlags { recursive: true };
-        let result = remove_path("test_rm_recursive", &flags);
+        let result = remove_path(Path::new("test_rm_recursive"), &flags);
         assert!(result.is_ok());
         
         // Verify it's gone
@@ -141,8 +196,8 @@ mod tests {
         fs::write("test_rm2.txt", "content2").unwrap();
         
         // Remove both
-        let result1 = remove_path("test_rm1.txt", &RmFlags::default());
-        let result2 = remove_path("test_rm2.txt", &RmFlags::default());
+        let result1 = remove_path(Path::new("test_rm1.txt"), &RmFlags::default());
+        let result2 = remove_path(Path::new("test_rm2.txt"), &RmFlags::default());
         
         assert!(result1.is_ok());
         assert!(result2.is_ok());
@@ -151,4 +206,45 @@ mod tests {
         assert!(!Path::new("test_rm1.txt").exists());
         assert!(!Path::new("test_rm2.txt").exists());
     }
+
+    #[test]
+    fn test_remove_tree_does_not_follow_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        fs::create_dir_all("test_rm_tree/a/b").unwrap();
+        fs::write("test_rm_tree/a/b/deep.txt", "deep").unwrap();
+        fs::create_dir_all("test_rm_tree_kept").unwrap();
+        fs::write("test_rm_tree_kept/file.txt", "kept").unwrap();
+        symlink("../../test_rm_tree_kept", "test_rm_tree/a/dir_link").unwrap();
+        symlink("../test_rm_tree_kept/file.txt", "test_rm_tree/file_link").unwrap();
+        symlink("test_rm_tree_kept", "test_rm_tree_link").unwrap();
+
+        // A link to a directory goes, the directory stays
+        remove_tree(Path::new("test_rm_tree_link")).unwrap();
+        assert!(fs::symlink_metadata("test_rm_tree_link").is_err());
+
+        remove_tree(Path::new("test_rm_tree")).unwrap();
+        assert!(fs::symlink_metadata("test_rm_tree").is_err());
+        assert_eq!(fs::read_to_string("test_rm_tree_kept/file.txt").unwrap(), "kept");
+
+        fs::remove_dir_all("test_rm_tree_kept").unwrap();
+    }
+
+    #[test]
+    fn test_interrupted_removal() {
+        use crate::shell::Shell;
+        use crate::streams::Io;
+
+        fs::create_dir_all("test_rm_interrupted/sub").unwrap();
+        fs::write("test_rm_interrupted/sub/file.txt", "content").unwrap();
+        let mut shell = Shell::new();
+        let mut ctx = Context::new(Io::inherit(), &mut shell);
+
+        let status = signals::with_interrupt(|| Rm.run(&mut ctx, &["-r", "test_rm_interrupted"]));
+        assert_eq!(status, signals::INTERRUPT_STATUS);
+        assert!(Path::new("test_rm_interrupted/sub/file.txt").exists());
+
+        assert_eq!(Rm.run(&mut ctx, &["-r", "test_rm_interrupted"]), 0);
+        assert!(!Path::new("test_rm_interrupted").exists());
+    }
 }
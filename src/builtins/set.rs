@@ -0,0 +1,76 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::parser::quote;
+
+/// List shell variables or replace the positional parameters
+/// Usage: set [--] [arg ...]
+pub struct Set;
+
+impl Builtin for Set {
+    fn name(&self) -> &'static str {
+        "set"
+    }
+
+    fn usage(&self) -> &'static str {
+        "set [--] [arg ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "List all variables, or set the positional parameters $1, $2, ..."
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["--"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        if args.is_empty() {
+            for (name, var) in ctx.shell.variables() {
+                let _ = writeln!(ctx.io.stdout, "{}={}", name, quote(&var.value));
+            }
+            return 0;
+        }
+
+        let positional = match args[0] {
+            "--" => &args[1..],
+            option if option.len() > 1 && (option.starts_with('-') || option.starts_with('+')) => {
+                let _ = writeln!(ctx.io.stderr, "set: {}: invalid option", option);
+                let _ = writeln!(ctx.io.stderr, "set: usage: {}", self.usage());
+                return 2;
+            }
+            _ => args,
+        };
+
+        ctx.shell.set_positional(positional.iter().map(|arg| arg.to_string()).collect());
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_set_positional_parameters() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        shell.run_line("set -- a 'b c'; echo $# $2");
+        shell.run_line("set x; echo $# $1");
+        assert_eq!(out.to_string_lossy(), "2 b c\n1 x\n");
+        assert_eq!(shell.run_line("set -e 2>/dev/null"), 2);
+    }
+
+    #[test]
+    fn test_set_lists_variables() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        shell.run_line("ZERO_SHELL_SET='a b'; set | grep ZERO_SHELL_SET");
+        assert_eq!(out.to_string_lossy(), "ZERO_SHELL_SET='a b'\n");
+    }
+}
@@ -0,0 +1,149 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::external::DEFAULT_PATH;
+use crate::streams;
+
+/// Run the commands of a file in the current shell
+/// Usage: source <file> [args ...]
+pub struct Source;
+
+/// `.`, the POSIX name of `source`
+pub struct Dot;
+
+impl Builtin for Source {
+    fn name(&self) -> &'static str {
+        "source"
+    }
+
+    fn usage(&self) -> &'static str {
+        "source <file> [args ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Run the commands of a file in the current shell"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        source(self.name(), ctx, args)
+    }
+}
+
+impl Builtin for Dot {
+    fn name(&self) -> &'static str {
+        "."
+    }
+
+    fn usage(&self) -> &'static str {
+        ". <file> [args ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Run the commands of a file in the current shell, like source"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        source(self.name(), ctx, args)
+    }
+}
+
+fn source(name: &str, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+    let Some((file, rest)) = args.split_first() else {
+        let _ = writeln!(ctx.io.stderr, "{}: filename argument required", name);
+        return 2;
+    };
+    let path = find(ctx, file);
+
+    // Arguments replace $1, $2, ... while the file runs
+    let saved = (!rest.is_empty()).then(|| {
+        let saved = ctx.shell.positional().to_vec();
+        ctx.shell.set_positional(rest.iter().map(|arg| arg.to_string()).collect());
+        saved
+    });
+
+    // Commands in the file write where `source` itself was redirected to
+    ctx.shell.swap_io(&mut ctx.io);
+    let result = ctx.shell.source(&path);
+    ctx.shell.swap_io(&mut ctx.io);
+
+    if let Some(saved) = saved {
+        ctx.shell.set_positional(saved);
+    }
+
+    match result {
+        Ok(status) => status,
+        Err(e) => {
+            let _ = writeln!(ctx.io.stderr, "{}: {}: {}", name, file, streams::describe(&e));
+            1
+        }
+    }
+}
+
+/// A name without `/` is looked up in `$PATH` first, then in the working
+/// directory
+fn find(ctx: &Context, file: &str) -> PathBuf {
+    if !file.contains('/') {
+        let shell = &ctx.shell;
+        let found = shell
+            .var("PATH")
+            .unwrap_or(DEFAULT_PATH)
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| shell.resolve(dir).join(file))
+            .find(|path| path.is_file());
+        if let Some(path) = found {
+            return path;
+        }
+    }
+    ctx.shell.resolve(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+    use std::fs;
+
+    #[test]
+    fn test_source_runs_in_the_current_shell() {
+        fs::write("test_source.sh", "GREETING=hello\necho \"$GREETING $1\"\n").unwrap();
+        let mut shell = Shell::new();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_stderr(Output::Buffer(err.clone()));
+        shell.set_positional(vec!["outer".to_string()]);
+
+        assert_eq!(shell.run_line("source ./test_source.sh world"), 0);
+        assert_eq!(shell.var("GREETING"), Some("hello"));
+        assert_eq!(shell.positional(), ["outer".to_string()]);
+
+        assert_eq!(shell.run_line(". ./test_source.sh > test_source.out"), 0);
+        assert_eq!(fs::read_to_string("test_source.out").unwrap(), "hello outer\n");
+        assert_eq!(out.to_string_lossy(), "hello world\n");
+
+        assert_eq!(shell.run_line("source ./test_source_missing.sh"), 1);
+        assert_eq!(
+            err.to_string_lossy(),
+            "source: ./test_source_missing.sh: No such file or directory\n"
+        );
+
+        // `return` ends the file, not the shell
+        fs::write("test_source.sh", "[ -n \"$1\" ] && return 4\necho not returned\n").unwrap();
+        out.take();
+        assert_eq!(shell.run_line(". ./test_source.sh early; echo $?"), 0);
+        assert_eq!(out.to_string_lossy(), "4\n");
+
+        // The file sees the status from before it, and one without any
+        // commands succeeds
+        fs::write("test_source.sh", "echo \"status $?\"\n").unwrap();
+        out.take();
+        assert_eq!(shell.run_line("false; . ./test_source.sh"), 0);
+        assert_eq!(out.to_string_lossy(), "status 1\n");
+        fs::write("test_source.sh", "# nothing\n\n").unwrap();
+        assert_eq!(shell.run_line("false; . ./test_source.sh"), 0);
+
+        fs::remove_file("test_source.sh").unwrap();
+        fs::remove_file("test_source.out").unwrap();
+    }
+}
@@ -0,0 +1,61 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::external;
+use crate::parser::is_reserved;
+
+/// Describe how each name would be run as a command
+/// Usage: type name ...
+///
+/// Names are looked up in the order the shell uses: aliases, reserved
+/// words, functions, builtins, then `$PATH`.
+pub struct Type;
+
+impl Builtin for Type {
+    fn name(&self) -> &'static str {
+        "type"
+    }
+
+    fn usage(&self) -> &'static str {
+        "type <name> ..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Describe how each name would be interpreted as a command"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let mut status = 0;
+
+        for name in args {
+            if let Some(value) = ctx.shell.aliases().get(*name) {
+                let _ = writeln!(ctx.io.stdout, "{} is aliased to `{}'", name, value);
+                continue;
+            }
+            if is_reserved(name) {
+                let _ = writeln!(ctx.io.stdout, "{} is a shell keyword", name);
+                continue;
+            }
+            if let Some(body) = ctx.shell.functions().get(*name) {
+                let _ = writeln!(ctx.io.stdout, "{} is a function\n{}() {}", name, name, body);
+                continue;
+            }
+            if ctx.shell.registry().contains(name) {
+                let _ = writeln!(ctx.io.stdout, "{} is a shell builtin", name);
+                continue;
+            }
+
+            match external::find_executable(name, ctx.shell.var("PATH"), ctx.shell.cwd()) {
+                Ok(path) => {
+                    let _ = writeln!(ctx.io.stdout, "{} is {}", name, path.display());
+                }
+                Err(_) => {
+                    let _ = writeln!(ctx.io.stderr, "type: {}: not found", name);
+                    status = 1;
+                }
+            }
+        }
+
+        status
+    }
+}
@@ -0,0 +1,48 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+
+/// Remove aliases
+/// Usage: unalias -a | unalias name ...
+pub struct Unalias;
+
+impl Builtin for Unalias {
+    fn name(&self) -> &'static str {
+        "unalias"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unalias -a | unalias <name> ..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Remove the named aliases, or all of them with -a"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-a"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        match args {
+            [] => {
+                let _ = writeln!(ctx.io.stderr, "unalias: usage: {}", self.usage());
+                2
+            }
+            ["-a"] => {
+                ctx.shell.aliases_mut().clear();
+                0
+            }
+            names => {
+                let mut status = 0;
+                for name in names {
+                    if ctx.shell.aliases_mut().remove(*name).is_none() {
+                        let _ = writeln!(ctx.io.stderr, "unalias: {}: not found", name);
+                        status = 1;
+                    }
+                }
+                status
+            }
+        }
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::parser::is_name;
+
+/// Remove shell variables or functions
+/// Usage: unset [-v | -f] name ...
+pub struct Unset;
+
+impl Builtin for Unset {
+    fn name(&self) -> &'static str {
+        "unset"
+    }
+
+    fn usage(&self) -> &'static str {
+        "unset [-v | -f] <name> ..."
+    }
+
+    fn help(&self) -> &'static str {
+        "Remove variables from the shell and the environment, or functions with -f"
+    }
+
+    fn flags(&self) -> &'static [&'static str] {
+        &["-v", "-f"]
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        let (names, functions) = match args.first() {
+            Some(&"-v") => (&args[1..], false),
+            Some(&"-f") => (&args[1..], true),
+            _ => (args, false),
+        };
+
+        // Like bash, a function that does not exist is not an error
+        if functions {
+            for name in names {
+                ctx.shell.functions_mut().remove(*name);
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for name in names {
+            if !is_name(name) {
+                let _ = writeln!(ctx.io.stderr, "unset: `{}': not a valid identifier", name);
+                status = 1;
+                continue;
+            }
+            ctx.shell.unset_var(name);
+        }
+
+        status
+    }
+}
@@ -0,0 +1,107 @@
+use std::io::Write;
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::jobs::JobState;
+use crate::signals;
+
+/// Wait for background jobs to finish
+/// Usage: wait [%job | pid ...]
+pub struct Wait;
+
+impl Builtin for Wait {
+    fn name(&self) -> &'static str {
+        "wait"
+    }
+
+    fn usage(&self) -> &'static str {
+        "wait [%job | pid ...]"
+    }
+
+    fn help(&self) -> &'static str {
+        "Wait for background jobs and report their status"
+    }
+
+    fn run(&self, ctx: &mut Context, args: &[&str]) -> ExitStatus {
+        // Without operands, wait for every running job and succeed
+        if args.is_empty() {
+            let running: Vec<usize> = ctx
+                .shell
+                .jobs()
+                .iter()
+                .filter(|job| job.state() == JobState::Running)
+                .map(|job| job.id())
+                .collect();
+            for id in running {
+                if ctx.shell.jobs_mut().wait(id).is_none() {
+                    return signals::INTERRUPT_STATUS;
+                }
+            }
+            return 0;
+        }
+
+        let mut status = 0;
+        for arg in args {
+            let jobs = ctx.shell.jobs_mut();
+            let id = if arg.starts_with('%') {
+                jobs.find(Some(arg))
+            } else {
+                match arg.parse() {
+                    Ok(pid) => jobs
+                        .find_pid(pid)
+                        .ok_or_else(|| format!("pid {} is not a child of this shell", pid)),
+                    Err(_) => Err(format!("`{}': not a pid or valid job spec", arg)),
+                }
+            };
+
+            status = match id {
+                Ok(id) => match jobs.wait(id) {
+                    Some(status) => status,
+                    None => return signals::INTERRUPT_STATUS,
+                },
+                Err(message) => {
+                    let _ = writeln!(ctx.io.stderr, "wait: {}", message);
+                    127
+                }
+            };
+        }
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_wait_for_background_jobs() {
+        let mut shell = Shell::new();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_stderr(Output::Buffer(err.clone()));
+
+        // The job runs in a forked copy of the shell, builtins included,
+        // and what it writes still reaches the buffers
+        assert_eq!(shell.run_line("echo done && sh -c 'echo child; echo oops >&2; exit 3' &"), 0);
+        let pid = shell.jobs().last_background().unwrap();
+        let listed = Buffer::new();
+        shell.set_stdout(Output::Buffer(listed.clone()));
+        shell.run_line("jobs -p");
+        shell.set_stdout(Output::Buffer(out.clone()));
+        assert_eq!(listed.take(), format!("{}\n", pid).into_bytes());
+
+        assert_eq!(shell.run_line("wait %1"), 3);
+        assert_eq!(out.take(), b"done\nchild\n");
+        assert_eq!(err.take(), b"oops\n");
+        assert!(shell.jobs().iter().next().is_none());
+
+        // Streams sharing a buffer keep their order
+        shell.set_stderr(Output::Buffer(out.clone()));
+        shell.run_line("{ echo a; echo b >&2; echo c; } & wait");
+        assert_eq!(out.take(), b"a\nb\nc\n");
+
+        shell.run_line("sleep 0 & sleep 0 &");
+        assert_eq!(shell.run_line("wait"), 0);
+        assert_eq!(shell.run_line(&format!("wait {} 2>/dev/null", pid)), 127);
+    }
+}
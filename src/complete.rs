@@ -0,0 +1,315 @@
+//! Tab completion for the line editor
+//!
+//! What the word before the cursor completes to depends on where it is:
+//!
+//! - in command position: aliases, functions, builtin names and executables
+//!   on `$PATH`, or paths to executables and directories once it contains a `/`
+//! - starting with `-` after a builtin: the flags that builtin accepts
+//! - anywhere else: file and directory names; only directories after `cd`
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::editor::{Candidate, Completer, Completion};
+use crate::external::DEFAULT_PATH;
+use crate::parser::is_name;
+use crate::shell::Shell;
+
+/// Characters that end a word, besides whitespace
+const OPERATORS: &[char] = &['|', '&', ';', '<', '>', '(', ')'];
+
+/// Characters that must be escaped to appear in a word literally
+const SPECIAL: &[char] = &[
+    ' ', '\t', '\'', '"', '\\', '$', '`', '|', '&', ';', '<', '>', '(', ')', '*', '?', '[', '#', '!', '{', '}',
+];
+
+impl Completer for Shell {
+    fn complete(&self, line: &str) -> Completion {
+        let chars: Vec<char> = line.chars().collect();
+        let start = word_start(&chars);
+        let word = unquote(&chars[start..].iter().collect::<String>());
+
+        // The words of the current command that come before this one
+        let previous = command_words(&chars[..start]);
+        let command = previous.iter().find(|word| !is_assignment(word));
+
+        let candidates = match command {
+            None if word.contains('/') => self.complete_path(&word, PathKind::Executables),
+            None => self.complete_command(&word),
+            Some(command) if word.starts_with('-') => self.complete_flag(command, &word),
+            Some(command) if command == "cd" => self.complete_path(&word, PathKind::Directories),
+            Some(_) => self.complete_path(&word, PathKind::All),
+        };
+
+        Completion { start, candidates }
+    }
+}
+
+/// Which paths a path completion offers
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PathKind {
+    All,
+    Directories,
+    /// Directories, to descend into, and executable files
+    Executables,
+}
+
+impl Shell {
+    fn complete_command(&self, prefix: &str) -> Vec<Candidate> {
+        let mut names: BTreeSet<String> = self
+            .registry()
+            .iter()
+            .map(|builtin| builtin.name())
+            .filter(|name| name.starts_with(prefix))
+            .map(str::to_string)
+            .collect();
+        names.extend(self.aliases().keys().filter(|name| name.starts_with(prefix)).cloned());
+        names.extend(self.functions().keys().filter(|name| name.starts_with(prefix)).cloned());
+
+        for dir in self.var("PATH").unwrap_or(DEFAULT_PATH).split(':') {
+            let Ok(entries) = fs::read_dir(self.resolve(dir)) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with(prefix) && is_executable_file(&entry.path()) {
+                    names.insert(name);
+                }
+            }
+        }
+
+        names
+            .into_iter()
+            .map(|name| Candidate {
+                replacement: escape(&name),
+                display: name,
+            })
+            .collect()
+    }
+
+    fn complete_flag(&self, command: &str, prefix: &str) -> Vec<Candidate> {
+        let Some(builtin) = self.registry().get(command) else {
+            return Vec::new();
+        };
+        builtin
+            .flags()
+            .iter()
+            .filter(|flag| flag.starts_with(prefix))
+            .map(|flag| Candidate {
+                replacement: flag.to_string(),
+                display: flag.to_string(),
+            })
+            .collect()
+    }
+
+    fn complete_path(&self, word: &str, kind: PathKind) -> Vec<Candidate> {
+        // Split into the directory part, kept as typed, and the name prefix
+        let (dir, prefix) = match word.rfind('/') {
+            Some(index) => word.split_at(index + 1),
+            None => ("", word),
+        };
+
+        let search = match dir.strip_prefix('~') {
+            Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+                format!("{}{}", self.var("HOME").unwrap_or(""), rest)
+            }
+            _ => dir.to_string(),
+        };
+        let search = if search.is_empty() { self.cwd().to_path_buf() } else { self.resolve(&search) };
+
+        let Ok(entries) = fs::read_dir(search) else {
+            return Vec::new();
+        };
+
+        let mut candidates = Vec::new();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                continue;
+            }
+
+            // Follow symlinks, so a link to a directory counts as one
+            let is_dir = entry.path().is_dir();
+            let wanted = match kind {
+                PathKind::All => true,
+                PathKind::Directories => is_dir,
+                PathKind::Executables => is_dir || is_executable_file(&entry.path()),
+            };
+            if !wanted {
+                continue;
+            }
+
+            let suffix = if is_dir { "/" } else { "" };
+            candidates.push(Candidate {
+                replacement: format!("{}{}{}", escape(dir), escape(&name), suffix),
+                display: format!("{}{}", name, suffix),
+            });
+        }
+
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates
+    }
+}
+
+/// Find where the last word of a line begins
+fn word_start(chars: &[char]) -> usize {
+    let mut start = 0;
+    let mut quote = None;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\\' => i += 1,
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c.is_whitespace() || OPERATORS.contains(&c) => start = i + 1,
+            None => {}
+        }
+        i += 1;
+    }
+
+    start.min(chars.len())
+}
+
+/// The unquoted words of the command the cursor is in, ignoring earlier
+/// commands of a pipeline or list
+fn command_words(chars: &[char]) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut chars = chars.iter();
+
+    while let Some(&c) = chars.next() {
+        match c {
+            '\\' => current.extend(chars.next()),
+            '\'' | '"' => {
+                for &inner in chars.by_ref() {
+                    if inner == c {
+                        break;
+                    }
+                    current.push(inner);
+                }
+            }
+            c if OPERATORS.contains(&c) && c != '<' && c != '>' => {
+                words.clear();
+                current.clear();
+            }
+            c if c.is_whitespace() || c == '<' || c == '>' => {
+                if !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| is_name(name))
+}
+
+/// Remove quotes and backslashes from a partly typed word
+fn unquote(word: &str) -> String {
+    let mut result = String::new();
+    let mut quote = None;
+    let mut chars = word.chars();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => result.push(c),
+            None if c == '\\' => result.extend(chars.next()),
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None => result.push(c),
+        }
+    }
+    result
+}
+
+/// Backslash-escape the characters the shell would otherwise interpret
+fn escape(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if SPECIAL.contains(&c) {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+fn is_executable_file(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replacements(shell: &Shell, line: &str) -> Vec<String> {
+        shell
+            .complete(line)
+            .candidates
+            .into_iter()
+            .map(|candidate| candidate.replacement)
+            .collect()
+    }
+
+    #[test]
+    fn test_word_boundaries() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(word_start(&chars("ls -l sr")), 6);
+        assert_eq!(word_start(&chars("cat a|gr")), 6);
+        assert_eq!(word_start(&chars("cat my\\ fi")), 4);
+        assert_eq!(word_start(&chars("cat 'my fi")), 4);
+        assert_eq!(command_words(&chars("cd /tmp; FOO=1 ls -a ")), vec!["FOO=1", "ls", "-a"]);
+    }
+
+    #[test]
+    fn test_complete_commands() {
+        let mut shell = Shell::new();
+        shell.set_var("PATH", "/bin:/usr/bin");
+
+        let candidates = replacements(&shell, "ech");
+        assert!(candidates.contains(&"echo".to_string()));
+        assert!(replacements(&shell, "histo").contains(&"history".to_string()));
+        assert!(replacements(&shell, "ls | wc; s").contains(&"sh".to_string()));
+    }
+
+    #[test]
+    fn test_complete_builtin_flags() {
+        let shell = Shell::new();
+        assert_eq!(replacements(&shell, "ls -"), vec!["-a", "-l", "-F"]);
+        assert_eq!(replacements(&shell, "rm -"), vec!["-r"]);
+        assert!(replacements(&shell, "pwd -").is_empty());
+    }
+
+    #[test]
+    fn test_complete_paths() {
+        fs::create_dir_all("test_complete/sub dir").unwrap();
+        fs::write("test_complete/file.txt", "").unwrap();
+        fs::write("test_complete/.hidden", "").unwrap();
+        let shell = Shell::new();
+
+        assert_eq!(replacements(&shell, "cat test_complete/"), vec![
+            "test_complete/file.txt",
+            "test_complete/sub\\ dir/",
+        ]);
+        assert_eq!(replacements(&shell, "cd test_complete/"), vec!["test_complete/sub\\ dir/"]);
+        assert_eq!(replacements(&shell, "cat test_complete/.h"), vec!["test_complete/.hidden"]);
+        assert_eq!(replacements(&shell, "cat 'test_complete/su"), vec!["test_complete/sub\\ dir/"]);
+
+        let completion = shell.complete("ls test_comp");
+        assert_eq!(completion.start, 3);
+        assert_eq!(completion.candidates[0].display, "test_complete/");
+
+        fs::remove_dir_all("test_complete").unwrap();
+    }
+}
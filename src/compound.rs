@@ -0,0 +1,263 @@
+//! Running compound commands: `if`, `while`, `until`, `for`, `case`,
+//! `{ ...; }` groups and `( ... )` subshells
+//!
+//! Their bodies are lists run by the shell itself, so they share its
+//! variables and working directory, except for a subshell's, which runs
+//! in a copy of the shell. `break` and `continue` leave a
+//! [`LoopControl`] in the shell, which stops the lists on the way out
+//! until the loop it applies to picks it up.
+
+use crate::builtins::ExitStatus;
+use crate::expand::{expand_fields, expand_pattern, expand_word};
+use crate::glob;
+use crate::pipeline::expansion_failed;
+use crate::parser::{CaseArm, CompoundCommand, List};
+use crate::shell::Shell;
+
+/// A `break` or `continue` on its way out to the loop it applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LoopControl {
+    /// Leave the `n`th enclosing loop
+    Break(usize),
+    /// Go on with the next round of the `n`th enclosing loop
+    Continue(usize),
+}
+
+/// What a loop does after part of it ran
+#[derive(PartialEq, Eq)]
+enum Flow {
+    Proceed,
+    /// Start the next round: `continue`
+    NextRound,
+    /// Stop looping: `break`, a `break` or `continue` for an outer loop,
+    /// `exit` or Ctrl-C
+    Leave,
+}
+
+/// Run a compound command with the streams the shell currently has
+pub(crate) fn execute(shell: &mut Shell, command: &CompoundCommand) -> ExitStatus {
+    match command {
+        CompoundCommand::If { branches, otherwise } => execute_if(shell, branches, otherwise.as_ref()),
+        CompoundCommand::Loop { condition, body, until } => execute_loop(shell, condition, body, *until),
+        CompoundCommand::For { name, words, body } => execute_for(shell, name, words.as_deref(), body),
+        CompoundCommand::Case { word, arms } => execute_case(shell, word, arms),
+        CompoundCommand::Group(list) => shell.execute_list(list),
+        CompoundCommand::Subshell(list) => execute_subshell(shell, list),
+    }
+}
+
+/// Run a list in a copy of the shell, which takes every change it makes
+/// with it: variables, functions, aliases, the directory, and the
+/// `exit`, `return`, `break` or `continue` that ends it early
+fn execute_subshell(shell: &Shell, list: &List) -> ExitStatus {
+    let mut subshell = shell.subshell();
+    let status = subshell.execute_list(list);
+    subshell.exit_requested().unwrap_or(status)
+}
+
+/// The body of the first branch whose condition succeeds, or the `else`
+/// part. Without either, the status is 0.
+fn execute_if(shell: &mut Shell, branches: &[(List, List)], otherwise: Option<&List>) -> ExitStatus {
+    for (condition, body) in branches {
+        let status = shell.execute_list(condition);
+        if shell.stopping() {
+            return status;
+        }
+        if status == 0 {
+            return shell.execute_list(body);
+        }
+    }
+
+    match otherwise {
+        Some(body) => shell.execute_list(body),
+        None => 0,
+    }
+}
+
+/// Repeat the body while (or, for `until`, until) the condition succeeds
+/// The status is the last one of the body, or 0 if it never ran.
+fn execute_loop(shell: &mut Shell, condition: &List, body: &List, until: bool) -> ExitStatus {
+    let mut status = 0;
+    shell.enter_loop();
+
+    loop {
+        let tested = shell.execute_list(condition);
+        match flow(shell) {
+            Flow::Proceed => {}
+            Flow::NextRound => continue,
+            Flow::Leave => break,
+        }
+        if (tested == 0) == until {
+            break;
+        }
+
+        status = shell.execute_list(body);
+        if flow(shell) == Flow::Leave {
+            break;
+        }
+    }
+
+    shell.leave_loop();
+    status
+}
+
+/// Run the body once for each word, with the variable set to it
+/// Without `in`, the words are the positional parameters.
+fn execute_for(shell: &mut Shell, name: &str, words: Option<&[String]>, body: &List) -> ExitStatus {
+    let mut values = Vec::new();
+    match words {
+        Some(words) => {
+            for word in words {
+                match expand_fields(word, shell) {
+                    Ok(fields) => values.extend(fields),
+                    Err(error) => return expansion_failed(&mut shell.io().clone(), error),
+                }
+            }
+        }
+        None => values = shell.positional().to_vec(),
+    }
+
+    let mut status = 0;
+    shell.enter_loop();
+
+    for value in values {
+        shell.set_var(name, &value);
+        status = shell.execute_list(body);
+        if flow(shell) == Flow::Leave {
+            break;
+        }
+    }
+
+    shell.leave_loop();
+    status
+}
+
+/// Run the first arm with a pattern matching the word
+/// Quoted parts of a pattern match literally; the status is 0 when no
+/// arm matches.
+fn execute_case(shell: &mut Shell, word: &str, arms: &[CaseArm]) -> ExitStatus {
+    let subject = match expand_word(word, shell) {
+        Ok(subject) => subject,
+        Err(error) => return expansion_failed(&mut shell.io().clone(), error),
+    };
+
+    for arm in arms {
+        let mut matched = false;
+        for pattern in &arm.patterns {
+            match expand_pattern(pattern, shell) {
+                Ok(pattern) if glob::matches(&pattern, &subject) => {
+                    matched = true;
+                    break;
+                }
+                Ok(_) => {}
+                Err(error) => return expansion_failed(&mut shell.io().clone(), error),
+            }
+        }
+        if matched {
+            return if arm.body.items.is_empty() { 0 } else { shell.execute_list(&arm.body) };
+        }
+    }
+    0
+}
+
+/// Deal with a `break` or `continue` that reached this loop
+fn flow(shell: &mut Shell) -> Flow {
+    match shell.take_loop_control() {
+        Some(LoopControl::Break(1)) => Flow::Leave,
+        Some(LoopControl::Continue(1)) => Flow::NextRound,
+        // Meant for an outer loop: pass it on
+        Some(LoopControl::Break(n)) => {
+            shell.set_loop_control(LoopControl::Break(n - 1));
+            Flow::Leave
+        }
+        Some(LoopControl::Continue(n)) => {
+            shell.set_loop_control(LoopControl::Continue(n - 1));
+            Flow::Leave
+        }
+        None if shell.stopping() => Flow::Leave,
+        None => Flow::Proceed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    fn run(script: &str) -> (String, i32) {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        let status = shell.run_script(script);
+        (out.to_string_lossy(), status)
+    }
+
+    #[test]
+    fn test_if_elif_else() {
+        let script = "for n in 1 2 3; do\n  if [ $n = 1 ]; then echo one\n  elif [ $n = 2 ]\n  then\n    echo two\n  else echo many; fi\ndone\n";
+        assert_eq!(run(script), ("one\ntwo\nmany\n".to_string(), 0));
+        assert_eq!(run("if false; then echo no; fi").1, 0);
+        assert_eq!(run("if true; then sh -c 'exit 4'; fi").1, 4);
+    }
+
+    #[test]
+    fn test_while_and_until() {
+        let script = "i=\nwhile [ \"$i\" != xxx ]; do i=x$i; echo $i; done\nuntil true; do echo never; done";
+        assert_eq!(run(script), ("x\nxx\nxxx\n".to_string(), 0));
+    }
+
+    #[test]
+    fn test_break_and_continue() {
+        let script = "for a in 1 2 3; do\n  for b in x y z; do\n    [ $b = y ] && continue\n    [ $a = 2 ] && continue 2\n    [ $a = 3 ] && break 2\n    echo $a$b\n  done\n  echo end $a\ndone\necho after";
+        assert_eq!(run(script), ("1x\n1z\nend 1\nafter\n".to_string(), 0));
+
+        let (out, _) = run("while true; do echo once; break; echo never; done | tr a-z A-Z");
+        assert_eq!(out, "ONCE\n");
+    }
+
+    #[test]
+    fn test_for_over_globs_and_positional_parameters() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_positional(vec!["a b".to_string(), "c".to_string()]);
+
+        shell.run_line("for arg; do echo \"[$arg]\"; done; for f in Cargo.*; do echo $f; done > test_compound_for.txt");
+        assert_eq!(out.to_string_lossy(), "[a b]\n[c]\n");
+        assert_eq!(
+            std::fs::read_to_string("test_compound_for.txt").unwrap(),
+            "Cargo.lock\nCargo.toml\n"
+        );
+        std::fs::remove_file("test_compound_for.txt").unwrap();
+    }
+
+    #[test]
+    fn test_subshells_keep_their_changes() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        let cwd = shell.cwd().to_path_buf();
+
+        let script = "\
+x=outer
+(cd / && x=inner && f() { :; } && echo \"$PWD $x\"; exit 3)
+echo \"$? $x\"
+type f >/dev/null 2>&1 || echo no f
+for i in 1 2; do (break); echo $i; done
+(echo a; echo b) | wc -l";
+        assert_eq!(shell.run_script(script), 0);
+        assert_eq!(out.to_string_lossy(), "/ inner\n3 outer\nno f\n1\n2\n2\n");
+        assert_eq!(shell.cwd(), cwd);
+        assert_eq!(shell.exit_requested(), None);
+    }
+
+    #[test]
+    fn test_case() {
+        let script = "for f in main.rs notes.txt Makefile '*'; do\n  case $f in\n    *.rs|*.toml) echo \"$f: rust\" ;;\n    (*.txt) echo \"$f: text\"\n      ;;\n    '*') echo star;;\n    *) echo \"$f: other\"\n  esac\ndone";
+        assert_eq!(
+            run(script).0,
+            "main.rs: rust\nnotes.txt: text\nMakefile: other\nstar\n"
+        );
+        assert_eq!(run("case x in y) false;; esac").1, 0);
+    }
+}
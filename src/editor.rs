@@ -0,0 +1,811 @@
+//! Interactive line editing for the prompt
+//!
+//! The terminal is put into raw mode while a line is read, so every key
+//! press arrives immediately and the editor draws the line itself. Keys
+//! follow the emacs bindings of readline:
+//!
+//! | Key                    | Action                                   |
+//! |------------------------|------------------------------------------|
+//! | Ctrl-A / Home          | start of line                            |
+//! | Ctrl-E / End           | end of line                              |
+//! | Ctrl-B / Left          | back one character                       |
+//! | Ctrl-F / Right         | forward one character                    |
+//! | Alt-B / Ctrl-Left      | back one word                            |
+//! | Alt-F / Ctrl-Right     | forward one word                         |
+//! | Ctrl-P / Up            | previous history entry                   |
+//! | Ctrl-N / Down          | next history entry                       |
+//! | Backspace / Ctrl-H     | delete the character before the cursor   |
+//! | Ctrl-D / Delete        | delete the character under the cursor    |
+//! | Ctrl-K                 | kill to end of line                      |
+//! | Ctrl-U                 | kill to start of line                    |
+//! | Ctrl-W / Alt-Backspace | kill the word before the cursor          |
+//! | Alt-D                  | kill the word after the cursor           |
+//! | Ctrl-Y                 | yank (paste) the last killed text        |
+//! | Ctrl-T                 | transpose characters                     |
+//! | Ctrl-L                 | clear the screen                         |
+//! | Ctrl-C                 | abandon the line                         |
+//! | Ctrl-D on empty line   | end of input                             |
+//! | Tab                    | complete the word before the cursor      |
+//!
+//! When stdin is not a terminal the editor falls back to reading a plain
+//! line, so piping into an interactive session still works.
+
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+
+use crate::sys;
+
+/// The outcome of reading one line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadLine {
+    /// A line was entered (without its newline)
+    Line(String),
+    /// Ctrl-C was pressed; the line was thrown away
+    Interrupted,
+    /// End of input: Ctrl-D on an empty line, or stdin was closed
+    Eof,
+}
+
+/// A possible completion of the word before the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// The text that replaces the word, already quoted for the shell
+    pub replacement: String,
+    /// How the candidate is shown when several are listed
+    pub display: String,
+}
+
+/// The completions of the word that starts at char index `start`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+/// Supplies completions for the Tab key
+pub trait Completer {
+    /// Complete the last word of `line`, which is the text before the cursor
+    fn complete(&self, line: &str) -> Completion;
+}
+
+/// Reads lines from the terminal with editing and history recall
+#[derive(Default)]
+pub struct Editor {
+    /// The text removed by the last kill command, for Ctrl-Y
+    kill_buffer: String,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Editor::default()
+    }
+
+    /// Show `prompt` and read a line. `history` holds earlier lines,
+    /// oldest first, for Up and Down to step through, and `completer`
+    /// is asked for completions when Tab is pressed.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &[String],
+        completer: &dyn Completer,
+    ) -> io::Result<ReadLine> {
+        if !io::stdin().is_terminal() {
+            return read_plain(prompt);
+        }
+        let raw = match RawMode::enable() {
+            Ok(raw) => raw,
+            Err(_) => return read_plain(prompt),
+        };
+
+        let result = self.edit(prompt, history, completer);
+        drop(raw);
+        result
+    }
+
+    fn edit(&mut self, prompt: &str, history: &[String], completer: &dyn Completer) -> io::Result<ReadLine> {
+        // Read the descriptor directly: a buffered reader could swallow
+        // type-ahead meant for the next command
+        // SAFETY: fd 0 stays open; ManuallyDrop keeps us from closing it
+        let stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(sys::STDIN_FILENO) });
+        let mut input = &*stdin;
+
+        // Only the last line of a multi-line prompt is redrawn while
+        // editing; the ones above it are printed once
+        let prompt = match prompt.rsplit_once('\n') {
+            Some((above, last)) => {
+                write_terminal(&format!("{}\r\n", above.replace('\n', "\r\n")))?;
+                last
+            }
+            None => prompt,
+        };
+
+        let mut session = Session {
+            prompt,
+            line: Line::default(),
+            cursor_row: 0,
+            history,
+            history_index: history.len(),
+            draft: String::new(),
+        };
+        session.refresh()?;
+
+        loop {
+            let Some(key) = read_key(&mut input)? else {
+                return Ok(ReadLine::Eof);
+            };
+
+            let line = &mut session.line;
+            match key {
+                Key::Enter => {
+                    line.end();
+                    session.refresh()?;
+                    write_terminal("\r\n")?;
+                    return Ok(ReadLine::Line(session.line.text()));
+                }
+                Key::Ctrl('c') => {
+                    line.end();
+                    session.refresh()?;
+                    write_terminal("^C\r\n")?;
+                    return Ok(ReadLine::Interrupted);
+                }
+                Key::Ctrl('d') if line.is_empty() => {
+                    write_terminal("\r\n")?;
+                    return Ok(ReadLine::Eof);
+                }
+                Key::Char(c) => line.insert(c),
+                Key::Ctrl('a') | Key::Home => line.home(),
+                Key::Ctrl('e') | Key::End => line.end(),
+                Key::Ctrl('b') | Key::Left => line.left(),
+                Key::Ctrl('f') | Key::Right => line.right(),
+                Key::Alt('b') | Key::CtrlLeft => line.word_left(),
+                Key::Alt('f') | Key::CtrlRight => line.word_right(),
+                Key::Ctrl('h') | Key::Backspace => line.backspace(),
+                Key::Ctrl('d') | Key::Delete => line.delete(),
+                Key::Ctrl('k') => self.kill_buffer = line.kill_to_end(),
+                Key::Ctrl('u') => self.kill_buffer = line.kill_to_start(),
+                Key::Ctrl('w') | Key::AltBackspace => self.kill_buffer = line.kill_word_back(),
+                Key::Alt('d') => self.kill_buffer = line.kill_word_forward(),
+                Key::Ctrl('y') => {
+                    for c in self.kill_buffer.chars() {
+                        line.insert(c);
+                    }
+                }
+                Key::Ctrl('t') => line.transpose(),
+                Key::Ctrl('p') | Key::Up => session.history_previous(),
+                Key::Ctrl('n') | Key::Down => session.history_next(),
+                Key::Ctrl('l') => {
+                    write_terminal("\x1b[H\x1b[2J")?;
+                    session.cursor_row = 0;
+                }
+                Key::Tab => session.complete(completer)?,
+                _ => continue,
+            }
+            session.refresh()?;
+        }
+    }
+}
+
+/// The state of one `read_line` call
+struct Session<'a> {
+    prompt: &'a str,
+    line: Line,
+    /// The row of the wrapped line the terminal cursor is on, counted from
+    /// the row the prompt starts on
+    cursor_row: usize,
+    history: &'a [String],
+    /// The history entry shown; `history.len()` stands for the new line
+    history_index: usize,
+    /// The new line, kept while browsing history
+    draft: String,
+}
+
+impl Session<'_> {
+    fn history_previous(&mut self) {
+        if self.history_index == 0 {
+            return;
+        }
+        if self.history_index == self.history.len() {
+            self.draft = self.line.text();
+        }
+        self.history_index -= 1;
+        self.line.set(&self.history[self.history_index]);
+    }
+
+    fn history_next(&mut self) {
+        if self.history_index >= self.history.len() {
+            return;
+        }
+        self.history_index += 1;
+        match self.history.get(self.history_index) {
+            Some(entry) => self.line.set(entry),
+            None => self.line.set(&self.draft),
+        }
+    }
+
+    /// Complete the word before the cursor as far as all candidates
+    /// agree. A single candidate is finished off with a space (or nothing,
+    /// for a directory); when the word cannot grow, the candidates are
+    /// listed below the line.
+    fn complete(&mut self, completer: &dyn Completer) -> io::Result<()> {
+        let before: String = self.line.chars[..self.line.pos].iter().collect();
+        let completion = completer.complete(&before);
+        let candidates = &completion.candidates;
+
+        if candidates.is_empty() {
+            return write_terminal("\x07");
+        }
+
+        let word: String = self.line.chars[completion.start..self.line.pos].iter().collect();
+        let mut replacement = common_prefix(candidates.iter().map(|c| c.replacement.as_str()));
+        if let [only] = candidates.as_slice() {
+            if !only.replacement.ends_with('/') {
+                replacement.push(' ');
+            }
+        }
+
+        if replacement.chars().count() > word.chars().count() || candidates.len() == 1 {
+            self.line.chars.splice(completion.start..self.line.pos, replacement.chars());
+            self.line.pos = completion.start + replacement.chars().count();
+            return Ok(());
+        }
+
+        // Nothing to add: show the choices, then draw the line again below
+        let pos = self.line.pos;
+        self.line.end();
+        self.refresh()?;
+        let columns = sys::terminal_width(sys::STDOUT_FILENO).unwrap_or(80);
+        let displays: Vec<&str> = candidates.iter().map(|c| c.display.as_str()).collect();
+        write_terminal(&format!("\r\n{}", format_columns(&displays, columns)))?;
+        self.cursor_row = 0;
+        self.line.pos = pos;
+        Ok(())
+    }
+
+    /// Redraw the prompt and line and put the cursor where it belongs
+    ///
+    /// The terminal wraps long lines, so the drawing may span several
+    /// rows; the terminal width is asked for on every redraw so a resized
+    /// window is handled too.
+    fn refresh(&mut self) -> io::Result<()> {
+        let columns = sys::terminal_width(sys::STDOUT_FILENO).unwrap_or(80);
+        let prompt_width = display_width(self.prompt);
+        let layout = Layout::new(
+            prompt_width + self.line.chars.len(),
+            prompt_width + self.line.pos,
+            columns,
+        );
+
+        let mut out = String::new();
+        // Back to the first row of the prompt, then clear everything below
+        if self.cursor_row > 0 {
+            out.push_str(&format!("\x1b[{}A", self.cursor_row));
+        }
+        out.push_str("\r\x1b[J");
+        out.push_str(self.prompt);
+        out.extend(&self.line.chars);
+
+        // A line that exactly fills the last row leaves the cursor in the
+        // right margin; move it to the start of the next row explicitly
+        if layout.wraps_at_end {
+            out.push_str("\r\n");
+        }
+
+        let up = layout.end_row - layout.cursor_row;
+        if up > 0 {
+            out.push_str(&format!("\x1b[{}A", up));
+        }
+        out.push('\r');
+        if layout.cursor_column > 0 {
+            out.push_str(&format!("\x1b[{}C", layout.cursor_column));
+        }
+
+        self.cursor_row = layout.cursor_row;
+        write_terminal(&out)
+    }
+}
+
+/// Where the end of the text and the cursor land once the terminal has
+/// wrapped the prompt and line at `columns`
+#[derive(Debug, PartialEq, Eq)]
+struct Layout {
+    end_row: usize,
+    cursor_row: usize,
+    cursor_column: usize,
+    wraps_at_end: bool,
+}
+
+impl Layout {
+    /// `end` and `cursor` are display columns counted from the start of
+    /// the prompt
+    fn new(end: usize, cursor: usize, columns: usize) -> Self {
+        let columns = columns.max(1);
+        Layout {
+            end_row: end / columns,
+            cursor_row: cursor / columns,
+            cursor_column: cursor % columns,
+            wraps_at_end: end > 0 && end.is_multiple_of(columns),
+        }
+    }
+}
+
+/// The longest string every item starts with
+fn common_prefix<'a>(mut items: impl Iterator<Item = &'a str>) -> String {
+    let Some(first) = items.next() else {
+        return String::new();
+    };
+    let mut prefix: Vec<char> = first.chars().collect();
+
+    for item in items {
+        let shared = prefix.iter().zip(item.chars()).take_while(|(a, b)| **a == *b).count();
+        prefix.truncate(shared);
+    }
+    prefix.into_iter().collect()
+}
+
+/// Lay out items in columns that fill a terminal `width` columns wide,
+/// sorted down each column like `ls` does, one line per row
+fn format_columns(items: &[&str], width: usize) -> String {
+    let column_width = items.iter().map(|item| display_width(item)).max().unwrap_or(0) + 2;
+    let columns = (width / column_width).max(1);
+    let rows = items.len().div_ceil(columns);
+
+    let mut out = String::new();
+    for row in 0..rows {
+        let mut line = String::new();
+        for column in 0..columns {
+            if let Some(item) = items.get(column * rows + row) {
+                line.push_str(item);
+                line.extend(std::iter::repeat_n(' ', column_width - display_width(item)));
+            }
+        }
+        out.push_str(line.trim_end());
+        out.push_str("\r\n");
+    }
+    out
+}
+
+/// The number of terminal columns `text` takes up, not counting ANSI
+/// escape sequences such as colour codes or window titles
+pub fn display_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            match chars.next() {
+                // CSI sequences end with a byte in the range `@` to `~`
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // OSC sequences end with BEL or `ESC \`
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        } else if !c.is_control() {
+            width += 1;
+        }
+    }
+
+    width
+}
+
+/// The text being edited and the cursor position within it
+#[derive(Debug, Default)]
+struct Line {
+    chars: Vec<char>,
+    /// Index into `chars`; `chars.len()` is the end of the line
+    pos: usize,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Replace the text, with the cursor at the end
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.pos = self.chars.len();
+    }
+
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.pos, c);
+        self.pos += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.pos > 0 {
+            self.pos -= 1;
+            self.chars.remove(self.pos);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.pos < self.chars.len() {
+            self.chars.remove(self.pos);
+        }
+    }
+
+    fn left(&mut self) {
+        self.pos = self.pos.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        self.pos = (self.pos + 1).min(self.chars.len());
+    }
+
+    fn home(&mut self) {
+        self.pos = 0;
+    }
+
+    fn end(&mut self) {
+        self.pos = self.chars.len();
+    }
+
+    /// Move to the start of the current or previous word
+    fn word_left(&mut self) {
+        self.pos = self.word_start(self.pos, |c| c.is_alphanumeric());
+    }
+
+    /// Move past the end of the current or next word
+    fn word_right(&mut self) {
+        self.pos = self.word_end(self.pos);
+    }
+
+    fn kill_to_end(&mut self) -> String {
+        self.chars.drain(self.pos..).collect()
+    }
+
+    fn kill_to_start(&mut self) -> String {
+        let killed = self.chars.drain(..self.pos).collect();
+        self.pos = 0;
+        killed
+    }
+
+    /// Kill back to the previous whitespace, like readline's Ctrl-W
+    fn kill_word_back(&mut self) -> String {
+        let start = self.word_start(self.pos, |c| !c.is_whitespace());
+        let killed = self.chars.drain(start..self.pos).collect();
+        self.pos = start;
+        killed
+    }
+
+    fn kill_word_forward(&mut self) -> String {
+        let end = self.word_end(self.pos);
+        self.chars.drain(self.pos..end).collect()
+    }
+
+    /// Swap the characters around the cursor, or the last two at the end
+    fn transpose(&mut self) {
+        if self.chars.len() < 2 || self.pos == 0 {
+            return;
+        }
+        if self.pos == self.chars.len() {
+            self.pos -= 1;
+        }
+        self.chars.swap(self.pos - 1, self.pos);
+        self.pos += 1;
+    }
+
+    /// Skip backwards over non-word characters, then over word characters
+    fn word_start(&self, from: usize, is_word: impl Fn(char) -> bool) -> usize {
+        let mut pos = from;
+        while pos > 0 && !is_word(self.chars[pos - 1]) {
+            pos -= 1;
+        }
+        while pos > 0 && is_word(self.chars[pos - 1]) {
+            pos -= 1;
+        }
+        pos
+    }
+
+    fn word_end(&self, from: usize) -> usize {
+        let mut pos = from;
+        while pos < self.chars.len() && !self.chars[pos].is_alphanumeric() {
+            pos += 1;
+        }
+        while pos < self.chars.len() && self.chars[pos].is_alphanumeric() {
+            pos += 1;
+        }
+        pos
+    }
+}
+
+/// A decoded key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    /// A control character, named by its lowercase letter (Ctrl-A is `'a'`)
+    Ctrl(char),
+    /// A character typed with Alt (or after Escape)
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    AltBackspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    CtrlLeft,
+    CtrlRight,
+    /// An escape sequence or control code without a binding
+    Unknown,
+}
+
+/// Read one key press, decoding escape sequences and UTF-8
+/// Returns `None` at end of input
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => read_escape(input)?,
+        0x01..=0x1a => Key::Ctrl((b'a' + byte - 1) as char),
+        0x00..=0x1f => Key::Unknown,
+        _ => match read_utf8(input, byte)? {
+            Some(c) => Key::Char(c),
+            None => Key::Unknown,
+        },
+    };
+
+    Ok(Some(key))
+}
+
+/// Decode what follows an Escape byte
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    let key = match read_byte(input)? {
+        Some(b'[') => {
+            // CSI: parameter bytes, then a final byte in `@`..`~`
+            let mut params = String::new();
+            let final_byte = loop {
+                match read_byte(input)? {
+                    Some(b @ 0x40..=0x7e) => break b,
+                    Some(b) => params.push(b as char),
+                    None => return Ok(Key::Unknown),
+                }
+            };
+            match (params.as_str(), final_byte) {
+                ("", b'A') => Key::Up,
+                ("", b'B') => Key::Down,
+                ("", b'C') => Key::Right,
+                ("", b'D') => Key::Left,
+                ("", b'H') | ("1" | "7", b'~') => Key::Home,
+                ("", b'F') | ("4" | "8", b'~') => Key::End,
+                ("3", b'~') => Key::Delete,
+                ("1;5", b'C') => Key::CtrlRight,
+                ("1;5", b'D') => Key::CtrlLeft,
+                _ => Key::Unknown,
+            }
+        }
+        Some(b'O') => match read_byte(input)? {
+            Some(b'A') => Key::Up,
+            Some(b'B') => Key::Down,
+            Some(b'C') => Key::Right,
+            Some(b'D') => Key::Left,
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown,
+        },
+        Some(0x7f | 0x08) => Key::AltBackspace,
+        Some(b) if b.is_ascii_graphic() => Key::Alt(b.to_ascii_lowercase() as char),
+        _ => Key::Unknown,
+    };
+    Ok(key)
+}
+
+/// Complete a UTF-8 character whose first byte has been read already
+fn read_utf8(input: &mut impl Read, first: u8) -> io::Result<Option<char>> {
+    let length = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Ok(None),
+    };
+
+    let mut bytes = vec![first];
+    for _ in 1..length {
+        match read_byte(input)? {
+            Some(b) => bytes.push(b),
+            None => return Ok(None),
+        }
+    }
+    Ok(std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()))
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn write_terminal(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(text.as_bytes())?;
+    stdout.flush()
+}
+
+/// Read a line without editing, for when stdin is not a terminal
+fn read_plain(prompt: &str) -> io::Result<ReadLine> {
+    write_terminal(prompt)?;
+
+    let mut input = String::new();
+    if io::stdin().read_line(&mut input)? == 0 {
+        return Ok(ReadLine::Eof);
+    }
+    if input.ends_with('\n') {
+        input.pop();
+        if input.ends_with('\r') {
+            input.pop();
+        }
+    }
+    Ok(ReadLine::Line(input))
+}
+
+/// Keeps the terminal in raw mode and restores its settings when dropped
+struct RawMode {
+    original: sys::Termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let original = sys::get_termios(sys::STDIN_FILENO)?;
+
+        let mut raw = original;
+        // No line buffering, echo or signal keys; Ctrl-C, Ctrl-S and
+        // carriage returns all reach the editor as they are
+        raw.c_iflag &= !(sys::ICRNL | sys::IXON);
+        raw.c_lflag &= !(sys::ECHO | sys::ICANON | sys::ISIG | sys::IEXTEN);
+        raw.c_cc[sys::VMIN] = 1;
+        raw.c_cc[sys::VTIME] = 0;
+        sys::set_termios(sys::STDIN_FILENO, &raw)?;
+
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = sys::set_termios(sys::STDIN_FILENO, &self.original);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        let mut input = bytes;
+        let mut keys = Vec::new();
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    fn line(text: &str, pos: usize) -> Line {
+        Line {
+            chars: text.chars().collect(),
+            pos,
+        }
+    }
+
+    #[test]
+    fn test_decode_keys() {
+        assert_eq!(keys(b"a\r\x01\x7f"), vec![Key::Char('a'), Key::Enter, Key::Ctrl('a'), Key::Backspace]);
+        assert_eq!(keys(b"\x1b[A\x1b[D\x1bOH\x1b[3~"), vec![Key::Up, Key::Left, Key::Home, Key::Delete]);
+        assert_eq!(keys(b"\x1b[1;5C\x1bb\x1b\x7f"), vec![Key::CtrlRight, Key::Alt('b'), Key::AltBackspace]);
+        assert_eq!(keys("é€".as_bytes()), vec![Key::Char('é'), Key::Char('€')]);
+        assert_eq!(keys(b"\x1b[200~"), vec![Key::Unknown]);
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut l = line("echo hello", 4);
+        l.insert('!');
+        assert_eq!(l.text(), "echo! hello");
+        l.backspace();
+        l.delete();
+        assert_eq!((l.text().as_str(), l.pos), ("echohello", 4));
+
+        l.transpose();
+        assert_eq!(l.text(), "echhoello");
+        l.end();
+        l.transpose();
+        assert_eq!(l.text(), "echhoelol");
+    }
+
+    #[test]
+    fn test_word_movement() {
+        let mut l = line("ls  -la /tmp/dir", 16);
+        l.word_left();
+        assert_eq!(l.pos, 13);
+        l.word_left();
+        assert_eq!(l.pos, 9);
+        l.home();
+        l.word_right();
+        assert_eq!(l.pos, 2);
+        l.word_right();
+        assert_eq!(l.pos, 7);
+    }
+
+    #[test]
+    fn test_kills() {
+        let mut l = line("cat /var/log/app.txt", 20);
+        assert_eq!(l.kill_word_back(), "/var/log/app.txt");
+        assert_eq!(l.text(), "cat ");
+
+        let mut l = line("echo one two", 5);
+        assert_eq!(l.kill_to_end(), "one two");
+        assert_eq!(l.kill_to_start(), "echo ");
+        assert!(l.is_empty());
+
+        let mut l = line("rm -rf build", 2);
+        assert_eq!(l.kill_word_forward(), " -rf");
+        assert_eq!(l.text(), "rm build");
+    }
+
+    #[test]
+    fn test_layout_on_narrow_terminal() {
+        // "$ " plus 8 characters on a 10 column terminal fills one row
+        // exactly, so the cursor must be moved onto the next one
+        let layout = Layout::new(10, 10, 10);
+        assert_eq!(layout, Layout { end_row: 1, cursor_row: 1, cursor_column: 0, wraps_at_end: true });
+
+        let layout = Layout::new(25, 12, 10);
+        assert_eq!(layout, Layout { end_row: 2, cursor_row: 1, cursor_column: 2, wraps_at_end: false });
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix(["src/", "src/main.rs", "srv"].into_iter()), "sr");
+        assert_eq!(common_prefix(["only"].into_iter()), "only");
+        assert_eq!(common_prefix(std::iter::empty()), "");
+    }
+
+    #[test]
+    fn test_format_columns() {
+        let items = ["a", "bb", "ccc", "dddd", "e"];
+        // Six columns per item leave room for two columns, filled downwards
+        assert_eq!(format_columns(&items, 13), "a     dddd\r\nbb    e\r\nccc\r\n");
+        assert_eq!(format_columns(&items, 3), "a\r\nbb\r\nccc\r\ndddd\r\ne\r\n");
+    }
+
+    #[test]
+    fn test_display_width() {
+        assert_eq!(display_width("$ "), 2);
+        assert_eq!(display_width("\x1b[1;32muser\x1b[0m$ "), 6);
+        assert_eq!(display_width("\x1b]0;title\x07$ "), 2);
+        assert_eq!(display_width("\x1b]0;title\x1b\\$ "), 2);
+    }
+}
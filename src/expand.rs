@@ -0,0 +1,823 @@
+use std::fmt;
+use std::io::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::arith::{self, ArithError};
+use crate::brace;
+use crate::builtins::ExitStatus;
+use crate::glob;
+use crate::parser::{arithmetic_expression, is_name, scan_backquoted, scan_substitution, ParseError};
+use crate::shell::Shell;
+use crate::streams::{Buffer, Output};
+
+/// `$IFS` when it is not set: fields are separated by blanks and newlines
+const DEFAULT_IFS: &str = " \t\n";
+
+/// The file `~user` looks home directories up in
+const PASSWD_FILE: &str = "/etc/passwd";
+
+/// What a word is expanded for, which decides the expansions it gets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// A single word, such as a redirection target or a `case` pattern
+    Word,
+    /// A command word, which substitutions split into fields
+    Fields,
+    /// An assignment value, in which a `~` after a `:` expands too, as in
+    /// `PATH=~/bin:~/.local/bin`
+    Assignment,
+    /// An arithmetic expression, in which `~` is an operator
+    Arithmetic,
+}
+
+/// An expansion that has no value, which stops the command it is part of
+/// from running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpandError {
+    /// An arithmetic expansion whose expression, after its own expansions,
+    /// cannot be evaluated
+    Arithmetic { expression: String, error: ArithError },
+    /// A `${...}` holding something other than a parameter name, such as
+    /// the unsupported `${x:-default}`
+    BadSubstitution(String),
+    /// Command substitutions inside each other past the parser's limit
+    NestedTooDeep,
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpandError::Arithmetic { expression, error } => write!(f, "{}: {}", expression.trim(), error),
+            ExpandError::BadSubstitution(text) => write!(f, "{}: bad substitution", text),
+            ExpandError::NestedTooDeep => write!(f, "command substitutions nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for ExpandError {}
+
+/// One field of an expanded word
+///
+/// Besides the final text, a copy is kept as a glob pattern in which
+/// every quoted character is escaped, so that `"*"` and `\*` stay literal
+/// while an unquoted `*` - written out or coming from a variable - is a
+/// wildcard.
+#[derive(Default)]
+struct Field {
+    text: String,
+    pattern: String,
+    has_wildcards: bool,
+    /// Whether the field stays even when it is empty, which only one made
+    /// of unquoted substitutions and parameters does not
+    keep: bool,
+}
+
+impl Field {
+    /// Add a quoted character
+    fn push_quoted(&mut self, c: char) {
+        self.text.push(c);
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(c);
+    }
+
+    /// Add an unquoted character, which may act as a wildcard
+    fn push_unquoted(&mut self, c: char) {
+        match c {
+            '*' | '?' | '[' => {
+                self.text.push(c);
+                self.pattern.push(c);
+                self.has_wildcards = true;
+            }
+            ']' => {
+                self.text.push(c);
+                self.pattern.push(c);
+            }
+            c => self.push_quoted(c),
+        }
+    }
+}
+
+/// The result of expanding one word: the fields unquoted substitutions
+/// and parameters split it into, the last of which is still being added to
+#[derive(Default)]
+struct Expansion {
+    fields: Vec<Field>,
+    current: Field,
+    /// Whether substitutions split the word, which only happens for the
+    /// words of a command
+    split: bool,
+    /// The exit status of the last command substitution
+    status: Option<ExitStatus>,
+}
+
+impl Expansion {
+    fn push_quoted(&mut self, c: char) {
+        self.current.push_quoted(c);
+    }
+
+    fn push_unquoted(&mut self, c: char) {
+        self.current.push_unquoted(c);
+    }
+
+    /// Add the output of an unquoted command substitution, or the value of
+    /// an unquoted parameter, splitting it at `ifs` characters when the
+    /// word is split
+    ///
+    /// As in POSIX shells, a run of blanks from `ifs` separates fields and
+    /// is dropped at either end, while each other `ifs` character ends a
+    /// field, even an empty one.
+    fn push_substituted(&mut self, output: &str, ifs: &str) {
+        if !self.split {
+            output.chars().for_each(|c| self.push_unquoted(c));
+            return;
+        }
+
+        let mut blank = false;
+        for c in output.chars() {
+            if !ifs.contains(c) {
+                if blank {
+                    self.end_field();
+                    blank = false;
+                }
+                self.push_unquoted(c);
+            } else if c.is_whitespace() {
+                blank = true;
+            } else {
+                self.current.keep = true;
+                self.end_field();
+                blank = false;
+            }
+        }
+        if blank {
+            self.end_field();
+        }
+    }
+
+    fn end_field(&mut self) {
+        self.fields.push(std::mem::take(&mut self.current));
+    }
+
+    /// All the fields, leaving out the empty ones that are not kept
+    fn into_fields(mut self) -> Vec<Field> {
+        self.end_field();
+        self.fields.retain(|field| field.keep || !field.text.is_empty());
+        self.fields
+    }
+}
+
+/// Turn a word as written on the command line into its final text
+///
+/// Performs quote removal, tilde expansion (`~`, `~user`, `~+` for the
+/// current directory and `~-` for the previous one), expands variables (`$NAME`, `${NAME}`) and
+/// the special parameters `$?`, `$!`, `$#`, `$@`, `$*` and `$0`..`$9`, and
+/// replaces command substitutions, `$(...)` or `` `...` ``, by the output
+/// of their commands and arithmetic expansions, `$((...))`, by their
+/// value. Single quotes keep everything literal; inside double
+/// quotes a backslash only escapes `$`, `` ` ``, `"`, `\` and newline;
+/// elsewhere a backslash escapes any character and `\<newline>` disappears.
+pub fn expand_word(word: &str, shell: &mut Shell) -> Result<String, ExpandError> {
+    Ok(expand(word, shell, Mode::Word)?.current.text)
+}
+
+/// Expand the value of an assignment, like `expand_word`, also giving the
+/// status of the last command substitution in it, if there is one
+pub fn expand_assignment(value: &str, shell: &mut Shell) -> Result<(String, Option<ExitStatus>), ExpandError> {
+    let expansion = expand(value, shell, Mode::Assignment)?;
+    Ok((expansion.current.text, expansion.status))
+}
+
+/// Expand a command word into the arguments it stands for
+///
+/// Brace expansion comes first, turning `log.{1..3}` into three words.
+/// Each of them is expanded like `expand_word`, except that unquoted
+/// parameters and the output of unquoted command substitutions are split
+/// into fields at `$IFS` characters, each positional parameter of `$@` or
+/// `$*` on its own, and that `"$@"` gives one field per positional
+/// parameter. An unquoted expansion that is empty gives no field. Then
+/// comes pathname expansion: a field with unquoted wildcards is replaced
+/// by the sorted paths it matches, or kept as it is when nothing matches.
+pub fn expand_fields(word: &str, shell: &mut Shell) -> Result<Vec<String>, ExpandError> {
+    let mut fields = Vec::new();
+
+    for word in brace::expand(word) {
+        for field in expand(&word, shell, Mode::Fields)?.into_fields() {
+            if field.has_wildcards {
+                let paths = glob::glob(&field.pattern, shell.cwd());
+                if !paths.is_empty() {
+                    fields.extend(paths);
+                    continue;
+                }
+            }
+            fields.push(field.text);
+        }
+    }
+    Ok(fields)
+}
+
+/// Expand a word into a pattern, as for `case`: quoted characters are
+/// escaped so they only match themselves, while unquoted `*`, `?` and
+/// `[...]` stay wildcards
+pub fn expand_pattern(word: &str, shell: &mut Shell) -> Result<String, ExpandError> {
+    Ok(expand(word, shell, Mode::Word)?.current.pattern)
+}
+
+/// Expand the text of a here-document whose delimiter was not quoted:
+/// parameters, command substitutions and arithmetic are expanded as
+/// inside double quotes, while quotes themselves stay as they are
+pub fn expand_here_doc(text: &str, shell: &mut Shell) -> Result<String, ExpandError> {
+    let mut result = Expansion::default();
+    expand_double_quoted(&mut text.chars().peekable(), false, shell, &mut result)?;
+    Ok(result.current.text)
+}
+
+fn expand(word: &str, shell: &mut Shell, mode: Mode) -> Result<Expansion, ExpandError> {
+    let mut result = Expansion {
+        split: mode == Mode::Fields,
+        ..Expansion::default()
+    };
+    let mut chars = word.chars().peekable();
+    // Whether a `~` here starts a tilde prefix
+    let mut tilde = mode != Mode::Arithmetic;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '~' if tilde => expand_tilde(&mut chars, shell, mode, &mut result),
+            '\'' => {
+                result.current.keep = true;
+                for c in chars.by_ref() {
+                    if c == '\'' {
+                        break;
+                    }
+                    result.push_quoted(c);
+                }
+            }
+            '"' => {
+                // `"$@"` stands for no field at all without positional parameters
+                if !(result.split && shell.positional().is_empty() && skip_quoted_at(&mut chars)) {
+                    result.current.keep = true;
+                    expand_double_quoted(&mut chars, true, shell, &mut result)?;
+                }
+            }
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped) => result.push_quoted(escaped),
+                None => result.push_quoted('\\'),
+            },
+            '$' if chars.next_if_eq(&'(').is_some() => {
+                substitute(&mut chars, false, false, shell, &mut result)?;
+            }
+            '`' => substitute(&mut chars, true, false, shell, &mut result)?,
+            '$' => match parameter_name(&mut chars)? {
+                Some(name) if result.split && (name == "@" || name == "*") => {
+                    // Each positional parameter is split on its own
+                    let ifs = ifs(shell);
+                    for (i, parameter) in shell.positional().iter().enumerate() {
+                        if i > 0 {
+                            result.end_field();
+                        }
+                        result.push_substituted(parameter, &ifs);
+                    }
+                }
+                Some(name) => result.push_substituted(&parameter_value(&name, shell), &ifs(shell)),
+                None => result.push_unquoted('$'),
+            },
+            c => result.push_unquoted(c),
+        }
+        tilde = ch == ':' && mode == Mode::Assignment;
+    }
+
+    Ok(result)
+}
+
+/// Replace the tilde prefix whose `~` was just read, up to the next `/`
+/// (or `:` in an assignment), by the directory it names: `$HOME` for a
+/// bare `~`, the user's home directory for `~user`, and the current or
+/// previous directory for `~+` and `~-`
+///
+/// A prefix that is quoted or names nothing known leaves the `~` as it is.
+fn expand_tilde(chars: &mut Peekable<Chars>, shell: &Shell, mode: Mode, result: &mut Expansion) {
+    let mut lookahead = chars.clone();
+    let mut prefix = String::new();
+    while let Some(c) = lookahead.next_if(|&c| c != '/' && !(c == ':' && mode == Mode::Assignment)) {
+        prefix.push(c);
+    }
+
+    let directory = match prefix.as_str() {
+        "" => shell.var("HOME").map(str::to_string),
+        "+" => Some(shell.cwd().to_string_lossy().into_owned()),
+        "-" => shell.var("OLDPWD").map(str::to_string),
+        user if is_user_name(user) => home_directory(user),
+        _ => None,
+    };
+
+    match directory {
+        Some(directory) => {
+            *chars = lookahead;
+            result.current.keep = true;
+            directory.chars().for_each(|c| result.push_quoted(c));
+        }
+        None => result.push_unquoted('~'),
+    }
+}
+
+/// Whether `name` can be a user name, which keeps `~"x"` or `~$USER`
+/// from being looked up
+fn is_user_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c))
+}
+
+/// The home directory of a user, from the password file
+fn home_directory(user: &str) -> Option<String> {
+    let passwd = std::fs::read_to_string(PASSWD_FILE).ok()?;
+    passwd.lines().find_map(|line| {
+        // name:password:uid:gid:comment:home:shell
+        let fields: Vec<&str> = line.split(':').collect();
+        (fields.len() >= 6 && fields[0] == user).then(|| fields[5].to_string())
+    })
+}
+
+/// Expand the rest of a double-quoted string, whose opening `"` was just
+/// read, up to the closing one; or, when `closed` is false, the whole text
+/// of a here-document, in which `"` is an ordinary character
+///
+/// A backslash only escapes `$`, `` ` ``, `\` and newline, and `"` when it
+/// can close the string.
+fn expand_double_quoted(
+    chars: &mut Peekable<Chars>,
+    closed: bool,
+    shell: &mut Shell,
+    result: &mut Expansion,
+) -> Result<(), ExpandError> {
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if closed => break,
+            '\\' => match chars.next() {
+                Some('\n') => {}
+                Some(escaped @ ('$' | '`' | '\\')) => result.push_quoted(escaped),
+                Some('"') if closed => result.push_quoted('"'),
+                Some(other) => {
+                    result.push_quoted('\\');
+                    result.push_quoted(other);
+                }
+                None => result.push_quoted('\\'),
+            },
+            '$' if chars.next_if_eq(&'(').is_some() => substitute(chars, false, true, shell, result)?,
+            '`' => substitute(chars, true, true, shell, result)?,
+            '$' => match parameter_name(chars)? {
+                Some(name) if result.split && name == "@" => {
+                    // One field for each positional parameter, the first
+                    // and last joined to what comes before and after
+                    for (i, parameter) in shell.positional().iter().enumerate() {
+                        if i > 0 {
+                            result.end_field();
+                            result.current.keep = true;
+                        }
+                        parameter.chars().for_each(|c| result.push_quoted(c));
+                    }
+                }
+                Some(name) => parameter_value(&name, shell).chars().for_each(|c| result.push_quoted(c)),
+                None => result.push_quoted('$'),
+            },
+            c => result.push_quoted(c),
+        }
+    }
+    Ok(())
+}
+
+/// Run the command substitution whose `$(` or opening backquote was just
+/// read, or evaluate the arithmetic expansion starting with `$((`, and add
+/// the result to `result`
+///
+/// One that is never closed, which the tokenizer does not let through,
+/// is taken as it is written; one with too many others nested inside it,
+/// which can still come from a here-document or a prompt, is an error.
+fn substitute(
+    chars: &mut Peekable<Chars>,
+    backquoted: bool,
+    quoted: bool,
+    shell: &mut Shell,
+    result: &mut Expansion,
+) -> Result<(), ExpandError> {
+    let mut command = String::new();
+    let scanned = if backquoted {
+        scan_backquoted(chars, &mut command)
+    } else {
+        scan_substitution(chars, &mut command, 0)
+    };
+    match scanned {
+        Ok(()) => {}
+        Err(ParseError::NestedTooDeep) => return Err(ExpandError::NestedTooDeep),
+        Err(_) => {
+            let opening = if backquoted { "`" } else { "$(" };
+            opening.chars().chain(command.chars()).for_each(|c| result.push_quoted(c));
+            return Ok(());
+        }
+    }
+
+    // Without the closing `)` or backquote
+    command.pop();
+    if backquoted {
+        command = unescape_backquoted(&command);
+    }
+
+    let output = match arithmetic_expression(&command) {
+        Some(expression) if !backquoted => {
+            // Each one inside the expression takes more stack to expand
+            if shell.arithmetic_depth() >= arith::MAX_NESTING_DEPTH {
+                let expression = expression.to_string();
+                return Err(ExpandError::Arithmetic { expression, error: ArithError::NestedTooDeep });
+            }
+            // The expression itself goes through parameter expansion,
+            // command substitution and quote removal first
+            shell.enter_arithmetic();
+            let expanded = expand(expression, shell, Mode::Arithmetic);
+            shell.leave_arithmetic();
+            let expression = expanded?.current.text;
+            match arith::evaluate(&expression, shell) {
+                Ok(value) => value.to_string(),
+                Err(error) => return Err(ExpandError::Arithmetic { expression, error }),
+            }
+        }
+        _ => {
+            let (output, status) = run_substitution(&command, shell);
+            result.status = Some(status);
+            output
+        }
+    };
+
+    if quoted {
+        output.chars().for_each(|c| result.push_quoted(c));
+    } else {
+        result.push_substituted(&output, &ifs(shell));
+    }
+    Ok(())
+}
+
+/// Inside backquotes a backslash only escapes `$`, `` ` `` and `\`, so
+/// backquotes can nest as `` `echo \`date\`` ``
+fn unescape_backquoted(command: &str) -> String {
+    let mut result = String::new();
+    let mut chars = command.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next_if(|&c| matches!(c, '$' | '`' | '\\')) {
+                Some(escaped) => result.push(escaped),
+                None => result.push('\\'),
+            },
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Run commands in a subshell and capture what they write to stdout,
+/// without the trailing newlines, along with their exit status
+///
+/// The subshell is a copy of the shell, so the commands see its variables
+/// and functions, while `cd`, assignments and `exit` in there stay there.
+fn run_substitution(command: &str, shell: &Shell) -> (String, ExitStatus) {
+    let mut subshell = shell.subshell();
+    let out = Buffer::new();
+    subshell.set_stdout(Output::Buffer(out.clone()));
+
+    let status = match subshell.parse(command) {
+        Ok(list) => subshell.execute_list(&list),
+        Err(error) => {
+            let _ = writeln!(subshell.io().stderr.clone(), "0-shell: {}", error);
+            2
+        }
+    };
+    // Jobs started with `&` may still be writing, and the output is
+    // complete only once they are done with it
+    subshell.jobs().finish_output();
+
+    let mut output = String::from_utf8_lossy(&out.take()).into_owned();
+    output.truncate(output.trim_end_matches('\n').len());
+    (output, status)
+}
+
+/// Read the name of the parameter following a `$`, or `None` when no
+/// parameter name follows it and the `$` is taken literally
+///
+/// Accepts `$NAME`, `${NAME}`, the special parameters and, in braces,
+/// multi-digit positional parameters such as `${10}`. Braces around
+/// anything else are a bad substitution.
+fn parameter_name(chars: &mut Peekable<Chars>) -> Result<Option<String>, ExpandError> {
+    let name = match chars.peek() {
+        Some('{') => {
+            let mut lookahead = chars.clone();
+            lookahead.next();
+            let mut name = String::new();
+            loop {
+                match lookahead.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    // No closing brace: not a parameter after all
+                    None => return Ok(None),
+                }
+            }
+            *chars = lookahead;
+            let special = name.len() == 1 && "?!#@*".contains(name.as_str());
+            let positional = !name.is_empty() && name.chars().all(|c| c.is_ascii_digit());
+            if !(special || positional || is_name(&name)) {
+                return Err(ExpandError::BadSubstitution(format!("${{{}}}", name)));
+            }
+            name
+        }
+        Some(&c) if c == '_' || c.is_ascii_alphabetic() => {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+                name.push(c);
+            }
+            name
+        }
+        Some(&c @ ('?' | '!' | '#' | '@' | '*' | '0'..='9')) => {
+            chars.next();
+            c.to_string()
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(name))
+}
+
+/// Skip the rest of a double-quoted string, whose opening `"` was just
+/// read, if it is `"$@"` alone
+fn skip_quoted_at(chars: &mut Peekable<Chars>) -> bool {
+    let mut lookahead = chars.clone();
+    let quoted_at = lookahead.next() == Some('$')
+        && matches!(parameter_name(&mut lookahead), Ok(Some(name)) if name == "@")
+        && lookahead.next() == Some('"');
+    if quoted_at {
+        *chars = lookahead;
+    }
+    quoted_at
+}
+
+/// The characters fields are split at
+fn ifs(shell: &Shell) -> String {
+    shell.var("IFS").unwrap_or(DEFAULT_IFS).to_string()
+}
+
+/// The value of a named, positional or special parameter
+fn parameter_value(name: &str, shell: &Shell) -> String {
+    match name {
+        "?" => shell.last_status().to_string(),
+        "!" => shell.jobs().last_background().map(|pid| pid.to_string()).unwrap_or_default(),
+        "#" => shell.positional().len().to_string(),
+        "@" => shell.positional().join(" "),
+        // Joined with the first character of `$IFS`
+        "*" => {
+            shell.positional().join(&ifs(shell).chars().next().map(String::from).unwrap_or_default())
+        }
+        "0" => shell.script_name().to_string(),
+        _ => match name.parse::<usize>() {
+            Ok(index) => shell
+                .positional()
+                .get(index.wrapping_sub(1))
+                .cloned()
+                .unwrap_or_default(),
+            Err(_) => shell.var(name).unwrap_or_default().to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_quote_removal() {
+        let mut shell = Shell::new();
+        assert_eq!(expand_word("'my file.txt'", &mut shell).unwrap(), "my file.txt");
+        assert_eq!(expand_word(r#""a \"b\" \\ \n""#, &mut shell).unwrap(), r#"a "b" \ \n"#);
+        assert_eq!(expand_word(r"a\ b\\c", &mut shell).unwrap(), r"a b\c");
+        assert_eq!(expand_word(r#"x""''y"#, &mut shell).unwrap(), "xy");
+    }
+
+    #[test]
+    fn test_last_status_expansion() {
+        let mut shell = Shell::new();
+        shell.run_line("no_such_command_0shell 2>/dev/null");
+        assert_eq!(expand_word("$?", &mut shell).unwrap(), "127");
+
+        shell.run_line("false");
+        assert_eq!(expand_word("\"status=$?\"", &mut shell).unwrap(), "status=1");
+        assert_eq!(expand_word("'$?'", &mut shell).unwrap(), "$?");
+        assert_eq!(expand_word(r"\$?", &mut shell).unwrap(), "$?");
+        assert_eq!(expand_word("$", &mut shell).unwrap(), "$");
+    }
+
+    #[test]
+    fn test_positional_parameters() {
+        let mut shell = Shell::new();
+        shell.set_script_name("deploy.sh");
+        shell.set_positional(vec!["one".to_string(), "two words".to_string()]);
+
+        assert_eq!(expand_word("$0", &mut shell).unwrap(), "deploy.sh");
+        assert_eq!(expand_word("\"$1-$2\"", &mut shell).unwrap(), "one-two words");
+        assert_eq!(expand_word("$3", &mut shell).unwrap(), "");
+        assert_eq!(expand_word("$#", &mut shell).unwrap(), "2");
+        assert_eq!(expand_word("\"$@\"", &mut shell).unwrap(), "one two words");
+        assert_eq!(expand_word("$10", &mut shell).unwrap(), "one0");
+        assert_eq!(expand_word("'$1'", &mut shell).unwrap(), "$1");
+        assert_eq!(expand_word("${2}", &mut shell).unwrap(), "two words");
+    }
+
+    #[test]
+    fn test_positional_parameter_fields() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_positional(vec!["a b".to_string(), "c".to_string()]);
+        let fields = |word: &str, shell: &mut Shell| expand_fields(word, shell).unwrap();
+
+        assert_eq!(fields("\"$@\"", &mut shell), vec!["a b", "c"]);
+        assert_eq!(fields("\"<$@>\"", &mut shell), vec!["<a b", "c>"]);
+        assert_eq!(fields("$@", &mut shell), vec!["a", "b", "c"]);
+        assert_eq!(fields("$*", &mut shell), vec!["a", "b", "c"]);
+        assert_eq!(fields("\"$*\"", &mut shell), vec!["a b c"]);
+
+        let script = "\
+count() { echo $#; printf '[%s]' \"$@\"; echo; }
+count \"$@\"
+sh -c 'printf \"[%s]\" \"$@\"; echo' sh \"$@\"
+for arg in \"$@\"; do echo \"<$arg>\"; done
+set --
+count \"$@\"";
+        shell.run_script(script);
+        assert_eq!(out.to_string_lossy(), "2\n[a b][c]\n[a b][c]\n<a b>\n<c>\n0\n[]\n");
+    }
+
+    #[test]
+    fn test_variable_expansion() {
+        let mut shell = Shell::new();
+        shell.set_var("NAME", "world");
+        shell.unset_var("ZERO_SHELL_UNSET");
+
+        assert_eq!(expand_word("hello $NAME!", &mut shell).unwrap(), "hello world!");
+        assert_eq!(expand_word("\"$NAME\"s", &mut shell).unwrap(), "worlds");
+        assert_eq!(expand_word("$NAMEs", &mut shell).unwrap(), "");
+        assert_eq!(expand_word("${NAME}s", &mut shell).unwrap(), "worlds");
+        assert_eq!(expand_word("[$ZERO_SHELL_UNSET]", &mut shell).unwrap(), "[]");
+        assert_eq!(expand_word("'$NAME' \\$NAME", &mut shell).unwrap(), "$NAME $NAME");
+        assert_eq!(expand_word("${NAME", &mut shell).unwrap(), "${NAME");
+        assert_eq!(expand_word("$-x $ 5$", &mut shell).unwrap(), "$-x $ 5$");
+
+        for word in ["${NAME:-default}", "\"${#NAME}\"", "${}"] {
+            let error = expand_word(word, &mut shell).unwrap_err();
+            assert_eq!(error, ExpandError::BadSubstitution(word.trim_matches('"').to_string()));
+        }
+        assert_eq!(expand_word("'${NAME:-default}'", &mut shell).unwrap(), "${NAME:-default}");
+    }
+
+    #[test]
+    fn test_field_splitting_of_parameters() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        let fields = |word: &str, shell: &mut Shell| expand_fields(word, shell).unwrap();
+
+        shell.set_var("OPTS", "-l  -a ");
+        shell.set_var("EMPTY", "");
+        assert_eq!(fields("$OPTS", &mut shell), vec!["-l", "-a"]);
+        assert_eq!(fields("x${OPTS}y", &mut shell), vec!["x-l", "-a", "y"]);
+        assert_eq!(fields("\"$OPTS\"", &mut shell), vec!["-l  -a "]);
+        assert_eq!(fields("$EMPTY", &mut shell), Vec::<String>::new());
+        assert_eq!(fields("$EMPTY$ZERO_SHELL_UNSET", &mut shell), Vec::<String>::new());
+        assert_eq!(fields("\"$EMPTY\"", &mut shell), vec![""]);
+        assert_eq!(expand_assignment("$OPTS", &mut shell).unwrap().0, "-l  -a ");
+
+        let script = "\
+list='a b c'
+for x in $list; do echo $x; done
+x=
+set -- $x
+echo $#
+IFS=:
+path=/bin::/usr/bin
+printf '[%s]' $path";
+        shell.run_script(script);
+        assert_eq!(out.to_string_lossy(), "a\nb\nc\n0\n[/bin][][/usr/bin]");
+    }
+
+    #[test]
+    fn test_tilde_expansion() {
+        let mut shell = Shell::new();
+        shell.set_var("HOME", "/home/pi");
+        shell.set_cwd("/tmp");
+        shell.set_cwd("/");
+
+        assert_eq!(expand_fields("~", &mut shell).unwrap(), vec!["/home/pi"]);
+        assert_eq!(expand_fields("~/a.conf", &mut shell).unwrap(), vec!["/home/pi/a.conf"]);
+        assert_eq!(expand_fields("~root/x", &mut shell).unwrap(), vec!["/root/x"]);
+        assert_eq!(expand_word("~+", &mut shell).unwrap(), "/");
+        assert_eq!(expand_word("~-/log", &mut shell).unwrap(), "/tmp/log");
+        assert_eq!(expand_fields("~/{a,b}", &mut shell).unwrap(), vec!["/home/pi/a", "/home/pi/b"]);
+
+        // Only an unquoted `~` at the start, naming something known
+        assert_eq!(expand_word("'~' \\~ \"~/\" a~", &mut shell).unwrap(), "~ ~ ~/ a~");
+        assert_eq!(expand_word("~zero_shell_nobody", &mut shell).unwrap(), "~zero_shell_nobody");
+        assert_eq!(expand_word("~\"root\"", &mut shell).unwrap(), "~root");
+        assert_eq!(expand_word("x=~", &mut shell).unwrap(), "x=~");
+        assert_eq!(
+            expand_assignment("~/bin:~-:a~", &mut shell).unwrap(),
+            ("/home/pi/bin:/tmp:a~".to_string(), None)
+        );
+
+        shell.set_var("n", "5");
+        assert_eq!(expand_word("$((~n))", &mut shell).unwrap(), "-6");
+    }
+
+    #[test]
+    fn test_command_substitution() {
+        let mut shell = Shell::new();
+        shell.set_var("NAME", "world");
+        shell.run_line("greet() { echo \"hello $1\"; echo; }");
+
+        assert_eq!(expand_word("$(greet $NAME)!", &mut shell).unwrap(), "hello world!");
+        assert_eq!(expand_word("\"[$(echo \")\"; echo x)]\"", &mut shell).unwrap(), "[)\nx]");
+        assert_eq!(expand_word("$(echo $(echo nested))", &mut shell).unwrap(), "nested");
+        assert_eq!(expand_word("$(case x in x) echo y;; esac)", &mut shell).unwrap(), "y");
+        assert_eq!(expand_word("$(echo a # it's a ) comment\n)", &mut shell).unwrap(), "a");
+        assert_eq!(expand_word("`echo \\`echo back\\``", &mut shell).unwrap(), "back");
+        assert_eq!(expand_word("[$(echo bg & wait)$(echo left &)]", &mut shell).unwrap(), "[bgleft]");
+        assert_eq!(expand_word("'$(echo no)' \\$(echo no)", &mut shell).unwrap(), "$(echo no) $(echo no)");
+
+        // Changes made inside stay inside
+        assert_eq!(expand_word("$(NAME=inner; cd /; echo $NAME)", &mut shell).unwrap(), "inner");
+        assert_eq!(shell.var("NAME"), Some("world"));
+
+        assert_eq!(expand_assignment("$(exit 3)", &mut shell).unwrap(), (String::new(), Some(3)));
+        assert_eq!(expand_assignment("$NAME", &mut shell).unwrap(), ("world".to_string(), None));
+    }
+
+    #[test]
+    fn test_arithmetic_expansion() {
+        let mut shell = Shell::new();
+        shell.set_var("size", "4096");
+        shell.set_var("n", "3");
+
+        assert_eq!(expand_word("$(( size / 1024 ))K", &mut shell).unwrap(), "4K");
+        assert_eq!(expand_word("\"$((n * (n + 1) / 2))\"", &mut shell).unwrap(), "6");
+        assert_eq!(expand_word("$(( $n << $(echo 2) ))", &mut shell).unwrap(), "12");
+        assert_eq!(expand_word("$((n += $((0x10))))-$n", &mut shell).unwrap(), "19-19");
+        assert_eq!(expand_word("$(( n > 100 ? 1 : -1 ))", &mut shell).unwrap(), "-1");
+        // Parentheses that close early make a command substitution
+        assert_eq!(expand_word("$((echo a); (echo b))", &mut shell).unwrap(), "a\nb");
+
+        let error = expand_word("x$(( 10 % (n - n) ))", &mut shell).unwrap_err();
+        assert_eq!(error.to_string(), "10 % (n - n): division by zero");
+        assert_eq!(shell.run_line("echo $((1/0)) 2>/dev/null"), 1);
+        let error = expand_word("$((08))", &mut shell).unwrap_err();
+        assert_eq!(error.to_string(), "08: invalid number `08'");
+
+        // Arithmetic expansions inside each other are limited like parentheses
+        let nested = |depth: usize| format!("{}1{}", "$((".repeat(depth), "))".repeat(depth));
+        assert_eq!(expand_word(&nested(50), &mut shell).unwrap(), "1");
+        let error = expand_word(&nested(5000), &mut shell).unwrap_err();
+        assert!(error.to_string().ends_with(": expression nesting too deep"), "{}", error);
+        assert_eq!(shell.arithmetic_depth(), 0);
+    }
+
+    #[test]
+    fn test_field_splitting_of_substitutions() {
+        let mut shell = Shell::new();
+        let fields = |word: &str, shell: &mut Shell| expand_fields(word, shell).unwrap();
+
+        assert_eq!(fields("$(echo ' a  b ')", &mut shell), vec!["a", "b"]);
+        assert_eq!(fields("x$(printf 'a\\nb')y", &mut shell), vec!["xa", "by"]);
+        assert_eq!(fields("\"$(echo ' a  b ')\"", &mut shell), vec![" a  b "]);
+        assert_eq!(fields("$(true)", &mut shell), Vec::<String>::new());
+        assert_eq!(fields("\"$(true)\"", &mut shell), vec![""]);
+
+        shell.set_var("IFS", ":");
+        assert_eq!(fields("$(echo 'a b::c')", &mut shell), vec!["a b", "", "c"]);
+    }
+
+    #[test]
+    fn test_pathname_expansion() {
+        fs::create_dir_all("test_expand_glob").unwrap();
+        fs::write("test_expand_glob/one.log", "").unwrap();
+        fs::write("test_expand_glob/two.log", "").unwrap();
+        let mut shell = Shell::new();
+        shell.set_var("PATTERN", "test_expand_glob/*.log");
+
+        let both = vec!["test_expand_glob/one.log", "test_expand_glob/two.log"];
+        assert_eq!(expand_fields("test_expand_glob/*.log", &mut shell).unwrap(), both);
+        assert_eq!(expand_fields("$PATTERN", &mut shell).unwrap(), both);
+        assert_eq!(expand_fields("test_expand_glob/t[uvw]o.log", &mut shell).unwrap(), vec!["test_expand_glob/two.log"]);
+
+        // Quoted wildcards and patterns without matches stay as they are
+        assert_eq!(expand_fields("\"test_expand_glob/*.log\"", &mut shell).unwrap(), vec!["test_expand_glob/*.log"]);
+        assert_eq!(expand_fields("test_expand_glob/\\*.log", &mut shell).unwrap(), vec!["test_expand_glob/*.log"]);
+        assert_eq!(expand_fields("\"$PATTERN\"", &mut shell).unwrap(), vec!["test_expand_glob/*.log"]);
+        assert_eq!(expand_fields("test_expand_glob/*.txt", &mut shell).unwrap(), vec!["test_expand_glob/*.txt"]);
+
+        fs::remove_dir_all("test_expand_glob").unwrap();
+    }
+}
@@ -0,0 +1,256 @@
+use std::fmt;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::raw::c_int;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::thread::JoinHandle;
+
+use crate::builtins::ExitStatus;
+use crate::shell::Shell;
+use crate::signals;
+use crate::streams::{self, Io};
+use crate::sys::{self, Pid};
+
+/// ENOEXEC - the file exists but is not in a format the kernel can run
+const ENOEXEC: i32 = 8;
+
+/// Where commands are looked for when `PATH` is unset
+pub(crate) const DEFAULT_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+/// Reasons an external command could not be started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+    NotFound,
+    PermissionDenied,
+    IsDirectory,
+    NotExecutable,
+    Other(String),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::NotFound => write!(f, "command not found"),
+            ExecError::PermissionDenied => write!(f, "Permission denied"),
+            ExecError::IsDirectory => write!(f, "Is a directory"),
+            ExecError::NotExecutable => write!(f, "cannot execute binary file: Exec format error"),
+            ExecError::Other(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+/// Where a new process goes when the shell has job control
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JobGroup {
+    /// The process group to join, or 0 to start a new one led by the process
+    pub pgid: Pid,
+    /// The terminal to hand to the group, for a job run in the foreground
+    pub terminal: Option<c_int>,
+}
+
+/// A running external program
+pub struct Process {
+    child: Child,
+    /// Threads copying the child's output into in-memory buffers
+    pumps: Vec<JoinHandle<()>>,
+}
+
+impl Process {
+    /// Wait for the program to finish and convert its status the way
+    /// shells do: a process killed by signal N reports 128 + N
+    pub fn wait(mut self) -> ExitStatus {
+        let status = match self.child.wait() {
+            Ok(status) => status
+                .code()
+                .unwrap_or_else(|| 128 + status.signal().unwrap_or(0)),
+            Err(_) => 1,
+        };
+
+        for pump in self.pumps {
+            let _ = pump.join();
+        }
+        status
+    }
+
+    pub fn id(&self) -> Pid {
+        self.child.id() as Pid
+    }
+
+    /// Stop tracking the program here, leaving it to be waited for by pid
+    /// Output still being copied into buffers keeps flowing in the
+    /// background.
+    pub fn into_id(self) -> Pid {
+        self.id()
+    }
+}
+
+/// Start an external program with the given streams, looking it up in the
+/// shell's $PATH when it has no slash. It runs in the shell's working
+/// directory with the shell's exported variables as its environment.
+/// With a `group` it becomes part of a job. The caller is responsible for
+/// waiting on it.
+pub fn spawn(
+    shell: &Shell,
+    command: &str,
+    args: &[&str],
+    io: Io,
+    group: Option<JobGroup>,
+) -> Result<Process, ExecError> {
+    let program = find_executable(command, shell.var("PATH"), shell.cwd())?;
+    let io_error = |e: std::io::Error| ExecError::Other(streams::describe(&e));
+
+    let stdin = io.stdin.into_stdio().map_err(io_error)?;
+    let (stdout, stdout_pump) = io.stdout.into_stdio().map_err(io_error)?;
+    let (stderr, stderr_pump) = io.stderr.into_stdio().map_err(io_error)?;
+
+    let mut command = Command::new(&program);
+    command
+        .args(args)
+        .current_dir(shell.cwd())
+        .env_clear()
+        .envs(shell.env())
+        .stdin(stdin)
+        .stdout(stdout)
+        .stderr(stderr);
+    if let Some(group) = group {
+        command.process_group(group.pgid);
+    }
+    let terminal = group.and_then(|group| group.terminal);
+    // SAFETY: tcgetpgrp, tcsetpgrp and sigaction are async-signal-safe.
+    // The terminal is taken before SIGTTOU is back to its default, which
+    // would stop a background process asking for it.
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(fd) = terminal {
+                let _ = sys::set_foreground_group(fd, sys::process_group());
+            }
+            signals::restore_defaults(group.is_some())
+        });
+    }
+    let child = command.spawn().map_err(|e| spawn_error(&program, e))?;
+
+    // Also set the group from this side, so it is in place whichever of
+    // the two processes gets there first
+    if let Some(group) = group {
+        let pid = child.id() as Pid;
+        let pgid = if group.pgid == 0 { pid } else { group.pgid };
+        let _ = sys::set_process_group(pid, pgid);
+    }
+
+    // The Command, and with it our copies of the pipe write ends, is gone
+    // by now, so the pumps stop once the child closes its side
+    let pumps = stdout_pump.into_iter().chain(stderr_pump).collect();
+    Ok(Process { child, pumps })
+}
+
+/// Resolve a command name to the program that should be executed
+///
+/// Names containing a `/` are used as-is. Otherwise each directory of
+/// `path_var` is searched in order; an empty entry means the current
+/// directory, and no `path_var` at all means `DEFAULT_PATH`. Relative
+/// paths are taken relative to `cwd`. A non-executable match is remembered
+/// so that a later executable one still wins, and "Permission denied" is
+/// only reported when nothing runnable was found.
+pub fn find_executable(command: &str, path_var: Option<&str>, cwd: &Path) -> Result<PathBuf, ExecError> {
+    if command.is_empty() {
+        return Err(ExecError::NotFound);
+    }
+
+    if command.contains('/') {
+        let path = cwd.join(command);
+        return check_executable(&path).map(|_| path);
+    }
+
+    let mut denied = false;
+
+    for dir in path_var.unwrap_or(DEFAULT_PATH).split(':') {
+        let candidate = cwd.join(dir).join(command);
+
+        match check_executable(&candidate) {
+            Ok(()) => return Ok(candidate),
+            Err(ExecError::PermissionDenied) => denied = true,
+            Err(_) => {}
+        }
+    }
+
+    if denied {
+        Err(ExecError::PermissionDenied)
+    } else {
+        Err(ExecError::NotFound)
+    }
+}
+
+fn check_executable(path: &Path) -> Result<(), ExecError> {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            return Err(ExecError::PermissionDenied);
+        }
+        Err(_) => return Err(ExecError::NotFound),
+    };
+
+    if metadata.is_dir() {
+        return Err(ExecError::IsDirectory);
+    }
+
+    if metadata.permissions().mode() & 0o111 == 0 {
+        return Err(ExecError::PermissionDenied);
+    }
+
+    Ok(())
+}
+
+fn spawn_error(program: &Path, error: std::io::Error) -> ExecError {
+    match error.kind() {
+        ErrorKind::NotFound => ExecError::NotFound,
+        ErrorKind::PermissionDenied => ExecError::PermissionDenied,
+        _ if error.raw_os_error() == Some(ENOEXEC) => ExecError::NotExecutable,
+        _ => ExecError::Other(format!("{}: {}", program.display(), error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_in_path() {
+        let result = find_executable("sh", Some("/nonexistent:/bin:/usr/bin"), Path::new("/"));
+        assert!(result.is_ok());
+        assert!(result.unwrap().ends_with("sh"));
+
+        // Without PATH the usual directories are searched, not the current one
+        let result = find_executable("sh", None, Path::new("/"));
+        assert!(result.unwrap().ends_with("bin/sh"));
+    }
+
+    #[test]
+    fn test_command_not_found() {
+        let result = find_executable("no_such_command_0shell", Some("/bin:/usr/bin"), Path::new("/"));
+        assert_eq!(result, Err(ExecError::NotFound));
+
+        let result = find_executable("sh", Some(""), Path::new("/"));
+        assert_eq!(result, Err(ExecError::NotFound));
+    }
+
+    #[test]
+    fn test_directory_is_not_executable() {
+        assert_eq!(find_executable("src/", Some(""), Path::new(".")), Err(ExecError::IsDirectory));
+    }
+
+    #[test]
+    fn test_non_executable_file() {
+        fs::write("test_exec_plain.sh", "echo hi\n").unwrap();
+
+        let result = find_executable("./test_exec_plain.sh", None, Path::new("."));
+        assert_eq!(result, Err(ExecError::PermissionDenied));
+
+        let result = find_executable("test_exec_plain.sh", Some("."), Path::new("."));
+        assert_eq!(result, Err(ExecError::PermissionDenied));
+
+        fs::remove_file("test_exec_plain.sh").unwrap();
+    }
+}
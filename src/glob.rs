@@ -0,0 +1,322 @@
+use std::fs;
+use std::path::Path;
+
+/// One element of a compiled pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    /// A character that must appear as-is
+    Literal(char),
+    /// `?` - any single character
+    AnyChar,
+    /// `*` - any run of characters, including none
+    AnyRun,
+    /// `[...]` - one character from a set, or not from it with `[!...]`
+    Class { negated: bool, items: Vec<ClassItem> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    /// A named class such as `[:digit:]`
+    Named(String),
+}
+
+/// Whether a pattern contains `*`, `?` or `[` outside of backslash escapes
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Whether `text` matches a shell pattern
+///
+/// `*` matches any run of characters, `?` any single character and
+/// `[...]` one character from a set (`[abc]`, `[a-z]`, `[!0-9]`,
+/// `[[:alpha:]]`). A backslash makes the next character literal, and a
+/// `[` without a closing `]` is literal too.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern = compile(pattern);
+    let text: Vec<char> = text.chars().collect();
+
+    // Greedy matching that backtracks to the most recent `*`
+    let (mut p, mut t) = (0, 0);
+    let mut last_star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some(Pattern::AnyRun) => {
+                last_star = Some((p, t));
+                p += 1;
+            }
+            Some(element) if matches_char(element, text[t]) => {
+                p += 1;
+                t += 1;
+            }
+            _ => match last_star {
+                // Let the star swallow one more character and retry
+                Some((star, start)) => {
+                    p = star + 1;
+                    t = start + 1;
+                    last_star = Some((star, start + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|element| *element == Pattern::AnyRun)
+}
+
+/// Expand a pattern into the sorted list of existing paths it matches,
+/// resolving relative patterns against `cwd`
+///
+/// Each `/`-separated component is matched against directory entries on
+/// its own, so wildcards never match a `/`. As with `ls` without `-a`,
+/// names starting with `.` are only matched by a component that starts
+/// with a literal `.`, and `.` and `..` are never produced. An empty list
+/// means nothing matched.
+pub fn glob(pattern: &str, cwd: &Path) -> Vec<String> {
+    let mut paths = vec![if pattern.starts_with('/') { "/".to_string() } else { String::new() }];
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+
+    for (index, component) in components.iter().enumerate() {
+        let mut next = Vec::new();
+
+        for prefix in &paths {
+            if !has_wildcards(component) {
+                next.push(format!("{}{}", prefix, unescape(component)));
+                continue;
+            }
+
+            let dir = cwd.join(if prefix.is_empty() { "." } else { prefix });
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let hidden_allowed = component.starts_with('.');
+            for entry in entries.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') && !hidden_allowed {
+                    continue;
+                }
+                if matches(component, &name) {
+                    next.push(format!("{}{}", prefix, name));
+                }
+            }
+        }
+
+        // Anything followed by another component has to be a directory
+        let more = index + 1 < components.len() || pattern.ends_with('/');
+        if more {
+            next.retain(|path| cwd.join(path).is_dir());
+            for path in &mut next {
+                path.push('/');
+            }
+        }
+        paths = next;
+    }
+
+    // Literal components were taken on trust; drop what does not exist
+    paths.retain(|path| fs::symlink_metadata(cwd.join(path)).is_ok());
+    paths.sort();
+    paths
+}
+
+/// Remove the backslash escapes from a pattern without wildcards
+fn unescape(pattern: &str) -> String {
+    let mut result = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+fn compile(pattern: &str) -> Vec<Pattern> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut result = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '*' => {
+                // Consecutive stars behave like one
+                if result.last() != Some(&Pattern::AnyRun) {
+                    result.push(Pattern::AnyRun);
+                }
+            }
+            '?' => result.push(Pattern::AnyChar),
+            '[' => match compile_class(&chars, i + 1) {
+                Some((class, end)) => {
+                    result.push(class);
+                    i = end;
+                }
+                None => result.push(Pattern::Literal('[')),
+            },
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                result.push(Pattern::Literal(chars[i]));
+            }
+            c => result.push(Pattern::Literal(c)),
+        }
+        i += 1;
+    }
+
+    result
+}
+
+/// Compile a bracket expression whose contents start at `start`
+/// Returns the class and the index of its closing `]`, or `None` when the
+/// bracket is never closed
+fn compile_class(chars: &[char], start: usize) -> Option<(Pattern, usize)> {
+    let mut i = start;
+    let negated = matches!(chars.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut items = Vec::new();
+    let first = i;
+
+    loop {
+        let c = *chars.get(i)?;
+        match c {
+            // A `]` right after the opening bracket is an ordinary member
+            ']' if i > first => return Some((Pattern::Class { negated, items }, i)),
+            '[' if chars.get(i + 1) == Some(&':') => {
+                let rest: String = chars[i + 2..].iter().collect();
+                match rest.find(":]") {
+                    Some(end) => {
+                        items.push(ClassItem::Named(rest[..end].to_string()));
+                        i += 2 + rest[..end].chars().count() + 2;
+                    }
+                    None => {
+                        items.push(ClassItem::Char('['));
+                        i += 1;
+                    }
+                }
+            }
+            _ => {
+                let (low, next) = class_char(chars, i)?;
+                // `a-z` is a range, but a `-` at either end is literal
+                if chars.get(next) == Some(&'-') && chars.get(next + 1).is_some_and(|&c| c != ']') {
+                    let (high, after) = class_char(chars, next + 1)?;
+                    items.push(ClassItem::Range(low, high));
+                    i = after;
+                } else {
+                    items.push(ClassItem::Char(low));
+                    i = next;
+                }
+            }
+        }
+    }
+}
+
+/// Read one possibly escaped character of a bracket expression
+fn class_char(chars: &[char], i: usize) -> Option<(char, usize)> {
+    match chars.get(i)? {
+        '\\' => chars.get(i + 1).map(|&c| (c, i + 2)),
+        &c => Some((c, i + 1)),
+    }
+}
+
+fn matches_char(element: &Pattern, c: char) -> bool {
+    match element {
+        Pattern::Literal(expected) => *expected == c,
+        Pattern::AnyChar => true,
+        Pattern::AnyRun => false,
+        Pattern::Class { negated, items } => {
+            let found = items.iter().any(|item| match item {
+                ClassItem::Char(expected) => *expected == c,
+                ClassItem::Range(low, high) => (*low..=*high).contains(&c),
+                ClassItem::Named(name) => matches_named_class(name, c),
+            });
+            found != *negated
+        }
+    }
+}
+
+fn matches_named_class(name: &str, c: char) -> bool {
+    match name {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "blank" => c == ' ' || c == '\t',
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*.rs", ".rs"));
+        assert!(!matches("*.rs", "main.rsx"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(!matches("a*b*c", "aXXbYY"));
+        assert!(matches("?", "x"));
+        assert!(!matches("?", ""));
+        assert!(matches("**", ""));
+    }
+
+    #[test]
+    fn test_bracket_expressions() {
+        assert!(matches("file[0-9].txt", "file7.txt"));
+        assert!(!matches("file[!0-9].txt", "file7.txt"));
+        assert!(matches("[abc]", "b"));
+        assert!(matches("[]x]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[[:upper:]]*", "Makefile"));
+        assert!(!matches("[[:digit:]]", "x"));
+        // An unclosed bracket is an ordinary character
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert!(matches(r"\*", "*"));
+        assert!(!matches(r"\*", "x"));
+        assert!(!has_wildcards(r"a\*b"));
+        assert!(has_wildcards("a[bc]"));
+    }
+
+    #[test]
+    fn test_glob_directory() {
+        fs::create_dir_all("test_glob_dir/sub").unwrap();
+        for name in ["a.log", "b.log", "c.txt", ".hidden.log", "sub/d.log"] {
+            fs::write(Path::new("test_glob_dir").join(name), "").unwrap();
+        }
+        let cwd = Path::new(".");
+
+        assert_eq!(glob("test_glob_dir/*.log", cwd), vec!["test_glob_dir/a.log", "test_glob_dir/b.log"]);
+        assert_eq!(glob("test_glob_dir/.*.log", cwd), vec!["test_glob_dir/.hidden.log"]);
+        assert_eq!(glob("test_glob_d?r/*/d.log", cwd), vec!["test_glob_dir/sub/d.log"]);
+        assert_eq!(glob("test_glob_dir/*/", cwd), vec!["test_glob_dir/sub/"]);
+        assert!(glob("test_glob_dir/*.none", cwd).is_empty());
+
+        let absolute = fs::canonicalize("test_glob_dir").unwrap();
+        let pattern = format!("{}/[ab].log", absolute.display());
+        assert_eq!(glob(&pattern, Path::new("/")).len(), 2);
+
+        fs::remove_dir_all("test_glob_dir").unwrap();
+    }
+}
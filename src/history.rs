@@ -0,0 +1,442 @@
+//! Command history, kept in memory and optionally in a file
+//!
+//! The file holds one command per line, oldest first. Every session
+//! appends its own commands as they are entered, under an exclusive
+//! `flock`, so several shells can share one file without interleaving or
+//! losing lines. The file is trimmed to the size limit when it is opened.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::parser::{self, ParseError, Token};
+use crate::sys;
+
+/// Previously entered commands, numbered from 1
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: Vec<String>,
+    /// The most entries kept, in memory and in the file
+    limit: usize,
+    file: Option<PathBuf>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+impl History {
+    /// Used when `$HISTSIZE` is not set
+    pub const DEFAULT_SIZE: usize = 1000;
+
+    /// An empty history that is not saved anywhere
+    pub fn new() -> Self {
+        History {
+            entries: Vec::new(),
+            limit: History::DEFAULT_SIZE,
+            file: None,
+        }
+    }
+
+    /// Load the history saved in `path` and keep saving new commands there
+    /// A missing file is created once the first command is added.
+    pub fn open(path: impl Into<PathBuf>, limit: usize) -> io::Result<Self> {
+        let path = path.into();
+        let mut history = History {
+            entries: Vec::new(),
+            limit,
+            file: Some(path.clone()),
+        };
+
+        let mut file = match OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(e),
+        };
+        sys::lock_exclusive(&file)?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        history.entries = contents.lines().map(str::to_string).collect();
+
+        if history.entries.len() > limit {
+            history.trim();
+            rewrite(&mut file, &history.entries)?;
+        }
+        Ok(history)
+    }
+
+    /// The entries, oldest first; entry `n` is at index `n - 1`
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// The file the history is saved to, if any
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    /// Remember a command. Blank lines and repeats of the previous command
+    /// are skipped. The command is appended to the history file right
+    /// away, so other sessions see it the next time they start.
+    pub fn add(&mut self, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return Ok(());
+        }
+
+        self.entries.push(line.to_string());
+        self.trim();
+
+        if let Some(path) = &self.file {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            sys::lock_exclusive(&file)?;
+            // One write per line, so appends from other sessions cannot
+            // end up in the middle of it
+            file.write_all(format!("{}\n", line).as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Forget every entry, in memory and in the file
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+
+        if let Some(path) = &self.file {
+            match fs::metadata(path) {
+                Ok(_) => {
+                    let file = OpenOptions::new().write(true).open(path)?;
+                    sys::lock_exclusive(&file)?;
+                    file.set_len(0)?;
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove entry number `number`. The most recent copy of the same
+    /// command in the file is removed too, leaving other sessions' lines
+    /// alone. Returns `false` if there is no such entry.
+    pub fn delete(&mut self, number: usize) -> io::Result<bool> {
+        if number == 0 || number > self.entries.len() {
+            return Ok(false);
+        }
+        let removed = self.entries.remove(number - 1);
+
+        if let Some(path) = &self.file {
+            let mut file = match OpenOptions::new().read(true).write(true).open(path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(true),
+                Err(e) => return Err(e),
+            };
+            sys::lock_exclusive(&file)?;
+
+            let mut contents = String::new();
+            file.read_to_string(&mut contents)?;
+            let mut lines: Vec<String> = contents.lines().map(str::to_string).collect();
+            if let Some(index) = lines.iter().rposition(|line| *line == removed) {
+                lines.remove(index);
+                rewrite(&mut file, &lines)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Apply history expansion to a line before it runs
+    ///
+    /// - `!!` is the previous command
+    /// - `!n` is entry number `n`, and `!-n` the command `n` lines back
+    /// - `!prefix` is the most recent command starting with `prefix`
+    ///
+    /// A `!` inside single quotes, after a backslash, or followed by a
+    /// space, `=`, `(` or the end of the line is left alone. Returns
+    /// `Ok(None)` when there was nothing to expand, and an error message
+    /// when a referenced command does not exist.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, String> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut result = String::with_capacity(line.len());
+        let mut expanded = false;
+        let mut in_single = false;
+        let mut in_double = false;
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '\'' if !in_double => in_single = !in_single,
+                '"' if !in_single => in_double = !in_double,
+                '\\' if !in_single && i + 1 < chars.len() => {
+                    result.push(c);
+                    result.push(chars[i + 1]);
+                    i += 2;
+                    continue;
+                }
+                '!' if !in_single => {
+                    let end = event_end(&chars, i + 1, in_double);
+                    if end > i + 1 {
+                        let designator: String = chars[i + 1..end].iter().collect();
+                        match self.event(&designator) {
+                            Some(event) => result.push_str(event),
+                            None => return Err(format!("!{}: event not found", designator)),
+                        }
+                        expanded = true;
+                        i = end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            result.push(c);
+            i += 1;
+        }
+
+        Ok(expanded.then_some(result))
+    }
+
+    /// Look up the command an event designator (the text after `!`) means
+    fn event(&self, designator: &str) -> Option<&str> {
+        let found = if designator == "!" {
+            self.entries.last()
+        } else if let Some(back) = designator.strip_prefix('-') {
+            let back: usize = back.parse().ok()?;
+            self.entries.len().checked_sub(back).and_then(|index| self.entries.get(index))
+        } else if let Ok(number) = designator.parse::<usize>() {
+            number.checked_sub(1).and_then(|index| self.entries.get(index))
+        } else {
+            self.entries.iter().rev().find(|entry| entry.starts_with(designator))
+        };
+        found.map(String::as_str)
+    }
+
+    /// Drop the oldest entries beyond the limit
+    fn trim(&mut self) {
+        if self.entries.len() > self.limit {
+            let excess = self.entries.len() - self.limit;
+            self.entries.drain(..excess);
+        }
+    }
+}
+
+/// Find where the event designator starting at `start` ends
+/// Returns `start` if the `!` there does not begin an event at all.
+fn event_end(chars: &[char], start: usize, in_double: bool) -> usize {
+    match chars.get(start) {
+        None => start,
+        Some(c) if c.is_whitespace() || matches!(c, '=' | '(') => start,
+        Some('"') if in_double => start,
+        Some('!') => start + 1,
+        Some(c) if c.is_ascii_digit() || *c == '-' => {
+            let digits = chars[start + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            start + 1 + digits
+        }
+        Some(_) => {
+            let length = chars[start..]
+                .iter()
+                .take_while(|c| !c.is_whitespace() && !";|&<>()\"'".contains(**c))
+                .count();
+            start + length
+        }
+    }
+}
+
+/// Join the lines of a command typed over several lines into one entry
+/// that runs the same way, as in `for f in *; do echo $f; done`
+///
+/// A line that ends a command is followed by `; `, and one that ends in a
+/// word or operator expecting more, such as `then`, `do`, `|` or a `case`
+/// pattern's `)`, by a space. A newline inside quotes, after a comment or
+/// once a here-document has started cannot be replaced, so it is kept.
+pub fn join_lines(lines: &[String]) -> String {
+    let mut entry = String::new();
+
+    for line in lines {
+        let separator = match parser::tokenize(&entry) {
+            _ if entry.is_empty() => "",
+            Err(ParseError::TrailingBackslash) => {
+                entry.pop();
+                ""
+            }
+            Err(_) => "\n",
+            Ok(_) if ends_in_comment(&entry) => "\n",
+            Ok(tokens) if has_here_doc(&tokens) => "\n",
+            Ok(tokens) if expects_more(&tokens) => " ",
+            Ok(_) => "; ",
+        };
+        // Blank lines only matter inside quotes
+        if separator != "\n" && line.trim().is_empty() {
+            continue;
+        }
+        entry.push_str(separator);
+        entry.push_str(if separator == "\n" { line } else { line.trim_start() });
+    }
+    entry
+}
+
+/// Whether a here-document started, whose text has to stay on lines of
+/// its own
+fn has_here_doc(tokens: &[Token]) -> bool {
+    tokens
+        .iter()
+        .any(|token| matches!(token, Token::Redirect(_, kind) if kind.is_here_doc()))
+}
+
+/// Whether the last token needs something after it on the same line
+fn expects_more(tokens: &[Token]) -> bool {
+    // Keywords count as the first word of a command, or as the `in` of a
+    // `case` (after `for`, the word list is ended with `;`)
+    let command_start = |index: usize| {
+        index == 0
+            || match &tokens[index - 1] {
+                Token::Word(word) => matches!(word.as_str(), "then" | "do" | "else"),
+                Token::RightParen | Token::Redirect(..) => false,
+                _ => true,
+            }
+    };
+    let Some(last) = tokens.len().checked_sub(1) else {
+        return false;
+    };
+
+    match &tokens[last] {
+        Token::Word(word) if word == "in" => last >= 2 && tokens[last - 2] == Token::Word("case".to_string()),
+        // After `f()` or a `case` pattern, `{` opens the body
+        Token::Word(word) if word == "{" => command_start(last) || tokens[last - 1] == Token::RightParen,
+        Token::Word(word) => {
+            matches!(word.as_str(), "if" | "then" | "elif" | "else" | "while" | "until" | "do") && command_start(last)
+        }
+        // A `)` closing a `case` pattern rather than a subshell
+        Token::RightParen => {
+            let count = |wanted: &Token| tokens.iter().filter(|token| *token == wanted).count();
+            count(&Token::RightParen) > count(&Token::LeftParen)
+        }
+        _ => true,
+    }
+}
+
+/// Whether the text ends in a `#` comment, which would swallow anything
+/// appended to its line
+fn ends_in_comment(text: &str) -> bool {
+    let probe = format!("{} x", text);
+    parser::tokenize(&probe).is_ok_and(|tokens| tokens.last() != Some(&Token::Word("x".to_string())))
+}
+
+/// Replace the whole contents of a locked file
+fn rewrite(file: &mut File, lines: &[String]) -> io::Result<()> {
+    let mut contents = lines.join("\n");
+    if !contents.is_empty() {
+        contents.push('\n');
+    }
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(entries: &[&str]) -> History {
+        let mut history = History::new();
+        for entry in entries {
+            history.add(entry).unwrap();
+        }
+        history
+    }
+
+    #[test]
+    fn test_add_skips_blank_lines_and_repeats() {
+        let history = history(&["ls", "ls", " ", "pwd", "ls"]);
+        assert_eq!(history.entries(), ["ls", "pwd", "ls"]);
+    }
+
+    #[test]
+    fn test_size_limit() {
+        let mut history = History::new();
+        history.limit = 2;
+        for entry in ["one", "two", "three"] {
+            history.add(entry).unwrap();
+        }
+        assert_eq!(history.entries(), ["two", "three"]);
+    }
+
+    #[test]
+    fn test_expansion() {
+        let history = history(&["ls -l", "echo hello", "cd /tmp"]);
+
+        assert_eq!(history.expand("sudo !!"), Ok(Some("sudo cd /tmp".to_string())));
+        assert_eq!(history.expand("!1 | wc"), Ok(Some("ls -l | wc".to_string())));
+        assert_eq!(history.expand("!-2"), Ok(Some("echo hello".to_string())));
+        assert_eq!(history.expand("!ec; !l"), Ok(Some("echo hello; ls -l".to_string())));
+        assert_eq!(history.expand("echo \"!!\""), Ok(Some("echo \"cd /tmp\"".to_string())));
+        assert_eq!(history.expand("!nope"), Err("!nope: event not found".to_string()));
+        assert_eq!(history.expand("!9"), Err("!9: event not found".to_string()));
+    }
+
+    #[test]
+    fn test_expansion_leaves_literal_bangs() {
+        let history = history(&["ls"]);
+        for line in ["echo hi!", "echo '!!'", "echo \\!!", "x != y", "echo \"wow!\"", "ls"] {
+            assert_eq!(history.expand(line), Ok(None), "{}", line);
+        }
+    }
+
+    #[test]
+    fn test_file_shared_between_sessions() {
+        let path = "test_history_shared";
+        let _ = fs::remove_file(path);
+
+        let mut first = History::open(path, 100).unwrap();
+        let mut second = History::open(path, 100).unwrap();
+        first.add("echo one").unwrap();
+        second.add("echo two").unwrap();
+        first.add("echo three").unwrap();
+
+        let reopened = History::open(path, 100).unwrap();
+        assert_eq!(reopened.entries(), ["echo one", "echo two", "echo three"]);
+
+        // Trimmed to the limit when opened
+        let trimmed = History::open(path, 2).unwrap();
+        assert_eq!(trimmed.entries(), ["echo two", "echo three"]);
+        assert_eq!(fs::read_to_string(path).unwrap(), "echo two\necho three\n");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_delete_and_clear_update_the_file() {
+        let path = "test_history_delete";
+        let _ = fs::remove_file(path);
+
+        let mut history = History::open(path, 100).unwrap();
+        for entry in ["a", "b", "c"] {
+            history.add(entry).unwrap();
+        }
+
+        assert!(history.delete(2).unwrap());
+        assert!(!history.delete(7).unwrap());
+        assert_eq!(history.entries(), ["a", "c"]);
+        assert_eq!(fs::read_to_string(path).unwrap(), "a\nc\n");
+
+        history.clear().unwrap();
+        assert!(history.entries().is_empty());
+        assert_eq!(fs::read_to_string(path).unwrap(), "");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_join_lines() {
+        let join = |lines: &[&str]| join_lines(&lines.iter().map(|line| line.to_string()).collect::<Vec<_>>());
+
+        assert_eq!(join(&["for f in a b", "do", "  echo $f |", "  wc -c", "done"]), "for f in a b; do echo $f | wc -c; done");
+        assert_eq!(join(&["if true", "then echo do", "", "fi"]), "if true; then echo do; fi");
+        assert_eq!(join(&["case $x in", "  a|b)", "    echo ab;;", "esac"]), "case $x in a|b) echo ab;; esac");
+        assert_eq!(join(&["echo 'two", "  lines'"]), "echo 'two\n  lines'");
+        assert_eq!(join(&["echo a \\", "b"]), "echo a b");
+        assert_eq!(join(&["while true # forever", "do :; done"]), "while true # forever\ndo :; done");
+        assert_eq!(join(&["f() {", "  (cd /tmp", "  ls)", "}", "echo {"]), "f() { (cd /tmp; ls); }; echo {");
+        assert_eq!(join(&["cat <<EOF |", "", "  text", "EOF", "wc -l"]), "cat <<EOF |\n\n  text\nEOF\nwc -l");
+    }
+}
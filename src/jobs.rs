@@ -0,0 +1,637 @@
+//! Jobs: commands started with `&` or stopped with Ctrl-Z
+//!
+//! A command line ending in `&` runs in a forked copy of the shell, so
+//! builtins and `&&` lists work there too, and the shell carries on. With
+//! job control (an interactive shell on a terminal) every job gets a
+//! process group of its own. Only the foreground job's group may use the
+//! terminal, so Ctrl-C and Ctrl-Z reach it and not the shell, and `fg`
+//! and `bg` move jobs between the two by handing the terminal over.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::raw::c_int;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use crate::builtins::ExitStatus;
+use crate::parser::AndOr;
+use crate::shell::Shell;
+use crate::signals;
+use crate::streams::{self, Input};
+use crate::sys::{self, Pid, Termios, WaitStatus};
+
+/// Status of a job stopped by Ctrl-Z: 128 + SIGTSTP
+pub const STOPPED_STATUS: ExitStatus = 128 + sys::SIGTSTP;
+
+/// What a job is doing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    /// Finished, with the status of its last process
+    Done(ExitStatus),
+}
+
+/// A pipeline or and-or list the shell keeps track of
+#[derive(Clone)]
+pub struct Job {
+    id: usize,
+    /// The job's own process group, if job control gave it one
+    pgid: Option<Pid>,
+    /// Every process, first to last
+    pids: Vec<Pid>,
+    /// The processes that have not finished yet
+    running: Vec<Pid>,
+    /// The status of the last process, once it has finished
+    status: ExitStatus,
+    command: String,
+    state: JobState,
+    /// Whether a process of the job was killed by Ctrl-C
+    interrupted: bool,
+    /// The terminal settings the job had when it stopped, given back on `fg`
+    modes: Option<Termios>,
+    /// Whether the latest change of state still has to be reported
+    changed: bool,
+    /// The threads copying what the job writes into buffers
+    pumps: Pumps,
+}
+
+/// Threads copying a job's output from pipes into buffers, shared by the
+/// copies of the job
+#[derive(Clone, Default)]
+struct Pumps(Arc<Mutex<Vec<JoinHandle<()>>>>);
+
+impl Pumps {
+    /// Wait until everything the job wrote has reached the buffers, which
+    /// is once no process, the job's or one it left behind, holds the pipes
+    fn finish(&self) {
+        let pumps = std::mem::take(&mut *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        for pump in pumps {
+            let _ = pump.join();
+        }
+    }
+}
+
+impl Job {
+    pub(crate) fn new(pgid: Option<Pid>, pids: Vec<Pid>, command: String) -> Job {
+        Job {
+            id: 0,
+            pgid,
+            running: pids.clone(),
+            pids,
+            status: 0,
+            command,
+            state: JobState::Running,
+            interrupted: false,
+            modes: None,
+            changed: false,
+            pumps: Pumps::default(),
+        }
+    }
+
+    /// The number `%n` refers to the job by
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The processes of the job, first to last
+    pub fn pids(&self) -> &[Pid] {
+        &self.pids
+    }
+
+    /// The command line, as it could have been typed
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+
+    pub fn state(&self) -> JobState {
+        self.state
+    }
+
+    /// Record what `waitpid` said about one of the job's processes
+    fn update(&mut self, pid: Pid, status: WaitStatus) {
+        let exit = match status {
+            WaitStatus::Exited(code) => code,
+            WaitStatus::Signaled(signal) => {
+                self.interrupted |= signal == sys::SIGINT;
+                128 + signal
+            }
+            WaitStatus::Stopped(_) => {
+                self.changed |= self.state != JobState::Stopped;
+                self.state = JobState::Stopped;
+                return;
+            }
+            WaitStatus::Continued => {
+                self.state = JobState::Running;
+                return;
+            }
+        };
+
+        self.running.retain(|&running| running != pid);
+        if self.pids.last() == Some(&pid) {
+            self.status = exit;
+        }
+        if self.running.is_empty() {
+            self.state = JobState::Done(self.status);
+            self.changed = true;
+            self.pumps.finish();
+        }
+    }
+
+    /// Collect changes of state without blocking
+    fn poll(&mut self) {
+        for pid in self.running.clone() {
+            match sys::wait_pid(pid, sys::WNOHANG | sys::WUNTRACED | sys::WCONTINUED) {
+                Ok(Some((pid, status))) => self.update(pid, status),
+                Ok(None) => {}
+                // Already waited for elsewhere, so its status is lost
+                Err(_) => self.update(pid, WaitStatus::Exited(0)),
+            }
+        }
+    }
+
+    /// Block until the job finishes or stops. An `interruptible` wait gives
+    /// up when Ctrl-C reaches the shell, returning `false`.
+    fn wait(&mut self, interruptible: bool) -> bool {
+        while self.state == JobState::Running {
+            // A job with a group of its own can be waited for as a whole,
+            // so a stop of any of its processes is noticed at once
+            let target = match (self.pgid, self.running.first()) {
+                (_, None) => break,
+                (Some(pgid), _) => -pgid,
+                (None, Some(&pid)) => pid,
+            };
+            match sys::wait_pid(target, sys::WUNTRACED) {
+                Ok(Some((pid, status))) => self.update(pid, status),
+                Ok(None) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    if interruptible && signals::interrupted() {
+                        return false;
+                    }
+                }
+                Err(_) => {
+                    // Nothing left to wait for
+                    for pid in self.running.clone() {
+                        self.update(pid, WaitStatus::Exited(0));
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Let a stopped job carry on
+    fn resume(&mut self) {
+        if self.state == JobState::Stopped {
+            let target = self.pgid.map_or(self.pids[0], |pgid| -pgid);
+            let _ = sys::send_signal(target, sys::SIGCONT);
+            self.state = JobState::Running;
+        }
+    }
+}
+
+/// The terminal an interactive shell shares with its foreground jobs
+#[derive(Clone, Copy)]
+struct Terminal {
+    fd: c_int,
+    /// The shell's own process group
+    pgid: Pid,
+    /// The shell's terminal settings, put back whenever a job stops
+    modes: Termios,
+}
+
+/// The jobs a shell has started and not yet forgotten
+#[derive(Clone, Default)]
+pub struct Jobs {
+    /// Sorted by id
+    jobs: Vec<Job>,
+    /// Job ids from least to most recently started, stopped or resumed;
+    /// the last one is the current job `%+`, the one before it `%-`
+    recent: Vec<usize>,
+    terminal: Option<Terminal>,
+    /// `$!`: the process of the last job started with `&`
+    last_background: Option<Pid>,
+}
+
+impl Jobs {
+    /// Turn on job control for a shell reading commands from the terminal
+    /// on stdin: move the shell into a process group of its own and make
+    /// that the terminal's foreground group.
+    ///
+    /// Call this before [`signals::install`], so a shell started in the
+    /// background can still be stopped until it is brought to the front.
+    pub fn enable_control(&mut self) -> io::Result<()> {
+        let fd = sys::STDIN_FILENO;
+        loop {
+            let group = sys::process_group();
+            if sys::foreground_group(fd)? == group {
+                break;
+            }
+            sys::send_signal(-group, sys::SIGTTIN)?;
+        }
+
+        // Taking the terminal from the new group would stop the shell
+        sys::set_disposition(sys::SIGTTOU, sys::Disposition::Ignore)?;
+        // A session leader already leads its group and may not change it
+        let _ = sys::set_process_group(0, 0);
+        let pgid = sys::process_group();
+        sys::set_foreground_group(fd, pgid)?;
+
+        self.terminal = Some(Terminal {
+            fd,
+            pgid,
+            modes: sys::get_termios(fd)?,
+        });
+        Ok(())
+    }
+
+    /// Whether jobs get process groups of their own and the terminal
+    pub fn has_control(&self) -> bool {
+        self.terminal.is_some()
+    }
+
+    /// The terminal foreground jobs are given, with job control
+    pub(crate) fn terminal(&self) -> Option<c_int> {
+        self.terminal.map(|terminal| terminal.fd)
+    }
+
+    /// The jobs of a copy of the shell: none, since the ones started so far
+    /// are this shell's to manage, and no job control, but the same `$!`
+    pub(crate) fn for_subshell(&self) -> Jobs {
+        Jobs {
+            last_background: self.last_background,
+            ..Jobs::default()
+        }
+    }
+
+    /// The process of the last job started with `&`, for `$!`
+    pub fn last_background(&self) -> Option<Pid> {
+        self.last_background
+    }
+
+    /// All jobs, by id
+    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+        self.jobs.iter()
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    /// `+` for the current job, `-` for the previous one, else a space
+    pub fn marker(&self, id: usize) -> char {
+        match self.recent.iter().rev().position(|&recent| recent == id) {
+            Some(0) => '+',
+            Some(1) => '-',
+            _ => ' ',
+        }
+    }
+
+    /// Look up a job by a spec such as `%2`, `%+`, `%-` or `%vim`;
+    /// no spec at all means the current job
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let no_such_job = || format!("{}: no such job", spec.unwrap_or("current"));
+        let pattern = match spec {
+            None => "+",
+            Some(spec) => spec.strip_prefix('%').ok_or_else(no_such_job)?,
+        };
+
+        match pattern {
+            "" | "+" | "%" => self.recent.last().copied().ok_or_else(no_such_job),
+            "-" => self.recent.iter().rev().nth(1).copied().ok_or_else(no_such_job),
+            _ if pattern.bytes().all(|b| b.is_ascii_digit()) => {
+                let id: usize = pattern.parse().map_err(|_| no_such_job())?;
+                self.get(id).map(|job| job.id).ok_or_else(no_such_job)
+            }
+            _ => {
+                let (matching, text) = match pattern.strip_prefix('?') {
+                    Some(text) => (true, text),
+                    None => (false, pattern),
+                };
+                let mut found = self
+                    .jobs
+                    .iter()
+                    .filter(|job| if matching { job.command.contains(text) } else { job.command.starts_with(text) });
+                match (found.next(), found.next()) {
+                    (Some(job), None) => Ok(job.id),
+                    (Some(_), Some(_)) => Err(format!("{}: ambiguous job spec", spec.unwrap_or(""))),
+                    _ => Err(no_such_job()),
+                }
+            }
+        }
+    }
+
+    /// The job that process `pid` belongs to
+    pub fn find_pid(&self, pid: Pid) -> Option<usize> {
+        self.jobs.iter().find(|job| job.pids.contains(&pid)).map(|job| job.id)
+    }
+
+    /// Add a job, keeping its id if it had one before, and make it current
+    pub(crate) fn add(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        }
+        let id = job.id;
+        let index = self.jobs.partition_point(|other| other.id < id);
+        self.jobs.insert(index, job);
+        self.touch(id);
+        id
+    }
+
+    /// Take a job out of the table
+    pub(crate) fn remove(&mut self, id: usize) -> Option<Job> {
+        self.recent.retain(|&recent| recent != id);
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    /// Make a job the current one
+    fn touch(&mut self, id: usize) {
+        self.recent.retain(|&recent| recent != id);
+        self.recent.push(id);
+    }
+
+    /// Notice jobs that finished, stopped or were resumed, without waiting
+    pub fn reap(&mut self) {
+        for job in &mut self.jobs {
+            if !matches!(job.state, JobState::Done(_)) {
+                job.poll();
+            }
+        }
+    }
+
+    /// Lines announcing the jobs whose state changed since they were last
+    /// reported, like `[1]+  Done                    sleep 1`. Finished jobs
+    /// are forgotten once announced.
+    pub fn take_notifications(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for job in &self.jobs {
+            if job.changed {
+                lines.push(self.format(job, false));
+            }
+        }
+        for job in &mut self.jobs {
+            job.changed = false;
+        }
+        self.forget_finished();
+        lines
+    }
+
+    /// Wait until the output of every job has reached the buffers it goes
+    /// to, as a command substitution does before it is complete
+    pub(crate) fn finish_output(&self) {
+        for job in &self.jobs {
+            job.pumps.finish();
+        }
+    }
+
+    /// Drop the jobs that have finished, reported or not
+    pub fn forget_finished(&mut self) {
+        let done: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| matches!(job.state, JobState::Done(_)))
+            .map(|job| job.id)
+            .collect();
+        for id in done {
+            self.remove(id);
+        }
+    }
+
+    /// A line describing a job the way `jobs` lists it, with the process
+    /// ids too when `long`
+    pub fn format(&self, job: &Job, long: bool) -> String {
+        let state = match job.state {
+            JobState::Running => "Running".to_string(),
+            JobState::Stopped => "Stopped".to_string(),
+            JobState::Done(0) => "Done".to_string(),
+            JobState::Done(status) => format!("Exit {}", status),
+        };
+        let suffix = if job.state == JobState::Running { " &" } else { "" };
+        let marker = self.marker(job.id);
+
+        if long {
+            format!("[{}]{} {} {:<24}{}{}", job.id, marker, job.pids[0], state, job.command, suffix)
+        } else {
+            format!("[{}]{}  {:<24}{}{}", job.id, marker, state, job.command, suffix)
+        }
+    }
+
+    /// Run a job in the foreground until it finishes or stops, resuming it
+    /// first if it was stopped. A job that stops is put in the table and
+    /// announced on `err`. Returns the job's status.
+    pub(crate) fn foreground(&mut self, mut job: Job, err: &mut dyn Write) -> ExitStatus {
+        let terminal = self.terminal.filter(|_| job.pgid.is_some());
+
+        if let (Some(terminal), Some(pgid)) = (terminal, job.pgid) {
+            if let Some(modes) = &job.modes {
+                let _ = sys::set_termios(terminal.fd, modes);
+            }
+            let _ = sys::set_foreground_group(terminal.fd, pgid);
+        }
+        job.resume();
+        job.wait(false);
+
+        if let Some(terminal) = terminal {
+            let _ = sys::set_foreground_group(terminal.fd, terminal.pgid);
+            if job.state == JobState::Stopped {
+                job.modes = sys::get_termios(terminal.fd).ok();
+            }
+            let _ = sys::set_termios(terminal.fd, &terminal.modes);
+        }
+
+        if job.interrupted {
+            signals::interrupt();
+        }
+
+        match job.state {
+            JobState::Done(status) => status,
+            _ => {
+                job.changed = false;
+                let id = self.add(job);
+                if let Some(job) = self.get(id) {
+                    let _ = writeln!(err, "\n{}", self.format(job, false));
+                }
+                STOPPED_STATUS
+            }
+        }
+    }
+
+    /// Let a stopped job carry on in the background. Returns `false` if it
+    /// was already running.
+    pub(crate) fn background(&mut self, id: usize) -> bool {
+        self.touch(id);
+        let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) else {
+            return false;
+        };
+        let stopped = job.state == JobState::Stopped;
+        job.resume();
+        stopped
+    }
+
+    /// Wait for a job to finish, giving up on Ctrl-C. A finished job is
+    /// forgotten without being announced. Returns its status, that of a
+    /// stopped job, or `None` when interrupted.
+    pub(crate) fn wait(&mut self, id: usize) -> Option<ExitStatus> {
+        let job = self.jobs.iter_mut().find(|job| job.id == id)?;
+        if !job.wait(true) {
+            return None;
+        }
+        match job.state {
+            JobState::Done(status) => {
+                self.remove(id);
+                Some(status)
+            }
+            _ => Some(STOPPED_STATUS),
+        }
+    }
+}
+
+/// Start an and-or list as a background job, in a forked copy of the
+/// shell. Returns the status of starting it.
+///
+/// A process cannot write into the shell's buffers, so output meant for
+/// them goes through pipes, and threads copy it over until the job is done.
+pub(crate) fn spawn_background(shell: &mut Shell, and_or: &AndOr) -> ExitStatus {
+    let mut stderr = shell.io().stderr.clone();
+    let control = shell.jobs().has_control();
+
+    let mut io = shell.io().clone();
+    if !control && matches!(io.stdin, Input::Stdin) {
+        // Without job control nothing keeps the terminal's input away
+        // from the job, so it does without
+        if let Ok(null) = File::open("/dev/null") {
+            io.stdin = Input::File(Arc::new(null));
+        }
+    }
+    let (mut io, pipes) = match io.for_fork() {
+        Ok(prepared) => prepared,
+        Err(e) => {
+            let _ = writeln!(stderr, "0-shell: {}", e);
+            return 1;
+        }
+    };
+
+    // Anything still buffered would otherwise be written twice
+    let _ = io::stdout().flush();
+    let _ = shell.io().stdout.clone().flush();
+
+    // SAFETY: other threads may be running, such as sibling pipeline
+    // stages, output pumps or those of a program embedding the shell. The
+    // child leaves alone the standard streams and buffers they may hold
+    // locks in, using only `io`, whose streams are plain descriptors, and
+    // ends with exit_immediately.
+    let pid = match unsafe { sys::fork_process() } {
+        Ok(pid) => pid,
+        Err(e) => {
+            let _ = writeln!(stderr, "0-shell: fork: {}", e);
+            return 1;
+        }
+    };
+
+    if pid == 0 {
+        drop(pipes);
+        shell.swap_io(&mut io);
+        let status = run_in_background(shell, and_or, control);
+        sys::exit_immediately(status);
+    }
+
+    // The pipes reach end of file once the job no longer writes to them
+    drop(io);
+    let pumps = pipes.into_iter().map(|(reader, buffer)| streams::pump(reader, buffer)).collect();
+
+    let pgid = control.then_some(pid);
+    if control {
+        // Same as in the child, whichever gets there first
+        let _ = sys::set_process_group(pid, pid);
+    }
+
+    let jobs = shell.jobs_mut();
+    jobs.last_background = Some(pid);
+    let mut job = Job::new(pgid, vec![pid], and_or.to_string());
+    job.pumps = Pumps(Arc::new(Mutex::new(pumps)));
+    let id = jobs.add(job);
+    if control {
+        let _ = writeln!(stderr, "[{}] {}", id, pid);
+    }
+    0
+}
+
+/// What the forked child does: run the commands and flush their output
+fn run_in_background(shell: &mut Shell, and_or: &AndOr, control: bool) -> ExitStatus {
+    let prepared = if control {
+        sys::set_process_group(0, 0).and_then(|_| signals::restore_defaults(true))
+    } else {
+        // Without job control nothing keeps Ctrl-C away from the job
+        signals::ignore_interrupts()
+    };
+    if let Err(e) = prepared {
+        let _ = writeln!(shell.io().stderr.clone(), "0-shell: {}", e);
+    }
+
+    // The copy is a subshell: the jobs are the parent's to manage
+    *shell.jobs_mut() = shell.jobs().for_subshell();
+
+    let status = shell.execute_and_or(and_or);
+    let _ = shell.io().stdout.clone().flush();
+    let _ = shell.io().stderr.clone().flush();
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(commands: &[&str]) -> Jobs {
+        let mut jobs = Jobs::default();
+        for (index, command) in commands.iter().enumerate() {
+            jobs.add(Job::new(None, vec![1000 + index as Pid], command.to_string()));
+        }
+        jobs
+    }
+
+    #[test]
+    fn test_job_specs() {
+        let jobs = table(&["sleep 10", "vim notes", "sleep 20"]);
+
+        assert_eq!(jobs.find(None), Ok(3));
+        assert_eq!(jobs.find(Some("%%")), Ok(3));
+        assert_eq!(jobs.find(Some("%-")), Ok(2));
+        assert_eq!(jobs.find(Some("%1")), Ok(1));
+        assert_eq!(jobs.find(Some("%vim")), Ok(2));
+        assert_eq!(jobs.find(Some("%?20")), Ok(3));
+        assert_eq!(jobs.find(Some("%sleep")), Err("%sleep: ambiguous job spec".to_string()));
+        assert_eq!(jobs.find(Some("%7")), Err("%7: no such job".to_string()));
+        assert_eq!(jobs.find(Some("2")), Err("2: no such job".to_string()));
+        assert_eq!(Jobs::default().find(None), Err("current: no such job".to_string()));
+    }
+
+    #[test]
+    fn test_ids_and_markers() {
+        let mut jobs = table(&["a", "b", "c"]);
+        jobs.remove(2);
+        assert_eq!(jobs.marker(3), '+');
+        assert_eq!(jobs.marker(1), '-');
+
+        // Freed ids below the highest one are not reused
+        assert_eq!(jobs.add(Job::new(None, vec![1], "d".to_string())), 4);
+        assert_eq!(jobs.marker(4), '+');
+        assert_eq!(jobs.marker(3), '-');
+        assert_eq!(jobs.marker(1), ' ');
+    }
+
+    #[test]
+    fn test_format_and_notifications() {
+        let mut jobs = table(&["sleep 10", "make"]);
+        assert_eq!(jobs.format(jobs.get(1).unwrap(), false), "[1]-  Running                 sleep 10 &");
+
+        let job = jobs.jobs.iter_mut().find(|job| job.id == 2).unwrap();
+        job.update(1001, WaitStatus::Exited(2));
+        assert_eq!(jobs.format(jobs.get(2).unwrap(), true), "[2]+ 1001 Exit 2                  make");
+
+        assert_eq!(jobs.take_notifications(), vec!["[2]+  Exit 2                  make"]);
+        assert!(jobs.take_notifications().is_empty());
+        assert!(jobs.get(2).is_none());
+        assert_eq!(jobs.marker(1), '+');
+    }
+}
@@ -0,0 +1,30 @@
+//! 0-shell: a minimalist Unix-like shell
+//!
+//! The interpreter lives in [`Shell`]; the `0-shell` binary is a thin
+//! prompt loop around it. Programs can embed their own shell, add builtins
+//! through [`Shell::register`] and capture output with
+//! [`streams::Buffer`].
+
+pub mod builtins;
+pub mod editor;
+pub mod history;
+pub mod jobs;
+pub mod parser;
+pub mod prompt;
+pub mod signals;
+pub mod streams;
+
+mod arith;
+mod brace;
+mod complete;
+mod compound;
+mod expand;
+mod external;
+mod glob;
+mod pipeline;
+mod redirect;
+mod shell;
+mod sys;
+
+pub use builtins::{Builtin, Context, ExitStatus, Registry};
+pub use shell::{Shell, Variable};
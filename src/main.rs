@@ -1,81 +1,249 @@
-use std::io::{self, Write};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Read};
+use std::path::{Path, PathBuf};
+use std::process;
 
-mod parser;
-mod builtins;
+use zero_shell::editor::{Editor, ReadLine};
+use zero_shell::history::{self, History};
+use zero_shell::{prompt, signals, streams};
+use zero_shell::{ExitStatus, Shell};
 
-use parser::parse_command;
-use builtins::*;
+/// Read by interactive shells, unless started with `--norc`
+const RC_FILES: [&str; 2] = ["/etc/0shellrc", "~/.0shellrc"];
+
+/// Read by login shells, before anything else
+const PROFILE_FILES: [&str; 2] = ["/etc/0shell_profile", "~/.0shell_profile"];
 
 fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let mut shell = Shell::new();
+    shell.set_script_name(args.first().map(String::as_str).unwrap_or("0-shell"));
+
+    // Startup options come before `-c` or the script; `login` starts a
+    // `-0-shell` too, which is how login(1) marks a login shell
+    let mut login = args.first().is_some_and(|name| name.starts_with('-'));
+    let mut rc = true;
+    let options = args
+        .iter()
+        .skip(1)
+        .take_while(|arg| matches!(arg.as_str(), "-l" | "--login" | "--norc"))
+        .count();
+    for option in args.drain(1..1 + options) {
+        match option.as_str() {
+            "--norc" => rc = false,
+            _ => login = true,
+        }
+    }
+
+    if login {
+        source_startup(&mut shell, &PROFILE_FILES);
+        if let Some(status) = shell.exit_requested() {
+            process::exit(status);
+        }
+    }
+
+    let status = match args.get(1).map(String::as_str) {
+        // zero-shell -c 'commands' [name [args...]]
+        Some("-c") => {
+            let Some(command) = args.get(2) else {
+                eprintln!("0-shell: -c: option requires an argument");
+                process::exit(2);
+            };
+            if let Some(name) = args.get(3) {
+                shell.set_script_name(name);
+            }
+            shell.set_positional(args.iter().skip(4).cloned().collect());
+            shell.run_script(command);
+            final_status(&shell)
+        }
+        Some(option) if option.starts_with('-') && option != "-" => {
+            eprintln!("0-shell: {}: invalid option", option);
+            eprintln!("Usage: 0-shell [-l] [--norc] [-c command [name [args...]] | script [args...]]");
+            2
+        }
+        // zero-shell script [args...]
+        Some(path) if path != "-" => {
+            let script = match fs::read(path) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(error) => {
+                    eprintln!("0-shell: {}: {}", path, streams::describe(&error));
+                    let status = if error.kind() == io::ErrorKind::NotFound { 127 } else { 126 };
+                    process::exit(status);
+                }
+            };
+            shell.set_script_name(path);
+            shell.set_positional(args.iter().skip(2).cloned().collect());
+            shell.run_script(&script);
+            final_status(&shell)
+        }
+        _ if io::stdin().is_terminal() => interactive(&mut shell, rc),
+        // Commands piped in: no banner, no prompt
+        _ => {
+            shell.set_positional(args.iter().skip(2).cloned().collect());
+            let mut script = Vec::new();
+            if let Err(error) = io::stdin().read_to_end(&mut script) {
+                eprintln!("0-shell: error reading input: {}", streams::describe(&error));
+                process::exit(1);
+            }
+            shell.run_script(&String::from_utf8_lossy(&script));
+            final_status(&shell)
+        }
+    };
+
+    process::exit(status);
+}
+
+/// Read and run commands typed at a terminal until `exit` or EOF
+fn interactive(shell: &mut Shell, rc: bool) -> ExitStatus {
     println!("0-Shell v0.1.0 - Minimalist Unix-like shell");
-    
+
+    if let Err(error) = shell.jobs_mut().enable_control() {
+        eprintln!("0-shell: no job control: {}", streams::describe(&error));
+    }
+    if let Err(error) = signals::install() {
+        eprintln!("0-shell: cannot install signal handlers: {}", streams::describe(&error));
+    }
+    // After the signal handlers, so Ctrl-C cannot kill the shell while
+    // they run, and before the history, so they can set `$HISTFILE`
+    if rc {
+        source_startup(shell, &RC_FILES);
+        if shell.exit_requested().is_some() {
+            return final_status(shell);
+        }
+    }
+    open_history(shell);
+    let mut editor = Editor::new();
+
     loop {
-        // Display prompt
-        print!("$ ");
-        io::stdout().flush().unwrap();
-        
-        // Read input line
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
-            Ok(0) => {
-                // EOF (Ctrl+D) - exit gracefully
-                println!();
-                break;
-            }
-            Ok(_) => {
-                let input = input.trim();
-                
+        // `[1]+  Done ...` for jobs that finished since the last prompt
+        shell.jobs_mut().reap();
+        for line in shell.jobs_mut().take_notifications() {
+            eprintln!("{}", line);
+        }
+
+        match read_command(shell, &mut editor) {
+            Ok(Some(lines)) => {
+                let input = lines.join("\n");
+
                 // Skip empty lines
-                if input.is_empty() {
+                if input.trim().is_empty() {
                     continue;
                 }
-                
-                // Parse and execute command
-                execute_command(input);
+
+                if let Err(error) = shell.history_mut().add(&history::join_lines(&lines)) {
+                    eprintln!("0-shell: history: {}", streams::describe(&error));
+                }
+                shell.run_line(&input);
+                // Leave the ^C the terminal echoed on a line of its own
+                if signals::interrupted() {
+                    println!();
+                }
+                if shell.exit_requested().is_some() {
+                    break;
+                }
             }
+            Ok(None) => break,
             Err(error) => {
-                eprintln!("Error reading input: {}", error);
+                eprintln!("0-shell: error reading input: {}", streams::describe(&error));
+                break;
             }
         }
     }
+
+    final_status(shell)
 }
 
-fn execute_command(input: &str) {
-    let (command, args) = parse_command(input);
-    
-    match command {
-        "exit" => {
-            std::process::exit(0);
-        }
-        "pwd" => {
-            pwd::execute(&args);
-        }
-        "cd" => {
-            cd::execute(&args);
-        }
-        "echo" => {
-            echo::execute(&args);
-        }
-        "ls" => {
-            ls::execute(&args);
-        }
-        "cat" => {
-            cat::execute(&args);
-        }
-        "cp" => {
-            cp::execute(&args);
-        }
-        "rm" => {
-            rm::execute(&args);
+/// Read one command at the prompt, which may take several lines: as long
+/// as the text so far is incomplete, like an `if` without its `fi` or an
+/// open quote, more lines are read with the `PS2` prompt
+///
+/// `!!` and friends are expanded in each line, and the expanded line is
+/// shown. Ctrl-C or a failed expansion abandons the command, giving no
+/// lines; `None` is the end of input.
+fn read_command(shell: &Shell, editor: &mut Editor) -> io::Result<Option<Vec<String>>> {
+    let mut lines: Vec<String> = Vec::new();
+
+    loop {
+        let prompt = if lines.is_empty() {
+            prompt::primary(shell)
+        } else {
+            prompt::continuation(shell)
+        };
+        let line = match editor.read_line(&prompt, shell.history().entries(), shell)? {
+            ReadLine::Line(line) => line,
+            // Run what there is, which reports the unexpected end of file
+            ReadLine::Eof if !lines.is_empty() => break,
+            ReadLine::Eof => return Ok(None),
+            ReadLine::Interrupted => return Ok(Some(Vec::new())),
+        };
+
+        let line = match shell.history().expand(&line) {
+            Ok(Some(expanded)) => {
+                println!("{}", expanded);
+                expanded
+            }
+            Ok(None) => line,
+            Err(message) => {
+                eprintln!("0-shell: {}", message);
+                return Ok(Some(Vec::new()));
+            }
+        };
+        lines.push(line);
+
+        if !shell.parse(&lines.join("\n")).is_err_and(|error| error.is_incomplete()) {
+            break;
         }
-        "mv" => {
-            mv::execute(&args);
+    }
+
+    Ok(Some(lines))
+}
+
+/// Run the startup files that exist, in order; `~/` stands for `$HOME`
+fn source_startup(shell: &mut Shell, files: &[&str]) {
+    for file in files {
+        let path = match file.strip_prefix("~/") {
+            Some(rest) => match shell.var("HOME") {
+                Some(home) => Path::new(home).join(rest),
+                None => continue,
+            },
+            None => PathBuf::from(file),
+        };
+
+        match shell.source(&path) {
+            Ok(_) => {}
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => eprintln!("0-shell: {}: {}", path.display(), streams::describe(&error)),
         }
-        "mkdir" => {
-            mkdir::execute(&args);
+        if shell.exit_requested().is_some() {
+            return;
         }
-        _ => {
-            println!("Command '{}' not found", command);
+    }
+}
+
+/// Load the history from `$HISTFILE` (by default `~/.0shell_history`),
+/// keeping at most `$HISTSIZE` entries
+fn open_history(shell: &mut Shell) {
+    let path = match (shell.var("HISTFILE"), shell.var("HOME")) {
+        (Some(file), _) => PathBuf::from(file),
+        (None, Some(home)) => Path::new(home).join(".0shell_history"),
+        (None, None) => return,
+    };
+    let limit = shell
+        .var("HISTSIZE")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(History::DEFAULT_SIZE);
+
+    match History::open(&path, limit) {
+        Ok(history) => *shell.history_mut() = history,
+        Err(error) => {
+            eprintln!("0-shell: {}: {}", path.display(), streams::describe(&error));
         }
     }
 }
+
+/// The status the process exits with: the one given to `exit`, or else
+/// that of the last command
+fn final_status(shell: &Shell) -> ExitStatus {
+    shell.exit_requested().unwrap_or(shell.last_status())
+}
@@ -1,44 +1,1917 @@
-/// Simple command parser that splits input by whitespace
-/// Returns (command, args) where command is the first token and args are the rest
-pub fn parse_command(input: &str) -> (&str, Vec<&str>) {
-    let tokens: Vec<&str> = input.split_whitespace().collect();
-    
-    if tokens.is_empty() {
-        return ("", vec![]);
-    }
-    
-    let command = tokens[0];
-    let args = if tokens.len() > 1 {
-        tokens[1..].to_vec()
-    } else {
-        vec![]
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Arc;
+
+/// Errors that can occur while tokenizing a command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A single or double quote was opened but never closed
+    UnterminatedQuote(char),
+    /// The input ended with a backslash that has nothing to escape
+    TrailingBackslash,
+    /// An operator appeared where a command was expected
+    UnexpectedToken(String),
+    /// The input ended inside a compound command, as in an `if` without
+    /// its `fi`
+    UnexpectedEof,
+    /// The input ended before the line closing a here-document, which
+    /// holds its delimiter
+    UnterminatedHereDoc(String),
+    /// Compound commands and command substitutions nested more than
+    /// `MAX_NESTING_DEPTH` deep
+    NestedTooDeep,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote(quote) => {
+                write!(f, "unexpected EOF while looking for matching `{}'", quote)
+            }
+            ParseError::TrailingBackslash => {
+                write!(f, "unexpected EOF after `\\'")
+            }
+            ParseError::UnexpectedToken(token) => {
+                write!(f, "syntax error near unexpected token `{}'", token)
+            }
+            ParseError::UnexpectedEof => write!(f, "syntax error: unexpected end of file"),
+            ParseError::UnterminatedHereDoc(delimiter) => {
+                write!(f, "unexpected EOF while looking for here-document delimiter `{}'", delimiter)
+            }
+            ParseError::NestedTooDeep => write!(f, "syntax error: commands nested too deeply"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl ParseError {
+    /// Whether more input could complete the command: an open quote, a
+    /// trailing backslash, an unfinished compound command or here-document.
+    /// Scripts and the prompt then read another line instead of reporting
+    /// the error.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            ParseError::UnterminatedQuote(_)
+                | ParseError::TrailingBackslash
+                | ParseError::UnexpectedEof
+                | ParseError::UnterminatedHereDoc(_)
+        )
+    }
+}
+
+/// A lexical unit of a command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A word exactly as written, quotes and escapes included;
+    /// they are removed by `expand` when the command runs
+    Word(String),
+    /// The `|` pipeline operator
+    Pipe,
+    /// The `&&` operator - run the next pipeline only on success
+    AndIf,
+    /// The `||` operator - run the next pipeline only on failure
+    OrIf,
+    /// The `;` command separator
+    Semicolon,
+    /// A newline, which separates commands like `;`
+    Newline,
+    /// The `;;` that ends a `case` branch
+    DoubleSemicolon,
+    /// `(`, which opens a subshell or a `case` pattern
+    LeftParen,
+    /// `)`, which closes a subshell or a `case` pattern
+    RightParen,
+    /// The `&` terminator - run the preceding and-or list in the background
+    Ampersand,
+    /// A redirection operator, with the file descriptor written before it
+    /// (as in `2>`) if there was one
+    Redirect(Option<u32>, RedirectKind),
+}
+
+/// The different redirection operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<` - read from a file
+    Read,
+    /// `>` - write to a file, truncating it
+    Write,
+    /// `>>` - append to a file
+    Append,
+    /// `>&` - duplicate an output descriptor (`2>&1`)
+    DupOutput,
+    /// `<&` - duplicate an input descriptor
+    DupInput,
+    /// `&>` - send both stdout and stderr to a file
+    WriteBoth,
+    /// `&>>` - append both stdout and stderr to a file
+    AppendBoth,
+    /// `<<` or `<<-` - read the lines that follow, up to a delimiter line;
+    /// the target is that text, in which `$` and backquotes are expanded
+    HereDoc,
+    /// A here-document whose delimiter was quoted, as in `<<'EOF'`, and
+    /// whose text is taken literally
+    QuotedHereDoc,
+    /// `<<<` - read a word, with a newline added
+    HereString,
+}
+
+impl RedirectKind {
+    /// The descriptor a redirection applies to when none is written
+    pub fn default_fd(self) -> u32 {
+        match self {
+            RedirectKind::Read
+            | RedirectKind::DupInput
+            | RedirectKind::HereDoc
+            | RedirectKind::QuotedHereDoc
+            | RedirectKind::HereString => 0,
+            _ => 1,
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            RedirectKind::Read => "<",
+            RedirectKind::Write => ">",
+            RedirectKind::Append => ">>",
+            RedirectKind::DupOutput => ">&",
+            RedirectKind::DupInput => "<&",
+            RedirectKind::WriteBoth => "&>",
+            RedirectKind::AppendBoth => "&>>",
+            RedirectKind::HereDoc | RedirectKind::QuotedHereDoc => "<<",
+            RedirectKind::HereString => "<<<",
+        }
+    }
+
+    /// Whether the target is the text of a here-document rather than a word
+    pub fn is_here_doc(self) -> bool {
+        matches!(self, RedirectKind::HereDoc | RedirectKind::QuotedHereDoc)
+    }
+}
+
+/// A redirection attached to a command, such as `2>> errors.log`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub fd: u32,
+    pub kind: RedirectKind,
+    pub target: String,
+}
+
+/// A `NAME=value` word in front of a command; the value is kept as
+/// written and expanded when the command runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: String,
+}
+
+/// A single command: its variable assignments, its words (name first) and
+/// its redirections
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// One command of a pipeline
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// A compound command with the redirections written after it, which
+    /// apply to everything inside
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// `name() compound_command`, defining a function whose body is the
+    /// (compound) command; the body is shared with the shell that stores it
+    Function(String, Arc<Command>),
+}
+
+/// The commands built from reserved words
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompoundCommand {
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    If {
+        branches: Vec<(List, List)>,
+        otherwise: Option<List>,
+    },
+    /// `while list; do list; done`, or `until` when `until` is set
+    Loop { condition: List, body: List, until: bool },
+    /// `for name [in word ...]; do list; done`; without `in` the loop
+    /// goes over the positional parameters
+    For {
+        name: String,
+        words: Option<Vec<String>>,
+        body: List,
+    },
+    /// `case word in [(]pattern[|pattern]...) list;; ... esac`
+    Case { word: String, arms: Vec<CaseArm> },
+    /// `{ list; }`, run as one command
+    Group(List),
+    /// `( list )`, run in a copy of the shell so that its changes to
+    /// variables, the directory and the like do not last
+    Subshell(List),
+}
+
+/// One branch of a `case` command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseArm {
+    /// Patterns as written, matched against the word once expanded
+    pub patterns: Vec<String>,
+    pub body: List,
+}
+
+/// Commands connected by `|`, each one's stdout feeding the next one's stdin
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+/// How a pipeline in an and-or list is joined to the one before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&` - run when the previous status was zero
+    And,
+    /// `||` - run when the previous status was non-zero
+    Or,
+}
+
+/// Pipelines joined by `&&` and `||`, evaluated left to right
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Whether it was terminated by `&`, to run as a background job
+    pub background: bool,
+}
+
+/// A sequence of and-or lists separated by `;` or `&`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct List {
+    pub items: Vec<AndOr>,
+}
+
+impl fmt::Display for Redirect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.fd != self.kind.default_fd() {
+            write!(f, "{}", self.fd)?;
+        }
+        // The original delimiter is gone, so here-documents get a new one
+        match self.kind {
+            RedirectKind::HereDoc => write!(f, "<<EOF\n{}EOF", self.target),
+            RedirectKind::QuotedHereDoc => write!(f, "<<'EOF'\n{}EOF", self.target),
+            kind => write!(f, "{}{}", kind.symbol(), self.target),
+        }
+    }
+}
+
+impl fmt::Display for SimpleCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let assignments = self.assignments.iter().map(|a| format!("{}={}", a.name, a.value));
+        let words = self.words.iter().cloned();
+        let redirects = self.redirects.iter().map(Redirect::to_string);
+        let parts: Vec<String> = assignments.chain(words).chain(redirects).collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Simple(command) => write!(f, "{}", command),
+            Command::Compound(command, redirects) => {
+                write!(f, "{}", command)?;
+                for redirect in redirects {
+                    write!(f, " {}", redirect)?;
+                }
+                Ok(())
+            }
+            Command::Function(name, body) => write!(f, "{}() {}", name, body),
+        }
+    }
+}
+
+impl fmt::Display for CompoundCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompoundCommand::If { branches, otherwise } => {
+                for (index, (condition, body)) in branches.iter().enumerate() {
+                    let keyword = if index == 0 { "if" } else { "elif" };
+                    write!(f, "{} {}then {}", keyword, Terminated(condition), Terminated(body))?;
+                }
+                if let Some(otherwise) = otherwise {
+                    write!(f, "else {}", Terminated(otherwise))?;
+                }
+                write!(f, "fi")
+            }
+            CompoundCommand::Loop { condition, body, until } => {
+                let keyword = if *until { "until" } else { "while" };
+                write!(f, "{} {}do {}done", keyword, Terminated(condition), Terminated(body))
+            }
+            CompoundCommand::For { name, words, body } => {
+                write!(f, "for {}", name)?;
+                if let Some(words) = words {
+                    write!(f, " in")?;
+                    for word in words {
+                        write!(f, " {}", word)?;
+                    }
+                }
+                write!(f, "; do {}done", Terminated(body))
+            }
+            CompoundCommand::Case { word, arms } => {
+                write!(f, "case {} in ", word)?;
+                for arm in arms {
+                    write!(f, "{}) ", arm.patterns.join(" | "))?;
+                    if !arm.body.items.is_empty() {
+                        write!(f, "{} ", arm.body)?;
+                    }
+                    write!(f, ";; ")?;
+                }
+                write!(f, "esac")
+            }
+            CompoundCommand::Group(list) => write!(f, "{{ {}}}", Terminated(list)),
+            CompoundCommand::Subshell(list) => write!(f, "({})", list),
+        }
+    }
+}
+
+/// A list followed by the separator that lets a keyword come next: `;`,
+/// unless it already ends in `&`
+struct Terminated<'a>(&'a List);
+
+impl fmt::Display for Terminated<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.items.last() {
+            Some(last) if last.background => write!(f, "{} ", self.0),
+            _ => write!(f, "{}; ", self.0),
+        }
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let commands: Vec<String> = self.commands.iter().map(Command::to_string).collect();
+        write!(f, "{}", commands.join(" | "))
+    }
+}
+
+/// Written back the way it could have been typed, for the jobs table
+impl fmt::Display for AndOr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.first)?;
+        for (connector, pipeline) in &self.rest {
+            let operator = match connector {
+                Connector::And => "&&",
+                Connector::Or => "||",
+            };
+            write!(f, " {} {}", operator, pipeline)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, item) in self.items.iter().enumerate() {
+            if index > 0 {
+                let separator = if self.items[index - 1].background { " " } else { "; " };
+                write!(f, "{}", separator)?;
+            }
+            write!(f, "{}", item)?;
+            if item.background {
+                write!(f, " &")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Token {
+    /// How the token is written, for error messages
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => word.clone(),
+            Token::Pipe => "|".to_string(),
+            Token::AndIf => "&&".to_string(),
+            Token::OrIf => "||".to_string(),
+            Token::Semicolon => ";".to_string(),
+            Token::Newline => "newline".to_string(),
+            Token::DoubleSemicolon => ";;".to_string(),
+            Token::LeftParen => "(".to_string(),
+            Token::RightParen => ")".to_string(),
+            Token::Ampersand => "&".to_string(),
+            Token::Redirect(_, kind) => kind.symbol().to_string(),
+        }
+    }
+}
+
+/// Split a command line into tokens, honouring quotes and backslash escapes
+///
+/// - Whitespace outside of quotes separates words; a newline is also a
+///   token of its own, and a backslash-newline joins two lines
+/// - Unquoted `|`, `||`, `&&`, `;`, `;;`, `&`, `(`, `)`, `<`, `>`, `>>`,
+///   `>&`, `<&`, `&>`, `&>>`, `<<`, `<<-` and `<<<` are operator tokens,
+///   even without surrounding spaces
+/// - A run of digits directly before `<` or `>` names the file descriptor
+///   to redirect, as in `2>&1`
+/// - Quotes and backslashes keep operators and whitespace inside a word;
+///   `''` and `""` still start a (possibly empty) word
+/// - A command substitution, `$(...)` or `` `...` ``, is part of the word
+///   it appears in, whatever it contains
+/// - The lines after one with here-documents are their text, up to each
+///   one's delimiter line. The word after `<<` is replaced by that text, and
+///   a quoted delimiter turns the operator into a `QuotedHereDoc`.
+///
+/// Words are returned as written. Quote removal happens in `expand`, so
+/// that `'$?'` and `$?` can still be told apart when the command runs.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut lexer = Lexer::default();
+    let mut chars = input.chars().peekable();
+    while let Some(ch) = chars.next() {
+        lexer.read(ch, &mut chars)?;
+    }
+    lexer.finish()
+}
+
+/// What `tokenize` knows between two characters, so that the commands of
+/// a substitution can be tokenized just as far as they go
+#[derive(Default)]
+struct Lexer {
+    tokens: Vec<Token>,
+    current: String,
+    /// Tracks whether a word has started, so that `""` yields an empty token
+    in_word: bool,
+    /// Whether any part of the current word was quoted or escaped
+    quoted: bool,
+    /// The here-documents whose text starts on the next line: the index of
+    /// their operator and whether it was `<<-`
+    here_docs: Vec<(usize, bool)>,
+    /// How many compound commands and command substitutions the text
+    /// being read is inside of
+    nesting: usize,
+}
+
+impl Lexer {
+    /// Take in `ch`, just read from `chars`, along with the characters
+    /// after it that belong to the same operator, quote or substitution
+    fn read(&mut self, ch: char, chars: &mut Peekable<Chars>) -> Result<(), ParseError> {
+        match ch {
+            '\n' => {
+                self.end_word();
+                self.tokens.push(Token::Newline);
+                for (index, strip_tabs) in std::mem::take(&mut self.here_docs) {
+                    read_here_doc(&mut self.tokens, index, strip_tabs, chars)?;
+                }
+            }
+            c if c.is_whitespace() => self.end_word(),
+            '|' | '<' | '>' | '&' | ';' | '(' | ')' => {
+                // A bare number right before `<` or `>` is a descriptor
+                let mut fd = None;
+                if self.in_word && !self.quoted && (ch == '<' || ch == '>') {
+                    if let Ok(number) = self.current.parse::<u32>() {
+                        if self.current.bytes().all(|b| b.is_ascii_digit()) {
+                            fd = Some(number);
+                            self.current.clear();
+                            self.in_word = false;
+                        }
+                    }
+                }
+                self.end_word();
+
+                let token = match ch {
+                    ';' if chars.next_if_eq(&';').is_some() => Token::DoubleSemicolon,
+                    ';' => Token::Semicolon,
+                    '(' => Token::LeftParen,
+                    ')' => Token::RightParen,
+                    '|' if chars.next_if_eq(&'|').is_some() => Token::OrIf,
+                    '|' => Token::Pipe,
+                    '<' if chars.next_if_eq(&'&').is_some() => {
+                        Token::Redirect(fd, RedirectKind::DupInput)
+                    }
+                    '<' if chars.next_if_eq(&'<').is_some() => {
+                        if chars.next_if_eq(&'<').is_some() {
+                            Token::Redirect(fd, RedirectKind::HereString)
+                        } else {
+                            let strip_tabs = chars.next_if_eq(&'-').is_some();
+                            self.here_docs.push((self.tokens.len(), strip_tabs));
+                            Token::Redirect(fd, RedirectKind::HereDoc)
+                        }
+                    }
+                    '<' => Token::Redirect(fd, RedirectKind::Read),
+                    '>' if chars.next_if_eq(&'>').is_some() => {
+                        Token::Redirect(fd, RedirectKind::Append)
+                    }
+                    '>' if chars.next_if_eq(&'&').is_some() => {
+                        Token::Redirect(fd, RedirectKind::DupOutput)
+                    }
+                    '>' => Token::Redirect(fd, RedirectKind::Write),
+                    _ if chars.next_if_eq(&'&').is_some() => Token::AndIf,
+                    _ if chars.next_if_eq(&'>').is_some() => {
+                        if chars.next_if_eq(&'>').is_some() {
+                            Token::Redirect(None, RedirectKind::AppendBoth)
+                        } else {
+                            Token::Redirect(None, RedirectKind::WriteBoth)
+                        }
+                    }
+                    _ => Token::Ampersand,
+                };
+                self.tokens.push(token);
+            }
+            '\\' => match chars.next() {
+                // A line ending in a backslash continues on the next one
+                Some('\n') if chars.peek().is_some() => {}
+                Some('\n') | None => return Err(ParseError::TrailingBackslash),
+                Some(escaped) => {
+                    self.current.push('\\');
+                    self.current.push(escaped);
+                    self.in_word = true;
+                    self.quoted = true;
+                }
+            },
+            '\'' => {
+                self.current.push('\'');
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => self.current.push(c),
+                        None => return Err(ParseError::UnterminatedQuote('\'')),
+                    }
+                }
+                self.current.push('\'');
+                self.in_word = true;
+                self.quoted = true;
+            }
+            '"' => {
+                self.current.push('"');
+                scan_double_quoted(chars, &mut self.current, self.nesting)?;
+                self.in_word = true;
+                self.quoted = true;
+            }
+            '$' if chars.next_if_eq(&'(').is_some() => {
+                self.current.push_str("$(");
+                scan_substitution(chars, &mut self.current, self.nesting)?;
+                self.in_word = true;
+            }
+            '`' => {
+                self.current.push('`');
+                scan_backquoted(chars, &mut self.current)?;
+                self.in_word = true;
+            }
+            // A `#` starting a word comments out the rest of the line
+            '#' if !self.in_word => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            c => {
+                self.current.push(c);
+                self.in_word = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn end_word(&mut self) {
+        if self.in_word {
+            self.tokens.push(Token::Word(std::mem::take(&mut self.current)));
+            self.in_word = false;
+            self.quoted = false;
+        }
+    }
+
+    /// The tokens, once the input has ended
+    fn finish(mut self) -> Result<Vec<Token>, ParseError> {
+        self.end_word();
+        // A here-document needs at least the line with its delimiter
+        if let Some(&(index, _)) = self.here_docs.first() {
+            if let Some(Token::Word(word)) = self.tokens.get(index + 1) {
+                return Err(ParseError::UnterminatedHereDoc(here_doc_delimiter(word).0));
+            }
+        }
+        Ok(self.tokens)
+    }
+}
+
+/// Read the text of the here-document whose operator is `tokens[index]`
+/// from the lines that follow, up to its delimiter line, and put it in
+/// place of the delimiter word
+///
+/// With `<<-`, leading tabs are removed from every line, delimiter
+/// included. Without a delimiter word, the parser reports the operator.
+fn read_here_doc(
+    tokens: &mut [Token],
+    index: usize,
+    strip_tabs: bool,
+    chars: &mut Peekable<Chars>,
+) -> Result<(), ParseError> {
+    let Some(Token::Word(word)) = tokens.get(index + 1) else {
+        return Ok(());
+    };
+    let (delimiter, quoted) = here_doc_delimiter(word);
+
+    let mut text = String::new();
+    loop {
+        if chars.peek().is_none() {
+            return Err(ParseError::UnterminatedHereDoc(delimiter));
+        }
+        let mut line = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '\n') {
+            line.push(c);
+        }
+        let ended = chars.next().is_some();
+
+        let line = if strip_tabs { line.trim_start_matches('\t') } else { &line };
+        if line == delimiter {
+            break;
+        }
+        if !ended {
+            return Err(ParseError::UnterminatedHereDoc(delimiter));
+        }
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    if quoted {
+        if let Token::Redirect(_, kind) = &mut tokens[index] {
+            *kind = RedirectKind::QuotedHereDoc;
+        }
+    }
+    tokens[index + 1] = Token::Word(text);
+    Ok(())
+}
+
+/// The delimiter a here-document word stands for, with its quotes
+/// removed, and whether any part of it was quoted
+fn here_doc_delimiter(word: &str) -> (String, bool) {
+    let mut delimiter = String::new();
+    let mut quoted = false;
+    let mut chars = word.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                quoted = true;
+                delimiter.extend(chars.by_ref().take_while(|&c| c != '\''));
+            }
+            '"' => {
+                quoted = true;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => delimiter.extend(chars.next()),
+                        c => delimiter.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                quoted = true;
+                delimiter.extend(chars.next());
+            }
+            c => delimiter.push(c),
+        }
+    }
+    (delimiter, quoted)
+}
+
+/// Copy the rest of a double-quoted string, whose opening `"` was just
+/// read, into `out`, up to and including the closing quote
+fn scan_double_quoted(chars: &mut Peekable<Chars>, out: &mut String, nesting: usize) -> Result<(), ParseError> {
+    while let Some(c) = chars.next() {
+        out.push(c);
+        match c {
+            '"' => return Ok(()),
+            '\\' => match chars.next() {
+                Some(escaped) => out.push(escaped),
+                None => break,
+            },
+            '$' if chars.next_if_eq(&'(').is_some() => {
+                out.push('(');
+                scan_substitution(chars, out, nesting)?;
+            }
+            '`' => scan_backquoted(chars, out)?,
+            _ => {}
+        }
+    }
+    Err(ParseError::UnterminatedQuote('"'))
+}
+
+/// Copy the rest of a `$(...)` command substitution, whose `$(` was just
+/// read, into `out`, up to and including the closing parenthesis
+///
+/// The commands inside are tokenized and parsed as they go, so the `)`
+/// that closes is the one after the last complete command: not one in
+/// quotes or a comment, nor the end of a `case` pattern or the `()` of a
+/// function. Invalid commands end at the first `)` that balances the
+/// parentheses before it, and running them reports the error. In
+/// `$((...))`, which is arithmetic, parentheses only group.
+///
+/// `nesting` counts the compound commands and substitutions around this
+/// one, which may be no more than `MAX_NESTING_DEPTH`.
+pub(crate) fn scan_substitution(
+    chars: &mut Peekable<Chars>,
+    out: &mut String,
+    nesting: usize,
+) -> Result<(), ParseError> {
+    if nesting >= MAX_NESTING_DEPTH {
+        return Err(ParseError::NestedTooDeep);
+    }
+    if chars.peek() == Some(&'(') {
+        let mut lookahead = chars.clone();
+        let mut text = String::new();
+        let scanned = scan_parenthesized(&mut lookahead, &mut text, nesting);
+        if scanned.is_ok() && text.strip_suffix(')').and_then(arithmetic_expression).is_some() {
+            *chars = lookahead;
+            out.push_str(&text);
+            return Ok(());
+        }
+    }
+
+    let start = chars.clone();
+    let aliases = BTreeMap::new();
+    let mut lexer = Lexer::default();
+    let mut error = None;
+    let parsed = {
+        // Tokens go to the parser as soon as they are read, but not before
+        // the here-documents they start have been
+        let mut more = |nesting| {
+            lexer.nesting = nesting;
+            // The input after an error is not read as more commands
+            if error.is_some() {
+                return None;
+            }
+            loop {
+                if !lexer.tokens.is_empty() && lexer.here_docs.is_empty() {
+                    return Some(std::mem::take(&mut lexer.tokens));
+                }
+                let ch = chars.next()?;
+                if let Err(e) = lexer.read(ch, chars) {
+                    error = Some(e);
+                    return None;
+                }
+            }
+        };
+        let mut parser = Parser::new(Vec::new(), &aliases);
+        parser.outer = nesting + 1;
+        parser.more = Some(&mut more);
+        parser
+            .parse_sequence(&[])
+            .map(|_| parser.next_if_eq(&Token::RightParen))
     };
-    
-    (command, args)
+    if let Some(error) = error {
+        return Err(error);
+    }
+
+    match parsed {
+        Ok(true) => {
+            let length = start.clone().count() - chars.clone().count();
+            out.extend(start.take(length));
+            Ok(())
+        }
+        Ok(false) if chars.peek().is_none() => Err(ParseError::UnterminatedQuote(')')),
+        Err(error) if error.is_incomplete() => Err(ParseError::UnterminatedQuote(')')),
+        Err(ParseError::NestedTooDeep) => Err(ParseError::NestedTooDeep),
+        // Invalid commands end at the first `)` that balances, and are
+        // reported when they run
+        _ => {
+            *chars = start;
+            scan_parenthesized(chars, out, nesting)
+        }
+    }
+}
+
+/// Copy the rest of a parenthesized text, whose `(` was just read, into
+/// `out`, up to and including the matching `)`, skipping over quotes
+fn scan_parenthesized(chars: &mut Peekable<Chars>, out: &mut String, nesting: usize) -> Result<(), ParseError> {
+    let mut depth = 1;
+
+    while let Some(c) = chars.next() {
+        out.push(c);
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(());
+                }
+            }
+            '\\' => match chars.next() {
+                Some(escaped) => out.push(escaped),
+                None => return Err(ParseError::TrailingBackslash),
+            },
+            '\'' => loop {
+                match chars.next() {
+                    Some(c) => {
+                        out.push(c);
+                        if c == '\'' {
+                            break;
+                        }
+                    }
+                    None => return Err(ParseError::UnterminatedQuote('\'')),
+                }
+            },
+            '"' => scan_double_quoted(chars, out, nesting)?,
+            '`' => scan_backquoted(chars, out)?,
+            _ => {}
+        }
+    }
+    Err(ParseError::UnterminatedQuote(')'))
+}
+
+/// The expression of `$((expression))`, given what is inside the outer
+/// parentheses, or `None` for a command substitution such as
+/// `$((cd /tmp); ls)` whose first parenthesis closes early
+pub(crate) fn arithmetic_expression(inner: &str) -> Option<&str> {
+    let expression = inner.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0usize;
+    for c in expression.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            _ => {}
+        }
+    }
+    Some(expression)
+}
+
+/// Copy the rest of a `` `...` `` command substitution, whose opening
+/// backquote was just read, into `out`, up to and including the closing one
+pub(crate) fn scan_backquoted(chars: &mut Peekable<Chars>, out: &mut String) -> Result<(), ParseError> {
+    while let Some(c) = chars.next() {
+        out.push(c);
+        match c {
+            '`' => return Ok(()),
+            '\\' => match chars.next() {
+                Some(escaped) => out.push(escaped),
+                None => break,
+            },
+            _ => {}
+        }
+    }
+    Err(ParseError::UnterminatedQuote('`'))
+}
+
+/// Whether `name` can be used as a variable name: a letter or underscore
+/// followed by letters, digits and underscores
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric())
+}
+
+/// The words that begin or continue compound commands, recognised only
+/// as the first word of a command
+const RESERVED_WORDS: [&str; 14] = [
+    "if", "then", "elif", "else", "fi", "while", "until", "for", "do", "done", "case", "esac", "{", "}",
+];
+
+/// Whether `word`, as written, is a reserved word such as `if` or `done`
+pub fn is_reserved(word: &str) -> bool {
+    RESERVED_WORDS.contains(&word)
+}
+
+/// The reserved words that start a compound command, and so a function
+/// body; a subshell can be one too
+const COMPOUND_STARTS: [&str; 6] = ["if", "while", "until", "for", "case", "{"];
+
+/// Whether `name` can be defined as a function: a word without quotes,
+/// expansions or `/` that is not a reserved word. Like bash, this allows
+/// names such as `git-prompt` that are not valid variable names.
+pub fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !is_reserved(name)
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_!%,@.-+:".contains(c))
+}
+
+/// Recognise a raw word of the form `NAME=value`. The name must be written
+/// without quotes, so `'A=b'` stays an ordinary word.
+fn split_assignment(word: &str) -> Option<Assignment> {
+    let (name, value) = word.split_once('=')?;
+    is_name(name).then(|| Assignment {
+        name: name.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Quote a string so that the tokenizer reads it back as one word with
+/// the same text, for output that can be fed to the shell again
+pub fn quote(text: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-./,:=@%+".contains(c);
+    if !text.is_empty() && text.chars().all(plain) {
+        return text.to_string();
+    }
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Parse a command line into a list of and-or lists
+/// An empty line yields an empty list
+pub fn parse(input: &str) -> Result<List, ParseError> {
+    parse_with_aliases(input, &BTreeMap::new())
+}
+
+/// Parse a command line, replacing aliases in command-name position
+///
+/// An unquoted command name that is an alias is replaced by the tokens of
+/// its value, which may in turn start with another alias. As in POSIX
+/// shells, an alias is not expanded again inside its own replacement, so
+/// `alias ls='ls -F'` and aliases that refer to each other terminate, and
+/// a value ending in a blank makes the word after it eligible too.
+pub fn parse_with_aliases(input: &str, aliases: &BTreeMap<String, String>) -> Result<List, ParseError> {
+    let tokens = tokenize(input)?;
+    Parser::new(tokens, aliases).parse_program()
+}
+
+/// A token waiting to be parsed
+struct Queued {
+    token: Token,
+    /// The aliases whose expansion produced the token, which must not be
+    /// expanded again in it
+    aliases: Vec<String>,
+    /// Whether it ends an alias value that ended in a blank, so the word
+    /// after it is checked for aliases too
+    check_next: bool,
+}
+
+/// How deeply compound commands may nest, as each level takes stack to
+/// parse and to run
+const MAX_NESTING_DEPTH: usize = 128;
+
+/// Recursive-descent parser over the tokens of one command line
+struct Parser<'a> {
+    tokens: VecDeque<Queued>,
+    aliases: &'a BTreeMap<String, String>,
+    /// How many compound commands are open, so that running out of input
+    /// inside one is reported as incomplete
+    depth: usize,
+    /// How many compound commands and command substitutions are open
+    /// around the text being parsed, which count towards `MAX_NESTING_DEPTH`
+    outer: usize,
+    /// Where the next tokens come from once `tokens` runs out, when they
+    /// are only tokenized as far as the parser reads. It is told how deeply
+    /// they are nested.
+    more: Option<&'a mut dyn FnMut(usize) -> Option<Vec<Token>>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: Vec<Token>, aliases: &'a BTreeMap<String, String>) -> Self {
+        let tokens = tokens
+            .into_iter()
+            .map(|token| Queued {
+                token,
+                aliases: Vec::new(),
+                check_next: false,
+            })
+            .collect();
+        Parser {
+            tokens,
+            aliases,
+            depth: 0,
+            outer: 0,
+            more: None,
+        }
+    }
+
+    /// Make sure that at least `count` tokens are queued, unless the input
+    /// ends first
+    fn fill(&mut self, count: usize) {
+        while self.tokens.len() < count {
+            let nesting = self.outer + self.depth;
+            let Some(tokens) = self.more.as_mut().and_then(|more| more(nesting)) else {
+                return;
+            };
+            self.tokens.extend(tokens.into_iter().map(|token| Queued {
+                token,
+                aliases: Vec::new(),
+                check_next: false,
+            }));
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.fill(1);
+        self.tokens.front().map(|queued| &queued.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        self.fill(1);
+        self.tokens.pop_front().map(|queued| queued.token)
+    }
+
+    fn next_if_eq(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.tokens.pop_front();
+        }
+        found
+    }
+
+    /// Replace the next token by the tokens of its alias value, if it is an
+    /// unquoted word naming an alias that is not already being expanded
+    fn expand_alias(&mut self) -> Result<bool, ParseError> {
+        self.fill(1);
+        let Some(Queued {
+            token: Token::Word(word),
+            aliases: active,
+            ..
+        }) = self.tokens.front()
+        else {
+            return Ok(false);
+        };
+        let Some(value) = self.aliases.get(word) else {
+            return Ok(false);
+        };
+        if active.contains(word) || is_reserved(word) {
+            return Ok(false);
+        }
+
+        let mut active = active.clone();
+        active.push(word.clone());
+        let replacement = tokenize(value)?;
+        let check_next = value.ends_with([' ', '\t']);
+
+        let last = self.tokens.pop_front().map(|queued| queued.check_next);
+        let count = replacement.len();
+        for (i, token) in replacement.into_iter().enumerate().rev() {
+            self.tokens.push_front(Queued {
+                token,
+                aliases: active.clone(),
+                check_next: i + 1 == count && (check_next || last == Some(true)),
+            });
+        }
+        Ok(true)
+    }
+
+    /// program := sequence, followed by nothing else
+    fn parse_program(&mut self) -> Result<List, ParseError> {
+        let list = self.parse_sequence(&[])?;
+        match self.peek() {
+            None => Ok(list),
+            Some(token) => Err(ParseError::UnexpectedToken(token.describe())),
+        }
+    }
+
+    /// sequence := and_or ((';' | '&' | newline) and_or)* [';' | '&' | newline]
+    ///
+    /// Blank lines are skipped. The sequence ends at the end of the input,
+    /// before one of the reserved `terminators` in command position, or at
+    /// a token that cannot follow or start a command, such as the `)` of a
+    /// subshell, which the caller deals with.
+    fn parse_sequence(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let mut list = List::default();
+
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None | Some(Token::DoubleSemicolon | Token::RightParen) => break,
+                Some(Token::Word(word)) if terminators.contains(&word.as_str()) => break,
+                _ => {}
+            }
+
+            let mut and_or = self.parse_and_or()?;
+            let separated = match self.peek() {
+                Some(Token::Semicolon | Token::Newline) => true,
+                Some(Token::Ampersand) => {
+                    and_or.background = true;
+                    true
+                }
+                _ => false,
+            };
+            list.items.push(and_or);
+            if !separated {
+                break;
+            }
+            self.next();
+        }
+
+        Ok(list)
+    }
+
+    /// A sequence inside a compound command, which must not be empty
+    fn parse_body(&mut self, terminators: &[&str]) -> Result<List, ParseError> {
+        let list = self.parse_sequence(terminators)?;
+        if list.items.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    /// and_or := pipeline (('&&' | '||') linebreak pipeline)*
+    fn parse_and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+
+        loop {
+            let connector = match self.peek() {
+                Some(Token::AndIf) => Connector::And,
+                Some(Token::OrIf) => Connector::Or,
+                _ => break,
+            };
+            self.next();
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+
+        Ok(AndOr {
+            first,
+            rest,
+            background: false,
+        })
+    }
+
+    /// pipeline := command ('|' linebreak command)*
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let mut pipeline = Pipeline::default();
+        pipeline.commands.push(self.parse_command()?);
+
+        while self.next_if_eq(&Token::Pipe) {
+            self.skip_newlines();
+            pipeline.commands.push(self.parse_command()?);
+        }
+
+        Ok(pipeline)
+    }
+
+    /// command := compound_command redirect* | function_definition | simple_command
+    ///
+    /// A compound command starts with a reserved word, or `(` for a
+    /// subshell.
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        while self.expand_alias()? {}
+
+        if let Some(name) = self.function_name() {
+            return self.parse_function(name);
+        }
+
+        let keyword = match self.peek() {
+            Some(Token::Word(word)) if is_reserved(word) => word.clone(),
+            Some(Token::LeftParen) => "(".to_string(),
+            _ => return Ok(Command::Simple(self.parse_simple_command()?)),
+        };
+        self.next();
+        if self.outer + self.depth >= MAX_NESTING_DEPTH {
+            return Err(ParseError::NestedTooDeep);
+        }
+        self.depth += 1;
+        let compound = match keyword.as_str() {
+            "(" => self.parse_subshell()?,
+            "if" => self.parse_if()?,
+            "while" => self.parse_loop(false)?,
+            "until" => self.parse_loop(true)?,
+            "for" => self.parse_for()?,
+            "case" => self.parse_case()?,
+            "{" => self.parse_group()?,
+            // `then`, `fi` and the like only where a compound expects them
+            _ => return Err(ParseError::UnexpectedToken(keyword)),
+        };
+        self.depth -= 1;
+
+        let mut redirects = Vec::new();
+        while let Some(&Token::Redirect(fd, kind)) = self.peek() {
+            self.next();
+            redirects.push(self.parse_redirect(fd, kind)?);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    /// The name of the function defined here, when the next tokens are a
+    /// plain word and `(`
+    fn function_name(&mut self) -> Option<String> {
+        self.fill(2);
+        match (self.tokens.front(), self.tokens.get(1)) {
+            (
+                Some(Queued {
+                    token: Token::Word(name),
+                    ..
+                }),
+                Some(Queued {
+                    token: Token::LeftParen,
+                    ..
+                }),
+            ) if is_function_name(name) => Some(name.clone()),
+            _ => None,
+        }
+    }
+
+    /// function_definition := name '(' ')' linebreak compound_command redirect*
+    fn parse_function(&mut self, name: String) -> Result<Command, ParseError> {
+        self.next();
+        self.next();
+        if !self.next_if_eq(&Token::RightParen) {
+            return Err(self.unexpected());
+        }
+
+        // The body is still to come, so running out of input is incomplete
+        self.depth += 1;
+        self.skip_newlines();
+        let body = match self.peek() {
+            Some(Token::Word(word)) if COMPOUND_STARTS.contains(&word.as_str()) => self.parse_command()?,
+            Some(Token::LeftParen) => self.parse_command()?,
+            _ => return Err(self.unexpected()),
+        };
+        self.depth -= 1;
+
+        Ok(Command::Function(name, Arc::new(body)))
+    }
+
+    /// if := 'if' body 'then' body ('elif' body 'then' body)* ['else' body] 'fi'
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+
+        loop {
+            let condition = self.parse_body(&["then"])?;
+            self.expect_keyword(&["then"])?;
+            let body = self.parse_body(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+
+            match self.expect_keyword(&["elif", "else", "fi"])? {
+                "elif" => continue,
+                "else" => {
+                    otherwise = Some(self.parse_body(&["fi"])?);
+                    self.expect_keyword(&["fi"])?;
+                }
+                _ => {}
+            }
+            break;
+        }
+
+        Ok(CompoundCommand::If { branches, otherwise })
+    }
+
+    /// loop := ('while' | 'until') body 'do' body 'done'
+    fn parse_loop(&mut self, until: bool) -> Result<CompoundCommand, ParseError> {
+        let condition = self.parse_body(&["do"])?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::Loop { condition, body, until })
+    }
+
+    /// for := 'for' name linebreak ['in' word* (';' | newline)] linebreak do_group
+    ///
+    /// A `;` may also follow the name directly, as in `for arg; do`.
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        let name = match self.next() {
+            Some(Token::Word(name)) if is_name(&name) => name,
+            Some(other) => return Err(ParseError::UnexpectedToken(other.describe())),
+            None => return Err(ParseError::UnexpectedEof),
+        };
+
+        self.skip_newlines();
+        let words = if self.peek() == Some(&Token::Word("in".to_string())) {
+            self.next();
+            let mut words = Vec::new();
+            while let Some(Token::Word(_)) = self.peek() {
+                if let Some(Token::Word(word)) = self.next() {
+                    words.push(word);
+                }
+            }
+            if !matches!(self.next(), Some(Token::Semicolon | Token::Newline)) {
+                return Err(self.unexpected());
+            }
+            Some(words)
+        } else {
+            self.next_if_eq(&Token::Semicolon);
+            None
+        };
+
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    /// do_group := linebreak 'do' body 'done'
+    fn parse_do_group(&mut self) -> Result<List, ParseError> {
+        self.skip_newlines();
+        self.expect_keyword(&["do"])?;
+        let body = self.parse_body(&["done"])?;
+        self.expect_keyword(&["done"])?;
+        Ok(body)
+    }
+
+    /// case := 'case' word linebreak 'in' linebreak arm* 'esac'
+    /// arm := ['('] pattern ('|' pattern)* ')' sequence [';;' linebreak]
+    ///
+    /// The `;;` may be left out after the last arm.
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        let word = self.expect_word()?;
+        self.skip_newlines();
+        self.expect_keyword(&["in"])?;
+
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek() == Some(&Token::Word("esac".to_string())) {
+                self.next();
+                break;
+            }
+
+            self.next_if_eq(&Token::LeftParen);
+            let mut patterns = vec![self.expect_word()?];
+            while self.next_if_eq(&Token::Pipe) {
+                patterns.push(self.expect_word()?);
+            }
+            if !self.next_if_eq(&Token::RightParen) {
+                return Err(self.unexpected());
+            }
+
+            let body = self.parse_sequence(&["esac"])?;
+            arms.push(CaseArm { patterns, body });
+            if !self.next_if_eq(&Token::DoubleSemicolon) {
+                self.expect_keyword(&["esac"])?;
+                break;
+            }
+        }
+
+        Ok(CompoundCommand::Case { word, arms })
+    }
+
+    /// group := '{' body '}'
+    fn parse_group(&mut self) -> Result<CompoundCommand, ParseError> {
+        let body = self.parse_body(&["}"])?;
+        self.expect_keyword(&["}"])?;
+        Ok(CompoundCommand::Group(body))
+    }
+
+    /// subshell := '(' body ')'
+    fn parse_subshell(&mut self) -> Result<CompoundCommand, ParseError> {
+        let body = self.parse_body(&[])?;
+        if !self.next_if_eq(&Token::RightParen) {
+            return Err(self.unexpected());
+        }
+        Ok(CompoundCommand::Subshell(body))
+    }
+
+    /// simple_command := (word | redirect)+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut command = SimpleCommand::default();
+        // Whether the next word may be an alias: the command name, or a
+        // word following an alias value that ended in a blank
+        let mut check_alias = true;
+
+        loop {
+            if check_alias && self.expand_alias()? {
+                continue;
+            }
+            match self.peek() {
+                Some(Token::Word(_)) => {
+                    let check_next = self.tokens.front().is_some_and(|queued| queued.check_next);
+                    if let Some(Token::Word(word)) = self.next() {
+                        // Assignments are only recognised before the command name
+                        match split_assignment(&word) {
+                            Some(assignment) if command.words.is_empty() => {
+                                command.assignments.push(assignment);
+                            }
+                            _ => {
+                                command.words.push(word);
+                                check_alias = check_next;
+                            }
+                        }
+                    }
+                }
+                Some(&Token::Redirect(fd, kind)) => {
+                    self.next();
+                    command.redirects.push(self.parse_redirect(fd, kind)?);
+                }
+                _ => break,
+            }
+        }
+
+        if command.words.is_empty() && command.redirects.is_empty() && command.assignments.is_empty() {
+            let depth = self.depth;
+            return Err(match self.peek() {
+                Some(token) => ParseError::UnexpectedToken(token.describe()),
+                None if depth > 0 => ParseError::UnexpectedEof,
+                None => ParseError::UnexpectedToken("newline".to_string()),
+            });
+        }
+
+        Ok(command)
+    }
+
+    /// The target of a redirection operator that was just read
+    fn parse_redirect(&mut self, fd: Option<u32>, kind: RedirectKind) -> Result<Redirect, ParseError> {
+        let fd = fd.unwrap_or(kind.default_fd());
+        match self.next() {
+            Some(Token::Word(target)) => Ok(Redirect { fd, kind, target }),
+            Some(other) => Err(ParseError::UnexpectedToken(other.describe())),
+            None => Err(ParseError::UnexpectedToken("newline".to_string())),
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.next_if_eq(&Token::Newline) {}
+    }
+
+    /// Read one of the reserved words a compound command expects next
+    fn expect_keyword(&mut self, keywords: &[&'static str]) -> Result<&'static str, ParseError> {
+        let found = match self.peek() {
+            Some(Token::Word(word)) => keywords.iter().find(|keyword| *keyword == word).copied(),
+            _ => None,
+        };
+        match found {
+            Some(keyword) => {
+                self.next();
+                Ok(keyword)
+            }
+            None => Err(self.unexpected()),
+        }
+    }
+
+    /// Read a word, such as the subject or a pattern of a `case`
+    fn expect_word(&mut self) -> Result<String, ParseError> {
+        match self.next() {
+            Some(Token::Word(word)) => Ok(word),
+            Some(other) => Err(ParseError::UnexpectedToken(other.describe())),
+            None => Err(ParseError::UnexpectedEof),
+        }
+    }
+
+    /// The error for the next token when it is not what the grammar wants
+    fn unexpected(&mut self) -> ParseError {
+        match self.peek() {
+            Some(token) => ParseError::UnexpectedToken(token.describe()),
+            None => ParseError::UnexpectedEof,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+    use crate::expand::expand_word;
+    use crate::shell::Shell;
+
+    /// The simple command a pipeline stage must be
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(command) => command,
+            other => panic!("not a simple command: {}", other),
+        }
+    }
+
+    /// Parse a line that must contain exactly one pipeline
+    fn parse_pipeline(input: &str) -> Result<Pipeline, ParseError> {
+        let mut list = parse(input)?;
+        assert_eq!(list.items.len(), 1);
+        let and_or = list.items.remove(0);
+        assert!(and_or.rest.is_empty());
+        Ok(and_or.first)
+    }
+
+    /// Parse a line that must contain exactly one command, removing quotes
+    fn parse_command(input: &str) -> Result<(String, Vec<String>), ParseError> {
+        let mut pipeline = parse_pipeline(input)?;
+        assert_eq!(pipeline.commands.len(), 1);
+        let mut shell = Shell::new();
+        let mut words: Vec<String> = simple(&pipeline.commands.remove(0))
+            .words
+            .iter()
+            .map(|word| expand_word(word, &mut shell).unwrap())
+            .collect();
+        let name = words.remove(0);
+        Ok((name, words))
+    }
+
+    /// Tokenize a line that must contain only words, removing quotes
+    fn words(input: &str) -> Result<Vec<String>, ParseError> {
+        let mut shell = Shell::new();
+        Ok(tokenize(input)?
+            .into_iter()
+            .map(|token| match token {
+                Token::Word(word) => expand_word(&word, &mut shell).unwrap(),
+                other => panic!("unexpected token {:?}", other),
+            })
+            .collect())
+    }
+
     #[test]
     fn test_empty_input() {
-        let (cmd, args) = parse_command("");
-        assert_eq!(cmd, "");
-        assert_eq!(args, Vec::<&str>::new());
+        assert!(parse("").unwrap().items.is_empty());
+        assert_eq!(tokenize("   ").unwrap(), Vec::<Token>::new());
     }
-    
+
     #[test]
     fn test_single_command() {
-        let (cmd, args) = parse_command("pwd");
+        let (cmd, args) = parse_command("pwd").unwrap();
         assert_eq!(cmd, "pwd");
         assert_eq!(args, Vec::<&str>::new());
     }
-    
+
     #[test]
     fn test_command_with_args() {
-        let (cmd, args) = parse_command("echo hello world");
+        let (cmd, args) = parse_command("echo hello world").unwrap();
         assert_eq!(cmd, "echo");
         assert_eq!(args, vec!["hello", "world"]);
     }
+
+    #[test]
+    fn test_double_quotes_preserve_whitespace() {
+        let (cmd, args) = parse_command("echo \"hello   world\"").unwrap();
+        assert_eq!(cmd, "echo");
+        assert_eq!(args, vec!["hello   world"]);
+    }
+
+    #[test]
+    fn test_single_quotes_are_literal() {
+        let tokens = words(r#"cat 'my file.txt' 'a\b"c'"#).unwrap();
+        assert_eq!(tokens, vec!["cat", "my file.txt", r#"a\b"c"#]);
+    }
+
+    #[test]
+    fn test_backslash_escapes() {
+        let tokens = words(r"mkdir a\ b c\\d").unwrap();
+        assert_eq!(tokens, vec!["mkdir", "a b", r"c\d"]);
+    }
+
+    #[test]
+    fn test_escapes_inside_double_quotes() {
+        let tokens = words(r#"echo "say \"hi\" \$HOME \n""#).unwrap();
+        assert_eq!(tokens, vec!["echo", r#"say "hi" $HOME \n"#]);
+    }
+
+    #[test]
+    fn test_empty_string_arguments() {
+        let tokens = words(r#"echo "" '' x"#).unwrap();
+        assert_eq!(tokens, vec!["echo", "", "", "x"]);
+    }
+
+    #[test]
+    fn test_adjacent_quoted_parts_join() {
+        let tokens = words(r#"echo foo"bar"'baz' "a"b"#).unwrap();
+        assert_eq!(tokens, vec!["echo", "foobarbaz", "ab"]);
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_eq!(words("echo hi # a comment").unwrap(), vec!["echo", "hi"]);
+        assert_eq!(words("echo a#b '#c' \\#d").unwrap(), vec!["echo", "a#b", "#c", "#d"]);
+        assert!(parse("#!/usr/bin/env zero-shell").unwrap().items.is_empty());
+    }
+
+    #[test]
+    fn test_unterminated_quotes() {
+        assert_eq!(tokenize("echo 'oops"), Err(ParseError::UnterminatedQuote('\'')));
+        assert_eq!(tokenize("echo \"oops"), Err(ParseError::UnterminatedQuote('"')));
+        assert_eq!(tokenize("echo oops\\"), Err(ParseError::TrailingBackslash));
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let pipeline = parse_pipeline("ls -l | grep foo|wc -l").unwrap();
+        let words: Vec<&Vec<String>> = pipeline.commands.iter().map(|c| &simple(c).words).collect();
+        assert_eq!(words, vec![&vec!["ls", "-l"], &vec!["grep", "foo"], &vec!["wc", "-l"]]);
+    }
+
+    #[test]
+    fn test_quoted_pipe_is_a_word() {
+        let (cmd, args) = parse_command("echo '|' \\| \"a|b\"").unwrap();
+        assert_eq!(cmd, "echo");
+        assert_eq!(args, vec!["|", "|", "a|b"]);
+    }
+
+    #[test]
+    fn test_empty_pipeline_stage() {
+        let expected = Err(ParseError::UnexpectedToken("|".to_string()));
+        assert_eq!(parse_pipeline("| ls"), expected);
+        assert_eq!(parse_pipeline("ls | | wc"), expected);
+        let newline = Err(ParseError::UnexpectedToken("newline".to_string()));
+        assert_eq!(parse("ls |"), newline);
+    }
+
+    #[test]
+    fn test_redirections() {
+        let pipeline = parse_pipeline("cat a b > merged.log 2>>err <in").unwrap();
+        let command = simple(&pipeline.commands[0]);
+        assert_eq!(command.words, vec!["cat", "a", "b"]);
+        assert_eq!(
+            command.redirects,
+            vec![
+                Redirect { fd: 1, kind: RedirectKind::Write, target: "merged.log".into() },
+                Redirect { fd: 2, kind: RedirectKind::Append, target: "err".into() },
+                Redirect { fd: 0, kind: RedirectKind::Read, target: "in".into() },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_descriptor_duplication() {
+        let tokens = tokenize("cmd 2>&1 >&2 &> all &>>more").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("cmd".into()),
+                Token::Redirect(Some(2), RedirectKind::DupOutput),
+                Token::Word("1".into()),
+                Token::Redirect(None, RedirectKind::DupOutput),
+                Token::Word("2".into()),
+                Token::Redirect(None, RedirectKind::WriteBoth),
+                Token::Word("all".into()),
+                Token::Redirect(None, RedirectKind::AppendBoth),
+                Token::Word("more".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_bare_numbers_are_descriptors() {
+        let (cmd, args) = parse_command("echo a2>x '2'>y 2 >z").unwrap();
+        assert_eq!(cmd, "echo");
+        assert_eq!(args, vec!["a2", "2", "2"]);
+        assert_eq!(words("echo '>' \\<").unwrap(), vec!["echo", ">", "<"]);
+    }
+
+    #[test]
+    fn test_here_documents() {
+        let list = parse("cat <<EOF | tr a-z A-Z; cat <<-'END' 2<<<\"$x\"\nhello $x\nEOF\n\tbody\n\tEND\necho after").unwrap();
+        let redirects = |item: usize, stage: usize| simple(&list.items[item].first.commands[stage]).redirects.clone();
+        assert_eq!(
+            redirects(0, 0),
+            vec![Redirect { fd: 0, kind: RedirectKind::HereDoc, target: "hello $x\n".into() }]
+        );
+        assert_eq!(
+            redirects(1, 0),
+            vec![
+                Redirect { fd: 0, kind: RedirectKind::QuotedHereDoc, target: "body\n".into() },
+                Redirect { fd: 2, kind: RedirectKind::HereString, target: "\"$x\"".into() },
+            ]
+        );
+        assert_eq!(list.items[2].to_string(), "echo after");
+
+        // The delimiter may be the last line, without a newline
+        assert_eq!(parse("cat <<\\E\n$x\nE").unwrap().items.len(), 1);
+        let unterminated = ParseError::UnterminatedHereDoc("EOF".to_string());
+        assert!(unterminated.is_incomplete());
+        assert_eq!(parse("cat <<EOF"), Err(unterminated.clone()));
+        assert_eq!(parse("cat <<\"EOF\"\nno end\n"), Err(unterminated));
+        assert_eq!(parse("cat <<\n"), Err(ParseError::UnexpectedToken("newline".to_string())));
+    }
+
+    #[test]
+    fn test_redirect_needs_target() {
+        let missing = Err(ParseError::UnexpectedToken("newline".to_string()));
+        assert_eq!(parse("echo hi >"), missing);
+        let pipe = Err(ParseError::UnexpectedToken("|".to_string()));
+        assert_eq!(parse("echo hi > | cat"), pipe);
+    }
+
+    #[test]
+    fn test_background() {
+        let list = parse("sleep 1 & echo a && echo b&").unwrap();
+        assert_eq!(list.items.len(), 2);
+        assert!(list.items[0].background);
+        assert!(list.items[1].background);
+        assert_eq!(list.items[1].to_string(), "echo a && echo b");
+        assert!(!parse("echo a; echo b").unwrap().items[0].background);
+
+        assert_eq!(tokenize("a&>b").unwrap()[1], Token::Redirect(None, RedirectKind::WriteBoth));
+        let ampersand = Err(ParseError::UnexpectedToken("&".to_string()));
+        assert_eq!(parse("& echo"), ampersand);
+        let semicolon = Err(ParseError::UnexpectedToken(";".to_string()));
+        assert_eq!(parse("sleep 1 & ;"), semicolon);
+    }
+
+    #[test]
+    fn test_words_keep_their_quotes() {
+        let tokens = tokenize(r#"echo '$?' "a b" c\ d"#).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("echo".into()),
+                Token::Word("'$?'".into()),
+                Token::Word("\"a b\"".into()),
+                Token::Word("c\\ d".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assignments() {
+        let command = parse_pipeline("A=1 B='x y' env C=3").unwrap().commands.remove(0);
+        let command = simple(&command);
+        let names: Vec<&str> = command.assignments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["A", "B"]);
+        assert_eq!(command.assignments[1].value, "'x y'");
+        assert_eq!(command.words, vec!["env", "C=3"]);
+
+        let command = parse_pipeline("X= >out").unwrap().commands.remove(0);
+        let command = simple(&command);
+        assert_eq!(command.assignments[0].value, "");
+        assert!(command.words.is_empty());
+
+        let command = parse_pipeline("'A=1' 1A=2").unwrap().commands.remove(0);
+        let command = simple(&command);
+        assert!(command.assignments.is_empty());
+        assert_eq!(command.words, vec!["'A=1'", "1A=2"]);
+    }
+
+    #[test]
+    fn test_quote_round_trips() {
+        for text in ["plain", "", "two words", "it's", "$HOME", "a\\b"] {
+            assert_eq!(words(&quote(text)).unwrap(), vec![text]);
+        }
+        assert_eq!(quote("/usr/bin:/bin"), "/usr/bin:/bin");
+    }
+
+    #[test]
+    fn test_command_lists() {
+        let list = parse("mkdir out && cp cfg out/ || echo failed; ls;").unwrap();
+        assert_eq!(list.items.len(), 2);
+
+        let first = &list.items[0];
+        assert_eq!(simple(&first.first.commands[0]).words, vec!["mkdir", "out"]);
+        let connectors: Vec<Connector> = first.rest.iter().map(|(c, _)| *c).collect();
+        assert_eq!(connectors, vec![Connector::And, Connector::Or]);
+        assert_eq!(simple(&first.rest[1].1.commands[0]).words, vec!["echo", "failed"]);
+
+        assert_eq!(simple(&list.items[1].first.commands[0]).words, vec!["ls"]);
+    }
+
+    #[test]
+    fn test_list_syntax_errors() {
+        let semicolon = Err(ParseError::UnexpectedToken(";".to_string()));
+        assert_eq!(parse("; ls"), semicolon);
+        assert_eq!(parse("ls;;"), Err(ParseError::UnexpectedToken(";;".to_string())));
+        let and = Err(ParseError::UnexpectedToken("&&".to_string()));
+        assert_eq!(parse("&& ls"), and);
+        let newline = Err(ParseError::UnexpectedToken("newline".to_string()));
+        assert_eq!(parse("true ||"), newline);
+        assert_eq!(words("echo ';' '&&'").unwrap(), vec!["echo", ";", "&&"]);
+    }
+
+    #[test]
+    fn test_alias_expansion() {
+        let aliases: BTreeMap<String, String> = [
+            ("ll", "ls -l"),
+            ("ls", "ls -F"),
+            ("loop1", "loop2 x"),
+            ("loop2", "loop1 y"),
+            ("sudo", "sudo "),
+            ("both", "echo a; echo b"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let words = |line: &str| -> Vec<Vec<String>> {
+            let list = parse_with_aliases(line, &aliases).unwrap();
+            list.items
+                .iter()
+                .flat_map(|and_or| and_or.first.commands.iter().map(|command| simple(command).words.clone()))
+                .collect()
+        };
+
+        // An alias is not expanded again inside its own value
+        assert_eq!(words("ll src"), vec![vec!["ls", "-F", "-l", "src"]]);
+        assert_eq!(words("loop1"), vec![vec!["loop1", "y", "x"]]);
+        // Only command names, and not when quoted
+        assert_eq!(words("echo ll | 'll'"), vec![vec!["echo", "ll"], vec!["'ll'"]]);
+        assert_eq!(words("A=1 ll"), vec![vec!["ls", "-F", "-l"]]);
+        // A trailing blank makes the next word a candidate too
+        assert_eq!(words("sudo ll"), vec![vec!["sudo", "ls", "-F", "-l"]]);
+        assert_eq!(words("both"), vec![vec!["echo", "a"], vec!["echo", "b"]]);
+    }
+
+    #[test]
+    fn test_compound_commands() {
+        let one_line = parse("for f in a b; do if true; then echo $f; elif false; then :; else :; fi; done").unwrap();
+        let multi_line = parse("for f in a b\ndo\n  if true\n  then echo $f\n  elif false; then :\n  else :\n  fi\ndone\n").unwrap();
+        assert_eq!(one_line, multi_line);
+
+        let Command::Compound(CompoundCommand::For { name, words: values, body }, _) = &one_line.items[0].first.commands[0]
+        else {
+            panic!("not a for loop");
+        };
+        assert_eq!(name, "f");
+        assert_eq!(values.as_deref(), Some(&["a".to_string(), "b".to_string()][..]));
+        assert!(matches!(body.items[0].first.commands[0], Command::Compound(CompoundCommand::If { .. }, _)));
+
+        // Written back so that it parses to the same thing, for `jobs`
+        let text = "while read x; do case $x in a|b) echo ab;; (*) ;; esac; done > out | wc -l";
+        let list = parse(text).unwrap();
+        assert_eq!(list.to_string(), "while read x; do case $x in a | b) echo ab ;; *) ;; esac; done >out | wc -l");
+        assert_eq!(parse(&list.to_string()).unwrap(), list);
+
+        // Reserved words only count in command position
+        assert_eq!(words("echo if then fi").unwrap(), vec!["echo", "if", "then", "fi"]);
+    }
+
+    #[test]
+    fn test_incomplete_input() {
+        for input in ["if true; then", "while true\ndo echo", "case x in\n a) echo", "for x in a b", "echo 'a\n", "echo \\\n"] {
+            let error = parse(input).unwrap_err();
+            assert!(error.is_incomplete(), "{:?}: {:?}", input, error);
+        }
+        // A line continuation inside the input joins the two lines
+        assert_eq!(words("echo a\\\nb").unwrap(), vec!["echo", "ab"]);
+
+        for input in ["fi", "if true; fi", "if; then :; fi", "for 1 in a; do :; done", "case x in a) ;; ) esac"] {
+            let error = parse(input).unwrap_err();
+            assert!(!error.is_incomplete(), "{:?}: {:?}", input, error);
+        }
+        assert_eq!(parse("done"), Err(ParseError::UnexpectedToken("done".to_string())));
+    }
+
+    #[test]
+    fn test_command_substitutions_stay_in_their_word() {
+        let word = |text: &str| Token::Word(text.to_string());
+        assert_eq!(
+            tokenize("echo $(ls -l | wc -l) x\"$(echo \")\")\"y `echo a;b` $((1 + (2)))").unwrap(),
+            vec![
+                word("echo"),
+                word("$(ls -l | wc -l)"),
+                word("x\"$(echo \")\")\"y"),
+                word("`echo a;b`"),
+                word("$((1 + (2)))")
+            ]
+        );
+        assert_eq!(tokenize("$(\necho a\n)").unwrap(), vec![word("$(\necho a\n)")]);
+        // Only a `)` after complete commands closes
+        for text in [
+            "$(case x in x) echo y;; esac)",
+            "$(case x in (x) echo y;; esac)",
+            "$(echo a # it's a ) comment\n)",
+            "$(f() { echo a; }; f)",
+            "$(cat <<E\n)\nE\n)",
+        ] {
+            assert_eq!(tokenize(&format!("{} x", text)).unwrap(), vec![word(text), word("x")]);
+        }
+        // Invalid commands end at the first balanced one, to fail when run
+        assert_eq!(tokenize("$(;) x").unwrap(), vec![word("$(;)"), word("x")]);
+        for input in [
+            "echo $(echo",
+            "echo \"$(echo )",
+            "echo `echo",
+            "echo $(echo ')",
+            "echo $(case x in x) echo y;; esac",
+            "echo $(echo a # )",
+        ] {
+            assert!(parse(input).unwrap_err().is_incomplete(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn test_function_definitions_and_groups() {
+        let list = parse("greet() {\n  echo hi $1\n} > out; greet you").unwrap();
+        let Command::Function(name, body) = &list.items[0].first.commands[0] else {
+            panic!("not a function definition");
+        };
+        assert_eq!(name, "greet");
+        let Command::Compound(CompoundCommand::Group(group), redirects) = &**body else {
+            panic!("not a group: {}", body);
+        };
+        assert_eq!(group.to_string(), "echo hi $1");
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(list.to_string(), "greet() { echo hi $1; } >out; greet you");
+        assert_eq!(parse(&list.to_string()).unwrap(), list);
+
+        // Any compound command can be the body, on the next line too
+        assert!(parse("f()\nif true; then :; fi").is_ok());
+        for input in ["f()", "f() {", "{ echo a;"] {
+            assert!(parse(input).unwrap_err().is_incomplete(), "{:?}", input);
+        }
+        for input in ["f() echo", "if() { :; }", "f(x) { :; }", "{ }"] {
+            assert!(parse(input).is_err(), "{:?}", input);
+        }
+        // A `}` that is not in command position is an argument
+        assert_eq!(words("echo a }").unwrap(), vec!["echo", "a", "}"]);
+    }
+
+    #[test]
+    fn test_subshells() {
+        let list = parse("(cd build && ls) > out | (\n  (echo nested)\n)").unwrap();
+        let commands = &list.items[0].first.commands;
+        let Command::Compound(CompoundCommand::Subshell(body), redirects) = &commands[0] else {
+            panic!("not a subshell: {}", commands[0]);
+        };
+        assert_eq!(body.to_string(), "cd build && ls");
+        assert_eq!(redirects.len(), 1);
+        assert_eq!(list.to_string(), "(cd build && ls) >out | ((echo nested))");
+        assert_eq!(parse(&list.to_string()).unwrap(), list);
+
+        assert!(matches!(
+            &parse("f() (cd /)").unwrap().items[0].first.commands[0],
+            Command::Function(_, body) if body.to_string() == "(cd /)"
+        ));
+        for input in ["(echo a", "(echo a;", "echo $(ls; (pwd)"] {
+            assert!(parse(input).unwrap_err().is_incomplete(), "{:?}", input);
+        }
+        assert_eq!(parse("()"), Err(ParseError::UnexpectedToken(")".to_string())));
+        assert_eq!(parse("echo a (b)"), Err(ParseError::UnexpectedToken("(".to_string())));
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |open: &str, close: &str, depth: usize| {
+            format!("{}echo x{}", open.repeat(depth), close.repeat(depth))
+        };
+        assert!(parse(&nested("(", ")", 100)).is_ok());
+        assert!(parse(&nested("{ ", "; }", 100)).is_ok());
+        for (open, close) in [("(", ")"), ("{ ", "; }"), ("if true; then ", "; fi"), ("(", "")] {
+            assert_eq!(parse(&nested(open, close, 5000)), Err(ParseError::NestedTooDeep), "{:?}", open);
+        }
+        // Command substitutions count too, with the commands around them
+        assert!(tokenize(&nested("$(echo ", ")", 100)).is_ok());
+        for (open, close) in [("$(echo ", ")"), ("\"$(echo ", ")\""), ("$( (", "))"), ("$(", "")] {
+            assert_eq!(tokenize(&nested(open, close, 5000)), Err(ParseError::NestedTooDeep), "{:?}", open);
+        }
+        assert_eq!(ParseError::NestedTooDeep.to_string(), "syntax error: commands nested too deeply");
+    }
 }
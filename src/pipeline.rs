@@ -0,0 +1,546 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::thread::{self, Scope, ScopedJoinHandle};
+
+use crate::builtins::{Builtin, Context, ExitStatus};
+use crate::compound;
+use crate::expand::{expand_assignment, expand_fields, expand_here_doc, expand_word, ExpandError};
+use crate::external::{self, ExecError, JobGroup, Process};
+use crate::jobs::Job;
+use crate::parser::{Command, CompoundCommand, Pipeline, Redirect, RedirectKind};
+use crate::redirect;
+use crate::shell::{Shell, Variable};
+use crate::signals::{self, INTERRUPT_STATUS};
+use crate::streams::{self, Io};
+
+/// A command whose words, assignments and redirection targets have been
+/// expanded
+struct Prepared<'a> {
+    /// `NAME=value` prefixes, only in effect while the command runs
+    assignments: Vec<(String, String)>,
+    words: Vec<String>,
+    io: Io,
+    /// The compound command to run instead, with `words` left empty
+    compound: Option<&'a CompoundCommand>,
+}
+
+impl Prepared<'_> {
+    /// Whether the command runs inside the shell rather than as a child
+    /// process
+    fn in_shell(&self, shell: &Shell) -> bool {
+        self.compound.is_some()
+            || self.words.first().is_some_and(|name| {
+                shell.functions().contains_key(name) || shell.registry().contains(name)
+            })
+    }
+}
+
+/// A started pipeline stage that still has to be waited for
+enum Stage<'scope> {
+    Process(Process),
+    Thread(ScopedJoinHandle<'scope, ExitStatus>),
+    Finished(ExitStatus),
+}
+
+/// The stack a stage running on a thread gets: as much as the main thread
+/// usually has, since functions and compound commands recurse on it
+const STAGE_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How deeply functions may call each other before the call fails, which
+/// keeps runaway recursion from overflowing the stack
+const MAX_FUNCTION_DEPTH: usize = 256;
+
+/// Run every command of a pipeline and wait for all of them to finish
+/// Returns the exit status of the last command
+///
+/// A command name is looked up as a function first, then as a builtin,
+/// then on `$PATH`; aliases are already gone by the time it runs.
+/// External commands become child processes. Builtins, functions and
+/// compound commands run inside the shell: a lone one runs directly against
+/// `shell`, while those that are part of a longer pipeline run on their
+/// own thread with a copy of the shell, like the subshells other shells
+/// fork for each stage.
+/// That way every stage makes progress at the same time and no pipe can
+/// fill up and stall the chain.
+///
+/// With job control, a pipeline made only of external commands runs as a
+/// job in a process group of its own, which Ctrl-Z can stop. Builtins
+/// cannot be stopped that way, so a pipeline using any stays with the
+/// shell; the external commands inside a compound command become jobs
+/// of their own.
+pub fn execute(shell: &mut Shell, pipeline: &Pipeline) -> ExitStatus {
+    if let [command] = pipeline.commands.as_slice() {
+        // Defining a function is all a definition does
+        if let Command::Function(name, body) = command {
+            shell.functions_mut().insert(name.clone(), Arc::clone(body));
+            return 0;
+        }
+
+        let io = shell.io().clone();
+        let mut prepared = match prepare(shell, command, io) {
+            Ok(prepared) => prepared,
+            Err(status) => return status,
+        };
+        if let Some(compound) = prepared.compound {
+            return run_compound(shell, compound, prepared.io);
+        }
+
+        // Without a command, assignments set shell variables, each one
+        // already seeing the ones before it (`A=1 B=$A`). The status is
+        // that of the last command substitution, so `if x=$(cmd)` works.
+        if prepared.words.is_empty() {
+            let mut status = 0;
+            if let Command::Simple(command) = command {
+                for assignment in &command.assignments {
+                    let (value, substituted) = match expand_assignment(&assignment.value, shell) {
+                        Ok(expanded) => expanded,
+                        Err(error) => return expansion_failed(&mut prepared.io, error),
+                    };
+                    shell.set_var(&assignment.name, &value);
+                    status = substituted.unwrap_or(status);
+                }
+            }
+            return status;
+        }
+
+        if let Some(body) = shell.functions().get(&prepared.words[0]).cloned() {
+            return run_function(shell, body, prepared);
+        }
+        return match shell.registry().get(&prepared.words[0]) {
+            Some(builtin) => run_builtin(shell, builtin, prepared),
+            None if shell.jobs().has_control() => run_job(shell, pipeline, vec![Ok(prepared)]),
+            None => match spawn_external(shell, prepared, None) {
+                Ok(process) => process.wait(),
+                Err(status) => status,
+            },
+        };
+    }
+
+    let base = shell.io().clone();
+    let stages = prepare_stages(shell, pipeline, &base);
+
+    let only_external = stages.iter().all(|stage| match stage {
+        Ok(prepared) => !prepared.in_shell(shell),
+        Err(_) => true,
+    });
+    if shell.jobs().has_control() && only_external {
+        return run_job(shell, pipeline, stages);
+    }
+
+    let shell = &*shell;
+    thread::scope(|scope| {
+        let started: Vec<Stage> = stages
+            .into_iter()
+            .map(|stage| match stage {
+                Ok(Prepared {
+                    compound: Some(compound),
+                    io,
+                    ..
+                }) => {
+                    let mut subshell = shell.clone();
+                    spawn_stage(scope, shell, move || run_compound(&mut subshell, compound, io))
+                }
+                Ok(prepared) if prepared.words.is_empty() => Stage::Finished(0),
+                Ok(prepared) if shell.functions().contains_key(&prepared.words[0]) => {
+                    let body = Arc::clone(&shell.functions()[&prepared.words[0]]);
+                    let mut subshell = shell.clone();
+                    spawn_stage(scope, shell, move || run_function(&mut subshell, body, prepared))
+                }
+                Ok(prepared) => match shell.registry().get(&prepared.words[0]) {
+                    Some(builtin) => {
+                        let mut subshell = shell.clone();
+                        spawn_stage(scope, shell, move || run_builtin(&mut subshell, builtin, prepared))
+                    }
+                    None => match spawn_external(shell, prepared, None) {
+                        Ok(process) => Stage::Process(process),
+                        Err(status) => Stage::Finished(status),
+                    },
+                },
+                Err(status) => Stage::Finished(status),
+            })
+            .collect();
+
+        let mut status = 0;
+        for stage in started {
+            status = match stage {
+                Stage::Process(process) => process.wait(),
+                Stage::Thread(handle) => handle.join().unwrap_or(1),
+                Stage::Finished(status) => status,
+            };
+        }
+        status
+    })
+}
+
+/// Start a stage that runs inside the shell on a thread of its own
+fn spawn_stage<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    shell: &Shell,
+    run: impl FnOnce() -> ExitStatus + Send + 'scope,
+) -> Stage<'scope> {
+    match thread::Builder::new().stack_size(STAGE_STACK_SIZE).spawn_scoped(scope, run) {
+        Ok(handle) => Stage::Thread(handle),
+        Err(e) => {
+            let _ = writeln!(shell.io().stderr.clone(), "0-shell: thread: {}", streams::describe(&e));
+            Stage::Finished(1)
+        }
+    }
+}
+
+/// Connect the commands of a pipeline with pipes and prepare each one
+///
+/// Every stage holds only its own ends of the pipes, so once a stage is
+/// started and has let go of them, the next one sees EOF as soon as it
+/// finishes. If a pipe cannot be created, the pipeline is cut short there
+/// with a failed stage.
+fn prepare_stages<'a>(shell: &Shell, pipeline: &'a Pipeline, base: &Io) -> Vec<Result<Prepared<'a>, ExitStatus>> {
+    let last = pipeline.commands.len() - 1;
+    let mut stages = Vec::new();
+    let mut stdin = base.stdin.clone();
+
+    for (index, command) in pipeline.commands.iter().enumerate() {
+        let (stdout, next_stdin) = if index == last {
+            (base.stdout.clone(), None)
+        } else {
+            match streams::pipe() {
+                Ok((reader, writer)) => (writer, Some(reader)),
+                Err(e) => {
+                    let _ = writeln!(base.stderr.clone(), "0-shell: pipe: {}", streams::describe(&e));
+                    stages.push(Err(1));
+                    break;
+                }
+            }
+        };
+
+        let io = Io {
+            stdin,
+            stdout,
+            stderr: base.stderr.clone(),
+        };
+
+        // Redirections are applied after the pipe is connected, so
+        // `cmd 2>&1 | less` sends stderr down the pipe too. Like the
+        // stage itself, its expansions cannot change the shell, so an
+        // assignment such as `$((n += 1))` is made to a copy.
+        stages.push(prepare(&mut shell.clone(), command, io));
+
+        stdin = match next_stdin {
+            Some(reader) => reader,
+            None => break,
+        };
+    }
+    stages
+}
+
+/// Run prepared external commands as a foreground job: the first one
+/// starts a new process group, the others join it, and the group gets the
+/// terminal until the job finishes or stops
+fn run_job(shell: &mut Shell, pipeline: &Pipeline, stages: Vec<Result<Prepared, ExitStatus>>) -> ExitStatus {
+    let terminal = shell.jobs().terminal();
+    let mut pids = Vec::new();
+    // The status of the last stage, unless it is a process still to be waited for
+    let mut last_status = None;
+
+    for stage in stages {
+        let group = JobGroup {
+            pgid: pids.first().copied().unwrap_or(0),
+            terminal,
+        };
+        last_status = match stage {
+            Ok(prepared) if prepared.words.is_empty() => Some(0),
+            Ok(prepared) => match spawn_external(shell, prepared, Some(group)) {
+                Ok(process) => {
+                    pids.push(process.into_id());
+                    None
+                }
+                Err(status) => Some(status),
+            },
+            Err(status) => Some(status),
+        };
+    }
+
+    let Some(&pgid) = pids.first() else {
+        return last_status.unwrap_or(0);
+    };
+    let mut stderr = shell.io().stderr.clone();
+    let job = Job::new(Some(pgid), pids, pipeline.to_string());
+    let status = shell.jobs_mut().foreground(job, &mut stderr);
+    last_status.unwrap_or(status)
+}
+
+/// Expand a command's words and apply its redirections, reporting any
+/// failure. Returns the status to use when the command should not run.
+///
+/// Assignment values are only expanded here when there is a command to
+/// run them for; a line of bare assignments is handled by the caller.
+/// The words of a compound command are expanded as it runs, and a
+/// function definition inside a longer pipeline does nothing.
+fn prepare<'a>(shell: &mut Shell, command: &'a Command, mut io: Io) -> Result<Prepared<'a>, ExitStatus> {
+    let command = match command {
+        Command::Function(..) => {
+            return Ok(Prepared {
+                assignments: Vec::new(),
+                words: Vec::new(),
+                io,
+                compound: None,
+            });
+        }
+        Command::Simple(command) => command,
+        Command::Compound(compound, redirects) => {
+            apply_redirects(shell, redirects, &mut io)?;
+            return Ok(Prepared {
+                assignments: Vec::new(),
+                words: Vec::new(),
+                io,
+                compound: Some(compound),
+            });
+        }
+    };
+    apply_redirects(shell, &command.redirects, &mut io)?;
+
+    let mut words = Vec::new();
+    for word in &command.words {
+        match expand_fields(word, shell) {
+            Ok(fields) => words.extend(fields),
+            Err(error) => return Err(expansion_failed(&mut io, error)),
+        }
+    }
+    // Ctrl-C during a command substitution cancels the whole command
+    if signals::interrupted() {
+        return Err(INTERRUPT_STATUS);
+    }
+
+    // A command made only of redirections, like `> file`, just opens them
+    let mut assignments = Vec::new();
+    if !words.is_empty() {
+        for assignment in &command.assignments {
+            match expand_word(&assignment.value, shell) {
+                Ok(value) => assignments.push((assignment.name.clone(), value)),
+                Err(error) => return Err(expansion_failed(&mut io, error)),
+            }
+        }
+    }
+
+    Ok(Prepared {
+        assignments,
+        words,
+        io,
+        compound: None,
+    })
+}
+
+/// Expand the targets of redirections and apply them to `io`
+///
+/// A here-document is expanded only when its delimiter was not quoted,
+/// and a here-string gets a newline at the end.
+fn apply_redirects(shell: &mut Shell, redirects: &[Redirect], io: &mut Io) -> Result<(), ExitStatus> {
+    let mut expanded = Vec::new();
+    for r in redirects {
+        let target = match r.kind {
+            RedirectKind::QuotedHereDoc => Ok(r.target.clone()),
+            RedirectKind::HereDoc => expand_here_doc(&r.target, shell),
+            RedirectKind::HereString => expand_word(&r.target, shell).map(|word| word + "\n"),
+            _ => expand_word(&r.target, shell),
+        };
+        match target {
+            Ok(target) => expanded.push(Redirect { target, ..r.clone() }),
+            Err(error) => return Err(expansion_failed(io, error)),
+        }
+    }
+    let redirects = expanded;
+
+    redirect::apply(&redirects, io, shell.cwd()).map_err(|message| {
+        let _ = writeln!(io.stderr, "0-shell: {}", message);
+        1
+    })
+}
+
+/// Report an expansion that failed, giving the status of the command that
+/// therefore does not run
+pub(crate) fn expansion_failed(io: &mut Io, error: ExpandError) -> ExitStatus {
+    let _ = writeln!(io.stderr, "0-shell: {}", error);
+    1
+}
+
+/// Run a compound command in `shell` with its own, redirected streams
+fn run_compound(shell: &mut Shell, compound: &CompoundCommand, mut io: Io) -> ExitStatus {
+    shell.swap_io(&mut io);
+    let status = compound::execute(shell, compound);
+    shell.swap_io(&mut io);
+    let _ = io.stdout.flush();
+    status
+}
+
+fn run_builtin(shell: &mut Shell, builtin: Arc<dyn Builtin>, prepared: Prepared) -> ExitStatus {
+    let Prepared {
+        assignments, words, io, ..
+    } = prepared;
+    let args: Vec<&str> = words[1..].iter().map(String::as_str).collect();
+
+    with_assignments(shell, &assignments, |shell| {
+        let mut ctx = Context::new(io, shell);
+        let status = builtin.run(&mut ctx, &args);
+        let _ = ctx.io.stdout.flush();
+        status
+    })
+}
+
+/// Call a function: its arguments become `$1`, `$2`, ... and its body runs
+/// in this shell, so whatever it changes stays changed, except for the
+/// variables it makes `local`
+fn run_function(shell: &mut Shell, body: Arc<Command>, prepared: Prepared) -> ExitStatus {
+    let Prepared {
+        assignments,
+        words,
+        mut io,
+        ..
+    } = prepared;
+
+    if shell.function_depth() >= MAX_FUNCTION_DEPTH {
+        let _ = writeln!(
+            io.stderr,
+            "0-shell: {}: maximum function nesting level exceeded ({})",
+            words[0], MAX_FUNCTION_DEPTH
+        );
+        return 1;
+    }
+    // Definitions only ever store compound commands
+    let Command::Compound(compound, redirects) = &*body else {
+        return 0;
+    };
+
+    with_assignments(shell, &assignments, |shell| {
+        if let Err(status) = apply_redirects(shell, redirects, &mut io) {
+            return status;
+        }
+        shell.enter_function(words[1..].to_vec());
+        let status = run_compound(shell, compound, io);
+        shell.leave_function(status)
+    })
+}
+
+/// Run `run` with prefix assignments exported, putting the variables back
+/// the way they were afterwards: `HOME=/tmp cd` sees the assignment, but
+/// it is gone once `cd` returns
+fn with_assignments(
+    shell: &mut Shell,
+    assignments: &[(String, String)],
+    run: impl FnOnce(&mut Shell) -> ExitStatus,
+) -> ExitStatus {
+    let saved: Vec<(String, Option<Variable>)> = assignments
+        .iter()
+        .map(|(name, _)| (name.clone(), shell.variables().get(name).cloned()))
+        .collect();
+    for (name, value) in assignments {
+        shell.set_exported(name, value);
+    }
+
+    let status = run(shell);
+
+    for (name, var) in saved.into_iter().rev() {
+        match var {
+            Some(var) => shell.set_variable(&name, var),
+            None => shell.unset_var(&name),
+        }
+    }
+    status
+}
+
+fn spawn_external(shell: &Shell, prepared: Prepared, group: Option<JobGroup>) -> Result<Process, ExitStatus> {
+    let Prepared {
+        assignments, words, io, ..
+    } = prepared;
+    let args: Vec<&str> = words[1..].iter().map(String::as_str).collect();
+    let mut stderr = io.stderr.clone();
+
+    // Prefix assignments go into the child's environment only
+    let mut with_assignments;
+    let shell = if assignments.is_empty() {
+        shell
+    } else {
+        with_assignments = shell.clone();
+        for (name, value) in &assignments {
+            with_assignments.set_exported(name, value);
+        }
+        &with_assignments
+    };
+
+    external::spawn(shell, &words[0], &args, io, group).map_err(|e| {
+        // Report the failure wherever the command's stderr was pointed
+        let _ = writeln!(stderr, "0-shell: {}: {}", words[0], e);
+        match e {
+            ExecError::NotFound => 127,
+            _ => 126,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shell::Shell;
+    use crate::streams::{Buffer, Output};
+
+    #[test]
+    fn test_builtin_feeds_external() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        assert_eq!(shell.run_line("echo hello | tr a-z A-Z"), 0);
+        assert_eq!(out.to_string_lossy(), "HELLO\n");
+    }
+
+    #[test]
+    fn test_status_of_last_stage() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.run_line("true | false"), 1);
+        assert_eq!(shell.run_line("false | true"), 0);
+        assert_eq!(shell.run_line("no_such_command_0shell 2>/dev/null | true"), 0);
+        assert_eq!(shell.run_line("true | no_such_command_0shell 2>/dev/null"), 127);
+    }
+
+    #[test]
+    fn test_pipeline_stage_does_not_change_shell() {
+        let mut shell = Shell::new();
+        let before = shell.cwd().to_path_buf();
+
+        shell.run_line("cd / | true");
+        assert_eq!(shell.cwd(), before);
+
+        shell.run_line("exit 3 | true");
+        assert_eq!(shell.exit_requested(), None);
+    }
+
+    #[test]
+    fn test_prefix_assignments_are_temporary() {
+        let mut shell = Shell::new();
+        let out = Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.unset_var("ZERO_SHELL_PREFIX");
+
+        shell.run_line("ZERO_SHELL_PREFIX=child sh -c 'echo $ZERO_SHELL_PREFIX'");
+        shell.run_line("ZERO_SHELL_PREFIX=builtin env | grep ZERO_SHELL_PREFIX");
+        assert_eq!(out.to_string_lossy(), "child\nZERO_SHELL_PREFIX=builtin\n");
+        assert_eq!(shell.var("ZERO_SHELL_PREFIX"), None);
+    }
+
+    #[test]
+    fn test_bare_assignments_set_variables() {
+        let mut shell = Shell::new();
+
+        assert_eq!(shell.run_line("A=1 B=$A-2"), 0);
+        assert_eq!(shell.var("B"), Some("1-2"));
+        assert!(!shell.variables()["B"].exported);
+
+        shell.run_line("C=3 | true");
+        assert_eq!(shell.var("C"), None);
+
+        // A failed expansion is reported where the line sends its errors
+        let err = Buffer::new();
+        shell.set_stderr(Output::Buffer(err.clone()));
+        assert_eq!(shell.run_line("D=$((1/0)) 2>/dev/null"), 1);
+        assert_eq!(shell.run_line("D=$((1/0))"), 1);
+        assert_eq!(err.to_string_lossy(), "0-shell: 1/0: division by zero\n");
+        assert_eq!(shell.var("D"), None);
+    }
+}
@@ -0,0 +1,242 @@
+//! The prompt shown before each command, configured through `PS1` and
+//! `PS2`
+//!
+//! Backslash escapes in the variables are replaced every time the prompt
+//! is shown:
+//!
+//! - `\u` the user name, `\h` the host name up to the first `.`, `\H` all
+//!   of it
+//! - `\w` the working directory with `$HOME` shown as `~`, `\W` only its
+//!   last component
+//! - `\$` `#` for root and `$` for everyone else, `\?` the last exit status
+//! - `\t` `HH:MM:SS`, `\T` the same on a 12-hour clock, `\@` `HH:MM AM`,
+//!   `\A` `HH:MM` and `\d` the date as in `Tue May 26`
+//! - `\j` the number of jobs, `\!` the history number of the next command,
+//!   `\s` the shell's name, `\v` and `\V` its version
+//! - `\e` or `\033` escape, for colours such as `\e[1;32m`; `\a`, `\n`,
+//!   `\r`, `\\` and other octal codes `\nnn`
+//! - `\[` and `\]`, which other shells need around colour codes, are
+//!   accepted and dropped; the line editor measures the prompt without
+//!   escape sequences anyway
+//!
+//! Anything else after a backslash is shown as written.
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::shell::Shell;
+use crate::sys::{self, LocalTime};
+
+/// The prompt when `PS1` is not set
+pub const DEFAULT_PS1: &str = "$ ";
+
+/// The prompt when `PS2` is not set
+pub const DEFAULT_PS2: &str = "> ";
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// The prompt shown when the shell waits for a new command
+pub fn primary(shell: &Shell) -> String {
+    render(shell.var("PS1").unwrap_or(DEFAULT_PS1), shell)
+}
+
+/// The prompt shown when a command continues on the next line
+pub fn continuation(shell: &Shell) -> String {
+    render(shell.var("PS2").unwrap_or(DEFAULT_PS2), shell)
+}
+
+/// Replace the backslash escapes of a prompt template
+pub fn render(template: &str, shell: &Shell) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    // Looked up once, and only if the template shows the time
+    let mut now = None;
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+
+        let Some(escape) = chars.next() else {
+            result.push('\\');
+            break;
+        };
+        match escape {
+            'u' => result.push_str(&user_name()),
+            'h' => result.push_str(hostname().split('.').next().unwrap_or_default()),
+            'H' => result.push_str(&hostname()),
+            'w' => result.push_str(&working_directory(shell, false)),
+            'W' => result.push_str(&working_directory(shell, true)),
+            '$' => result.push(if sys::effective_user_id() == 0 { '#' } else { '$' }),
+            '?' => result.push_str(&shell.last_status().to_string()),
+            'j' => result.push_str(&shell.jobs().iter().count().to_string()),
+            '!' => result.push_str(&(shell.history().entries().len() + 1).to_string()),
+            's' => result.push_str(shell_name(shell)),
+            'v' => result.push_str(env!("CARGO_PKG_VERSION").rsplit_once('.').map_or("", |(v, _)| v)),
+            'V' => result.push_str(env!("CARGO_PKG_VERSION")),
+            'd' | 't' | 'T' | '@' | 'A' => {
+                let time = now.get_or_insert_with(current_time);
+                match time {
+                    Some(time) => result.push_str(&format_time(escape, time)),
+                    None => result.push_str("??"),
+                }
+            }
+            'e' => result.push('\x1b'),
+            'a' => result.push('\x07'),
+            'n' => result.push('\n'),
+            'r' => result.push('\r'),
+            '\\' => result.push('\\'),
+            '[' | ']' => {}
+            '0'..='7' => {
+                let mut code = escape.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            code = code * 8 + digit;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                result.extend(char::from_u32(code));
+            }
+            other => {
+                result.push('\\');
+                result.push(other);
+            }
+        }
+    }
+
+    result
+}
+
+/// `\w` and `\W`: the working directory, with `$HOME` abbreviated to `~`
+fn working_directory(shell: &Shell, last_only: bool) -> String {
+    let cwd = shell.cwd();
+    let home = shell.var("HOME").map(Path::new).filter(|home| home.parent().is_some());
+
+    match home.and_then(|home| cwd.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => "~".to_string(),
+        Some(_) if last_only => last_component(cwd),
+        Some(rest) => format!("~/{}", rest.display()),
+        None if last_only => last_component(cwd),
+        None => cwd.display().to_string(),
+    }
+}
+
+fn last_component(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.display().to_string(),
+    }
+}
+
+/// `\s`: the name the shell runs under, without its directory
+fn shell_name(shell: &Shell) -> &str {
+    let name = shell.script_name();
+    name.rsplit('/').next().unwrap_or(name)
+}
+
+/// The name of the user running the shell, from `/etc/passwd`, falling
+/// back to `$USER` and finally the numeric id
+fn user_name() -> String {
+    let uid = sys::user_id();
+    let from_passwd = std::fs::read_to_string("/etc/passwd").ok().and_then(|passwd| {
+        passwd.lines().find_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let id = fields.nth(1)?;
+            (id.parse() == Ok(uid)).then(|| name.to_string())
+        })
+    });
+    from_passwd
+        .or_else(|| std::env::var("USER").ok())
+        .unwrap_or_else(|| uid.to_string())
+}
+
+fn hostname() -> String {
+    sys::hostname().unwrap_or_default()
+}
+
+fn current_time() -> Option<LocalTime> {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    sys::local_time(seconds as i64)
+}
+
+/// Format a time for one of the `\d \t \T \@ \A` escapes
+fn format_time(escape: char, time: &LocalTime) -> String {
+    let hour12 = match time.hour % 12 {
+        0 => 12,
+        hour => hour,
+    };
+    match escape {
+        'd' => format!(
+            "{} {} {:02}",
+            WEEKDAYS[time.weekday as usize % 7],
+            MONTHS[(time.month as usize + 11) % 12],
+            time.day
+        ),
+        't' => format!("{:02}:{:02}:{:02}", time.hour, time.minute, time.second),
+        'T' => format!("{:02}:{:02}:{:02}", hour12, time.minute, time.second),
+        '@' => {
+            let meridiem = if time.hour < 12 { "AM" } else { "PM" };
+            format!("{:02}:{:02} {}", hour12, time.minute, meridiem)
+        }
+        _ => format!("{:02}:{:02}", time.hour, time.minute),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_escapes() {
+        let mut shell = Shell::new();
+        shell.set_var("HOME", "/home/pi");
+
+        shell.set_cwd("/home/pi/projects/shell");
+        assert_eq!(render("\\w \\W", &shell), "~/projects/shell shell");
+        shell.set_cwd("/home/pi");
+        assert_eq!(render("\\w \\W", &shell), "~ ~");
+        shell.set_cwd("/home/pip");
+        assert_eq!(render("\\w", &shell), "/home/pip");
+        shell.set_cwd("/");
+        assert_eq!(render("\\w \\W", &shell), "/ /");
+    }
+
+    #[test]
+    fn test_status_and_literal_escapes() {
+        let mut shell = Shell::new();
+        shell.run_line("sh -c 'exit 3'");
+
+        assert_eq!(render("[\\?] ", &shell), "[3] ");
+        assert_eq!(
+            render("\\[\\e[1;32m\\]ok\\[\\033[0m\\]\\n\\\\ \\q", &shell),
+            "\x1b[1;32mok\x1b[0m\n\\ \\q"
+        );
+        assert!(render("\\$", &shell) == "$" || render("\\$", &shell) == "#");
+        assert_eq!(render("\\h", &shell), hostname().split('.').next().unwrap());
+        assert_eq!(render("\\s", &shell), "0-shell");
+    }
+
+    #[test]
+    fn test_time_formats() {
+        let time = LocalTime {
+            year: 2024,
+            month: 5,
+            day: 7,
+            hour: 15,
+            minute: 4,
+            second: 9,
+            weekday: 2,
+        };
+        assert_eq!(format_time('t', &time), "15:04:09");
+        assert_eq!(format_time('T', &time), "03:04:09");
+        assert_eq!(format_time('@', &time), "03:04 PM");
+        assert_eq!(format_time('A', &time), "15:04");
+        assert_eq!(format_time('d', &time), "Tue May 07");
+    }
+}
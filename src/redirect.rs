@@ -0,0 +1,241 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::parser::{Redirect, RedirectKind};
+use crate::streams::{self, Input, Io, Output};
+
+/// Apply a command's redirections to its streams, left to right
+///
+/// Relative file names are resolved against `cwd`, and the target of a
+/// here-document or here-string is the text to read, already expanded.
+/// Only descriptors 0, 1 and 2 are supported. On failure the returned message is ready to be
+/// printed after a "0-shell: " prefix, and the command should not be run.
+pub fn apply(redirects: &[Redirect], io: &mut Io, cwd: &Path) -> Result<(), String> {
+    for redirect in redirects {
+        apply_one(redirect, io, cwd)?;
+    }
+    Ok(())
+}
+
+fn apply_one(redirect: &Redirect, io: &mut Io, cwd: &Path) -> Result<(), String> {
+    let target = redirect.target.as_str();
+    let path = cwd.join(target);
+    let open_error = |e: io::Error| format!("{}: {}", target, streams::describe(&e));
+
+    match redirect.kind {
+        RedirectKind::Read => {
+            let file = File::open(&path).map_err(open_error)?;
+            set_input(io, redirect.fd, Input::File(Arc::new(file)))
+        }
+        RedirectKind::Write | RedirectKind::Append => {
+            let file = open_for_writing(&path, redirect.kind == RedirectKind::Append)
+                .map_err(open_error)?;
+            set_output(io, redirect.fd, Output::File(Arc::new(file)))
+        }
+        RedirectKind::WriteBoth | RedirectKind::AppendBoth => {
+            let file = open_for_writing(&path, redirect.kind == RedirectKind::AppendBoth)
+                .map_err(open_error)?;
+            io.stdout = Output::File(Arc::new(file));
+            io.stderr = io.stdout.clone();
+            Ok(())
+        }
+        RedirectKind::DupOutput => {
+            // `>& file` without a descriptor number is shorthand for `&> file`
+            let source = match target.parse::<u32>() {
+                Ok(source) => source,
+                Err(_) if redirect.fd == 1 => {
+                    let both = Redirect {
+                        fd: 1,
+                        kind: RedirectKind::WriteBoth,
+                        target: redirect.target.clone(),
+                    };
+                    return apply_one(&both, io, cwd);
+                }
+                Err(_) => return Err(format!("{}: ambiguous redirect", target)),
+            };
+            let output = match source {
+                1 => io.stdout.clone(),
+                2 => io.stderr.clone(),
+                _ => return Err(format!("{}: Bad file descriptor", source)),
+            };
+            set_output(io, redirect.fd, output)
+        }
+        RedirectKind::DupInput => {
+            if target != "0" {
+                return Err(format!("{}: Bad file descriptor", target));
+            }
+            let input = io.stdin.clone();
+            set_input(io, redirect.fd, input)
+        }
+        RedirectKind::HereDoc | RedirectKind::QuotedHereDoc | RedirectKind::HereString => {
+            let file = text_file(&redirect.target)
+                .map_err(|e| format!("cannot create temp file for here-document: {}", streams::describe(&e)))?;
+            set_input(io, redirect.fd, Input::File(Arc::new(file)))
+        }
+    }
+}
+
+/// Tells apart the temporary files of here-documents made at the same time
+static TEXT_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// A file to read `text` from, as other shells make for here-documents
+///
+/// It lives in the temporary directory only until it is opened, so
+/// nothing is left behind, while external commands can still read it
+/// through their stdin.
+fn text_file(text: &str) -> io::Result<File> {
+    let number = TEXT_FILES.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!("0-shell-{}-{}", std::process::id(), number));
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    let _ = fs::remove_file(&path);
+    file.write_all(text.as_bytes())?;
+    file.rewind()?;
+    Ok(file)
+}
+
+fn open_for_writing(path: &Path, append: bool) -> io::Result<File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)
+}
+
+fn set_input(io: &mut Io, fd: u32, input: Input) -> Result<(), String> {
+    match fd {
+        0 => {
+            io.stdin = input;
+            Ok(())
+        }
+        _ => Err(format!("{}: Bad file descriptor", fd)),
+    }
+}
+
+fn set_output(io: &mut Io, fd: u32, output: Output) -> Result<(), String> {
+    match fd {
+        1 => io.stdout = output,
+        2 => io.stderr = output,
+        _ => return Err(format!("{}: Bad file descriptor", fd)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::{parse, Command};
+    use std::fs;
+    use std::io::Write;
+
+    fn redirects(line: &str) -> Vec<Redirect> {
+        match parse(line).unwrap().items.remove(0).first.commands.remove(0) {
+            Command::Simple(command) => command.redirects,
+            Command::Compound(_, redirects) => redirects,
+            Command::Function(..) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_write_then_append() {
+        let mut io = Io::inherit();
+        apply(&redirects("echo > test_redirect_out.txt"), &mut io, Path::new(".")).unwrap();
+        io.stdout.write_all(b"first\n").unwrap();
+
+        let mut io = Io::inherit();
+        apply(&redirects("echo >> test_redirect_out.txt"), &mut io, Path::new(".")).unwrap();
+        io.stdout.write_all(b"second\n").unwrap();
+
+        let content = fs::read_to_string("test_redirect_out.txt").unwrap();
+        assert_eq!(content, "first\nsecond\n");
+
+        fs::remove_file("test_redirect_out.txt").unwrap();
+    }
+
+    #[test]
+    fn test_stderr_follows_stdout() {
+        let mut io = Io::inherit();
+        apply(&redirects("cmd > test_redirect_both.txt 2>&1"), &mut io, Path::new(".")).unwrap();
+        io.stdout.write_all(b"out\n").unwrap();
+        io.stderr.write_all(b"err\n").unwrap();
+
+        let content = fs::read_to_string("test_redirect_both.txt").unwrap();
+        assert_eq!(content, "out\nerr\n");
+
+        fs::remove_file("test_redirect_both.txt").unwrap();
+    }
+
+    #[test]
+    fn test_missing_input_file() {
+        let mut io = Io::inherit();
+        let result = apply(&redirects("cat < test_redirect_missing.txt"), &mut io, Path::new("."));
+        assert_eq!(
+            result,
+            Err("test_redirect_missing.txt: No such file or directory".to_string())
+        );
+    }
+
+    #[test]
+    fn test_unsupported_descriptor() {
+        let mut io = Io::inherit();
+        let result = apply(&redirects("cmd 1>&7"), &mut io, Path::new("."));
+        assert_eq!(result, Err("7: Bad file descriptor".to_string()));
+    }
+
+    #[test]
+    fn test_group_and_subshell_redirects() {
+        let mut shell = crate::shell::Shell::new();
+        let out = crate::streams::Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+
+        let script = "\
+{ echo a; echo b >&2; } > test_redirect_group.txt 2>&1
+(echo c; pwd >/dev/null) >> test_redirect_group.txt
+{ read_twice() { cat; }; read_twice; } < test_redirect_group.txt
+(cat) <<< d";
+        assert_eq!(shell.run_script(script), 0);
+        assert_eq!(fs::read_to_string("test_redirect_group.txt").unwrap(), "a\nb\nc\n");
+        assert_eq!(out.to_string_lossy(), "a\nb\nc\nd\n");
+
+        fs::remove_file("test_redirect_group.txt").unwrap();
+    }
+
+    #[test]
+    fn test_here_documents_and_strings() {
+        let mut shell = crate::shell::Shell::new();
+        let out = crate::streams::Buffer::new();
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_var("name", "world");
+
+        let script = "\
+cat <<EOF
+hello $name, \"$((6 * 7))\" \\$name
+EOF
+cat <<'EOF' | wc -l
+$name
+$(echo not run)
+EOF
+if true; then
+\tcat <<-END
+\t\tindented
+\tEND
+fi
+tr a-z A-Z <<< \"$name\"
+cat < /dev/null <<<first";
+        assert_eq!(shell.run_script(script), 0);
+        assert_eq!(
+            out.to_string_lossy(),
+            "hello world, \"42\" $name\n2\nindented\nWORLD\nfirst\n"
+        );
+    }
+}
@@ -0,0 +1,638 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use crate::builtins::{Builtin, ExitStatus, Registry};
+use crate::compound::LoopControl;
+use crate::history::History;
+use crate::jobs::{self, Jobs};
+use crate::parser::{self, AndOr, Command, Connector, List, ParseError};
+use crate::pipeline;
+use crate::signals;
+use crate::streams::{Input, Io, Output};
+
+/// A shell variable and whether it is passed on to child processes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+/// What a running function hides from its caller, restored when it returns
+#[derive(Clone)]
+struct Frame {
+    /// The caller's `$1`, `$2`, ...
+    positional: Vec<String>,
+    /// The caller's loop depth; `break` does not reach through a function
+    loop_depth: usize,
+    /// The variables made `local`, with the values they had before
+    locals: Vec<(String, Option<Variable>)>,
+}
+
+/// An interpreter instance: everything commands can observe or change
+///
+/// A `Shell` owns its working directory, variables and standard streams
+/// instead of using the process-wide ones, so several shells can live in
+/// one program and their output can be captured:
+///
+/// ```
+/// use zero_shell::streams::{Buffer, Output};
+/// use zero_shell::Shell;
+///
+/// let mut shell = Shell::new();
+/// let out = Buffer::new();
+/// shell.set_stdout(Output::Buffer(out.clone()));
+///
+/// let status = shell.run_line("echo hello && exit 3");
+/// assert_eq!(out.to_string_lossy(), "hello\n");
+/// assert_eq!(status, 3);
+/// assert_eq!(shell.exit_requested(), Some(3));
+/// ```
+#[derive(Clone)]
+pub struct Shell {
+    cwd: PathBuf,
+    variables: BTreeMap<String, Variable>,
+    last_status: ExitStatus,
+    exit_requested: Option<ExitStatus>,
+    /// `$0`: the script being run, or the shell's own name
+    script_name: String,
+    /// `$1`, `$2`, ...
+    positional: Vec<String>,
+    /// `alias` definitions, replaced in command names when a line is parsed
+    aliases: BTreeMap<String, String>,
+    /// Functions defined with `name() { ...; }`, by name
+    functions: BTreeMap<String, Arc<Command>>,
+    /// One frame for each function being run, innermost last
+    frames: Vec<Frame>,
+    /// How many files `source` is running
+    source_depth: usize,
+    /// The status of a `return` on its way out to its function or file
+    returning: Option<ExitStatus>,
+    /// How many loops the running command is inside of
+    loop_depth: usize,
+    /// A `break` or `continue` on its way out to its loop
+    loop_control: Option<LoopControl>,
+    /// How many arithmetic expansions are being expanded, one inside another
+    arithmetic_depth: usize,
+    registry: Arc<Registry>,
+    history: History,
+    jobs: Jobs,
+    io: Io,
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Shell::new()
+    }
+}
+
+impl Shell {
+    /// A shell with the default builtins, started in the process's current
+    /// directory, with the process environment as its exported variables
+    /// and the process's standard streams
+    pub fn new() -> Self {
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+
+        let variables = env::vars()
+            .map(|(name, value)| (name, Variable { value, exported: true }))
+            .collect();
+
+        let mut shell = Shell {
+            cwd: PathBuf::new(),
+            variables,
+            last_status: 0,
+            exit_requested: None,
+            script_name: "0-shell".to_string(),
+            positional: Vec::new(),
+            aliases: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            frames: Vec::new(),
+            source_depth: 0,
+            returning: None,
+            loop_depth: 0,
+            loop_control: None,
+            arithmetic_depth: 0,
+            registry: Arc::new(Registry::with_defaults()),
+            history: History::new(),
+            jobs: Jobs::default(),
+            io: Io::inherit(),
+        };
+        shell.set_cwd(cwd);
+        shell
+    }
+
+    /// Parse and run one line of input, returning its exit status
+    /// Syntax errors are reported on the shell's stderr with status 2.
+    /// A Ctrl-C stops the rest of the line from running.
+    pub fn run_line(&mut self, line: &str) -> ExitStatus {
+        if self.exit_requested.is_some() {
+            return self.last_status;
+        }
+        let parsed = self.parse(line);
+        self.run_parsed(parsed)
+    }
+
+    /// Run a script one command at a time, stopping early if it calls
+    /// `exit` or is interrupted. Returns the status of the last command
+    /// that ran
+    ///
+    /// A command takes up as many lines as it needs: an `if` runs once its
+    /// `fi` has been read, so a later syntax error does not keep the
+    /// commands before it from running.
+    pub fn run_script(&mut self, script: &str) -> ExitStatus {
+        self.run_commands(script);
+        self.last_status
+    }
+
+    /// Run a script as `run_script` does, returning whether it had any
+    /// commands to run, or a syntax error to report
+    fn run_commands(&mut self, script: &str) -> bool {
+        let mut ran = false;
+        let mut pending = String::new();
+        let mut lines = script.lines().peekable();
+
+        while let Some(line) = lines.next() {
+            if self.exit_requested.is_some() || self.returning.is_some() {
+                break;
+            }
+            pending.push_str(line);
+            pending.push('\n');
+
+            let parsed = self.parse(&pending);
+            if matches!(&parsed, Err(error) if error.is_incomplete()) && lines.peek().is_some() {
+                continue;
+            }
+            if std::mem::take(&mut pending).trim().is_empty() {
+                continue;
+            }
+            ran |= !matches!(&parsed, Ok(list) if list.items.is_empty());
+            self.run_parsed(parsed);
+            if signals::interrupted() {
+                break;
+            }
+        }
+        ran
+    }
+
+    /// Parse input with this shell's aliases
+    pub fn parse(&self, input: &str) -> Result<List, ParseError> {
+        parser::parse_with_aliases(input, &self.aliases)
+    }
+
+    fn run_parsed(&mut self, parsed: Result<List, ParseError>) -> ExitStatus {
+        signals::clear();
+
+        match parsed {
+            Ok(list) => self.execute_list(&list),
+            Err(error) => {
+                let _ = writeln!(self.io.stderr, "0-shell: {}", error);
+                self.last_status = 2;
+                2
+            }
+        }
+    }
+
+    /// Run the commands in a file in this shell, as `source` does, so
+    /// variables and the directory it changes stay changed. Returns the
+    /// status of the last command, or 0 for a file without any
+    ///
+    /// `return` ends the file early, with the status it is given.
+    pub fn source(&mut self, path: impl AsRef<Path>) -> io::Result<ExitStatus> {
+        let script = fs::read(self.resolve(path))?;
+        self.source_depth += 1;
+        let ran = self.run_commands(&String::from_utf8_lossy(&script));
+        self.source_depth -= 1;
+        let status = if ran { self.last_status } else { 0 };
+        Ok(self.returning.take().unwrap_or(status))
+    }
+
+    pub(crate) fn execute_list(&mut self, list: &List) -> ExitStatus {
+        for and_or in &list.items {
+            if and_or.background {
+                self.last_status = jobs::spawn_background(self, and_or);
+            } else {
+                self.execute_and_or(and_or);
+            }
+
+            if self.stopping() {
+                break;
+            }
+        }
+
+        self.last_status
+    }
+
+    pub(crate) fn execute_and_or(&mut self, and_or: &AndOr) -> ExitStatus {
+        self.last_status = pipeline::execute(self, &and_or.first);
+
+        for (connector, next) in &and_or.rest {
+            if self.stopping() {
+                break;
+            }
+            let run = match connector {
+                Connector::And => self.last_status == 0,
+                Connector::Or => self.last_status != 0,
+            };
+            if run {
+                self.last_status = pipeline::execute(self, next);
+            }
+        }
+
+        self.last_status
+    }
+
+    /// Whether the rest of the commands must be skipped: after `exit`,
+    /// Ctrl-C, or while a `break`, `continue` or `return` is on its way out
+    pub(crate) fn stopping(&self) -> bool {
+        self.exit_requested.is_some()
+            || signals::interrupted()
+            || self.loop_control.is_some()
+            || self.returning.is_some()
+    }
+
+    /// How many loops the running command is inside of
+    pub(crate) fn loop_depth(&self) -> usize {
+        self.loop_depth
+    }
+
+    pub(crate) fn enter_loop(&mut self) {
+        self.loop_depth += 1;
+    }
+
+    pub(crate) fn leave_loop(&mut self) {
+        self.loop_depth -= 1;
+    }
+
+    /// Make the enclosing loops stop or go on, for `break` and `continue`
+    pub(crate) fn set_loop_control(&mut self, control: LoopControl) {
+        self.loop_control = Some(control);
+    }
+
+    pub(crate) fn take_loop_control(&mut self) -> Option<LoopControl> {
+        self.loop_control.take()
+    }
+
+    /// How many arithmetic expansions are being expanded, one inside another
+    pub(crate) fn arithmetic_depth(&self) -> usize {
+        self.arithmetic_depth
+    }
+
+    pub(crate) fn enter_arithmetic(&mut self) {
+        self.arithmetic_depth += 1;
+    }
+
+    pub(crate) fn leave_arithmetic(&mut self) {
+        self.arithmetic_depth -= 1;
+    }
+
+    /// How many functions are running, the innermost one calling the rest
+    pub(crate) fn function_depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Start running a function with `args` as its positional parameters
+    pub(crate) fn enter_function(&mut self, args: Vec<String>) {
+        self.frames.push(Frame {
+            positional: std::mem::replace(&mut self.positional, args),
+            loop_depth: std::mem::take(&mut self.loop_depth),
+            locals: Vec::new(),
+        });
+    }
+
+    /// Finish running a function whose body ended with `status`, bringing
+    /// back what it hid. Returns the function's status: the one given to
+    /// `return`, if it returned that way.
+    pub(crate) fn leave_function(&mut self, status: ExitStatus) -> ExitStatus {
+        if let Some(frame) = self.frames.pop() {
+            for (name, var) in frame.locals.into_iter().rev() {
+                match var {
+                    Some(var) => self.set_variable(&name, var),
+                    None => self.unset_var(&name),
+                }
+            }
+            self.positional = frame.positional;
+            self.loop_depth = frame.loop_depth;
+        }
+        self.returning.take().unwrap_or(status)
+    }
+
+    /// Make a variable local to the running function, so the value it has
+    /// now comes back when the function returns. Returns false outside of
+    /// a function.
+    pub(crate) fn make_local(&mut self, name: &str) -> bool {
+        let Some(frame) = self.frames.last_mut() else {
+            return false;
+        };
+        if !frame.locals.iter().any(|(local, _)| local == name) {
+            frame.locals.push((name.to_string(), self.variables.get(name).cloned()));
+        }
+        true
+    }
+
+    /// The names made local in the running function, in the order it did so
+    pub(crate) fn locals(&self) -> impl Iterator<Item = &str> {
+        self.frames
+            .last()
+            .into_iter()
+            .flat_map(|frame| frame.locals.iter().map(|(name, _)| name.as_str()))
+    }
+
+    /// Whether `return` has anything to return from: a function or a file
+    /// being sourced
+    pub(crate) fn can_return(&self) -> bool {
+        !self.frames.is_empty() || self.source_depth > 0
+    }
+
+    /// Leave the running function or sourced file with `status`
+    pub(crate) fn set_return(&mut self, status: ExitStatus) {
+        self.returning = Some(status);
+    }
+
+    /// Exit status of the last command (`$?`)
+    pub fn last_status(&self) -> ExitStatus {
+        self.last_status
+    }
+
+    /// The status passed to `exit`, once a command has asked to exit
+    /// The shell runs no further commands after that
+    pub fn exit_requested(&self) -> Option<ExitStatus> {
+        self.exit_requested
+    }
+
+    /// Ask the shell to stop running commands and exit with `status`
+    pub fn request_exit(&mut self, status: ExitStatus) {
+        self.exit_requested = Some(status);
+    }
+
+    /// `$0`, the name of the running script
+    pub fn script_name(&self) -> &str {
+        &self.script_name
+    }
+
+    pub fn set_script_name(&mut self, name: &str) {
+        self.script_name = name.to_string();
+    }
+
+    /// The positional parameters `$1`, `$2`, ... (`$@`)
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn set_positional(&mut self, args: Vec<String>) {
+        self.positional = args;
+    }
+
+    /// The shell's working directory
+    pub fn cwd(&self) -> &Path {
+        &self.cwd
+    }
+
+    /// Change the working directory, updating `$PWD` and `$OLDPWD`
+    /// A relative path is taken relative to the current one, and `.` and
+    /// `..` components are resolved logically, the way `cd` does
+    pub fn set_cwd(&mut self, path: impl AsRef<Path>) {
+        let cwd = normalize(&self.resolve(path));
+        let old = std::mem::replace(&mut self.cwd, cwd);
+
+        if !old.as_os_str().is_empty() {
+            self.set_exported("OLDPWD", &old.to_string_lossy());
+        }
+        let pwd = self.cwd.to_string_lossy().into_owned();
+        self.set_exported("PWD", &pwd);
+    }
+
+    /// Resolve a path relative to the shell's working directory
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.cwd.join(path)
+    }
+
+    /// The value of a variable, if it is set
+    pub fn var(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(|var| var.value.as_str())
+    }
+
+    /// Set a variable, keeping its exported flag if it already existed
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        match self.variables.get_mut(name) {
+            Some(var) => var.value = value.to_string(),
+            None => {
+                let var = Variable {
+                    value: value.to_string(),
+                    exported: false,
+                };
+                self.variables.insert(name.to_string(), var);
+            }
+        }
+    }
+
+    /// Set a variable and mark it for export to child processes
+    pub fn set_exported(&mut self, name: &str, value: &str) {
+        let var = Variable {
+            value: value.to_string(),
+            exported: true,
+        };
+        self.set_variable(name, var);
+    }
+
+    /// Set a variable together with its exported flag
+    pub fn set_variable(&mut self, name: &str, var: Variable) {
+        self.variables.insert(name.to_string(), var);
+    }
+
+    /// Remove a variable
+    pub fn unset_var(&mut self, name: &str) {
+        self.variables.remove(name);
+    }
+
+    /// All variables, sorted by name
+    pub fn variables(&self) -> &BTreeMap<String, Variable> {
+        &self.variables
+    }
+
+    /// The environment handed to external commands: exported variables only
+    pub fn env(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.variables
+            .iter()
+            .filter(|(_, var)| var.exported)
+            .map(|(name, var)| (name.as_str(), var.value.as_str()))
+    }
+
+    /// The builtins commands are looked up in
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Add a builtin, replacing any existing one with the same name
+    pub fn register(&mut self, builtin: impl Builtin + 'static) {
+        Arc::make_mut(&mut self.registry).register(builtin);
+    }
+
+    /// The aliases defined with `alias`, by name
+    pub fn aliases(&self) -> &BTreeMap<String, String> {
+        &self.aliases
+    }
+
+    pub fn aliases_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.aliases
+    }
+
+    /// The functions defined so far, by name, each with its body
+    pub fn functions(&self) -> &BTreeMap<String, Arc<Command>> {
+        &self.functions
+    }
+
+    pub fn functions_mut(&mut self) -> &mut BTreeMap<String, Arc<Command>> {
+        &mut self.functions
+    }
+
+    /// Commands entered so far, for the `history` builtin and `!!`
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// The jobs started with `&` or stopped with Ctrl-Z
+    pub fn jobs(&self) -> &Jobs {
+        &self.jobs
+    }
+
+    pub fn jobs_mut(&mut self) -> &mut Jobs {
+        &mut self.jobs
+    }
+
+    /// The streams commands start with before pipes and redirections
+    pub fn io(&self) -> &Io {
+        &self.io
+    }
+
+    pub fn set_stdin(&mut self, input: Input) {
+        self.io.stdin = input;
+    }
+
+    pub fn set_stdout(&mut self, output: Output) {
+        self.io.stdout = output;
+    }
+
+    pub fn set_stderr(&mut self, output: Output) {
+        self.io.stderr = output;
+    }
+
+    /// A copy of the shell for commands whose changes must not reach it,
+    /// like those of a command substitution; the jobs stay with this shell
+    pub(crate) fn subshell(&self) -> Shell {
+        let mut subshell = self.clone();
+        subshell.jobs = self.jobs.for_subshell();
+        subshell
+    }
+
+    /// Exchange the shell's streams with `io`, for builtins that run
+    /// commands with their own, redirected ones
+    pub(crate) fn swap_io(&mut self, io: &mut Io) {
+        std::mem::swap(&mut self.io, io);
+    }
+}
+
+/// Resolve `.` and `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other),
+        }
+    }
+
+    if result.as_os_str().is_empty() {
+        result.push("/");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::streams::Buffer;
+
+    /// A shell whose stdout and stderr are captured into buffers
+    fn captured() -> (Shell, Buffer, Buffer) {
+        let mut shell = Shell::new();
+        let (out, err) = (Buffer::new(), Buffer::new());
+        shell.set_stdout(Output::Buffer(out.clone()));
+        shell.set_stderr(Output::Buffer(err.clone()));
+        (shell, out, err)
+    }
+
+    #[test]
+    fn test_capture_builtin_and_external_output() {
+        let (mut shell, out, err) = captured();
+
+        assert_eq!(shell.run_line("echo from builtin"), 0);
+        assert_eq!(shell.run_line("sh -c 'echo from child; echo oops >&2'"), 0);
+        assert_eq!(out.to_string_lossy(), "from builtin\nfrom child\n");
+        assert_eq!(err.to_string_lossy(), "oops\n");
+    }
+
+    #[test]
+    fn test_exit_status_and_exit_request() {
+        let (mut shell, out, _) = captured();
+
+        assert_eq!(shell.run_line("ls /nonexistent_0shell"), 1);
+        assert_eq!(shell.last_status(), 1);
+
+        assert_eq!(shell.run_script("echo one\nexit 4\necho never\n"), 4);
+        assert_eq!(shell.exit_requested(), Some(4));
+        assert_eq!(out.to_string_lossy(), "one\n");
+    }
+
+    #[test]
+    fn test_syntax_error_status() {
+        let (mut shell, _, err) = captured();
+
+        assert_eq!(shell.run_line("echo 'unterminated"), 2);
+        assert!(err.to_string_lossy().starts_with("0-shell: unexpected EOF"));
+    }
+
+    #[test]
+    fn test_cd_is_local_to_the_shell() {
+        let process_cwd = env::current_dir().unwrap();
+        fs::create_dir_all("test_shell_cd/inner").unwrap();
+        let (mut shell, out, _) = captured();
+
+        assert_eq!(shell.run_line("cd test_shell_cd/inner && cd .. && pwd"), 0);
+        let expected = process_cwd.join("test_shell_cd");
+        assert_eq!(out.to_string_lossy(), format!("{}\n", expected.display()));
+        assert_eq!(shell.cwd(), expected);
+        assert_eq!(shell.var("PWD"), Some(expected.to_str().unwrap()));
+        assert_eq!(env::current_dir().unwrap(), process_cwd);
+
+        fs::remove_dir_all("test_shell_cd").unwrap();
+    }
+
+    #[test]
+    fn test_exported_variables_reach_children() {
+        let (mut shell, out, _) = captured();
+        shell.set_exported("ZERO_SHELL_TEST", "exported");
+        shell.set_var("ZERO_SHELL_HIDDEN", "local");
+
+        shell.run_line("sh -c 'echo $ZERO_SHELL_TEST-$ZERO_SHELL_HIDDEN'");
+        assert_eq!(out.to_string_lossy(), "exported-\n");
+        assert!(shell.env().any(|(name, _)| name == "ZERO_SHELL_TEST"));
+        assert!(!shell.env().any(|(name, _)| name == "ZERO_SHELL_HIDDEN"));
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(normalize(Path::new("/..")), PathBuf::from("/"));
+    }
+}
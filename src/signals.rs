@@ -0,0 +1,125 @@
+//! Signal handling for the interactive shell
+//!
+//! An interactive shell must survive the Ctrl-C meant for the command it
+//! is running. [`install`] makes SIGINT only raise a flag, and ignores
+//! SIGQUIT, SIGTSTP and the signals that stop background processes using
+//! the terminal. External programs get the default dispositions back
+//! before they start, so Ctrl-C still kills them; built-ins check
+//! [`interrupted`] while they work and give up with [`INTERRUPT_STATUS`].
+//!
+//! Non-interactive shells leave the dispositions they inherited alone.
+
+#[cfg(test)]
+use std::cell::Cell;
+use std::io;
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::builtins::ExitStatus;
+use crate::sys::{self, Disposition};
+
+/// The status of a command stopped by Ctrl-C: 128 + SIGINT
+pub const INTERRUPT_STATUS: ExitStatus = 128 + sys::SIGINT;
+
+/// Set by the SIGINT handler, cleared when the next command line starts
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    /// An interrupt that only the thread running [`with_interrupt`] sees
+    static INTERRUPTED_HERE: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether [`install`] has changed the dispositions children must undo
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_signal: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catch SIGINT and ignore SIGQUIT, SIGTSTP, SIGTTIN and SIGTTOU in this
+/// process
+pub fn install() -> io::Result<()> {
+    sys::set_disposition(sys::SIGINT, Disposition::Handle(on_interrupt))?;
+    for signal in [sys::SIGQUIT, sys::SIGTSTP, sys::SIGTTIN, sys::SIGTTOU] {
+        sys::set_disposition(signal, Disposition::Ignore)?;
+    }
+    INSTALLED.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Whether Ctrl-C was pressed since the current command line started
+///
+/// Long-running built-ins should check this regularly and return
+/// [`INTERRUPT_STATUS`] once it is set.
+pub fn interrupted() -> bool {
+    #[cfg(test)]
+    if INTERRUPTED_HERE.with(Cell::get) {
+        return true;
+    }
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Run `f` as if Ctrl-C had been pressed, for tests
+///
+/// Only the calling thread sees the interrupt, so the tests running in
+/// parallel with it are not cut short.
+#[cfg(test)]
+pub(crate) fn with_interrupt<T>(f: impl FnOnce() -> T) -> T {
+    INTERRUPTED_HERE.with(|flag| flag.set(true));
+    let result = f();
+    INTERRUPTED_HERE.with(|flag| flag.set(false));
+    result
+}
+
+/// Record a Ctrl-C that went to a job instead of the shell, so the rest
+/// of the command line is skipped just the same
+pub(crate) fn interrupt() {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Forget an earlier interrupt, before running a new command line
+pub(crate) fn clear() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Put back the default dispositions in a child about to `exec`
+///
+/// Caught signals are reset by `exec` anyway, but ignored ones would be
+/// inherited. Only a `job`, with a process group of its own, may be
+/// stopped by Ctrl-Z: anything else shares the shell's group, and the
+/// shell would wait for it forever. Runs between `fork` and `exec`, so
+/// it only makes async-signal-safe calls.
+pub(crate) fn restore_defaults(job: bool) -> io::Result<()> {
+    if INSTALLED.load(Ordering::SeqCst) {
+        for signal in [sys::SIGINT, sys::SIGQUIT, sys::SIGTTIN, sys::SIGTTOU] {
+            sys::set_disposition(signal, Disposition::Default)?;
+        }
+        if job {
+            sys::set_disposition(sys::SIGTSTP, Disposition::Default)?;
+        }
+    }
+    Ok(())
+}
+
+/// Ignore SIGINT and SIGQUIT, as commands started with `&` do when there
+/// is no job control to keep Ctrl-C away from them
+pub(crate) fn ignore_interrupts() -> io::Result<()> {
+    sys::set_disposition(sys::SIGINT, Disposition::Ignore)?;
+    sys::set_disposition(sys::SIGQUIT, Disposition::Ignore)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_hook_stays_on_its_thread() {
+        assert!(!interrupted());
+        with_interrupt(|| {
+            assert!(interrupted());
+            assert!(!std::thread::spawn(interrupted).join().unwrap());
+        });
+        assert!(!interrupted());
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsFd, OwnedFd};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Where a command reads its standard input from
+#[derive(Clone)]
+pub enum Input {
+    /// The shell's own standard input
+    Stdin,
+    /// An open file or the read end of a pipe
+    File(Arc<File>),
+}
+
+/// Where a command writes one of its output streams to
+///
+/// Cloning an output shares the underlying handle, the same way `2>&1`
+/// makes two descriptors point at one open file.
+#[derive(Clone)]
+pub enum Output {
+    /// The shell's own standard output
+    Stdout,
+    /// The shell's own standard error
+    Stderr,
+    /// An open file or the write end of a pipe
+    File(Arc<File>),
+    /// An in-memory buffer, used to capture output when embedding the shell
+    Buffer(Buffer),
+}
+
+/// The three standard streams of a single command
+#[derive(Clone)]
+pub struct Io {
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+}
+
+/// A growable byte buffer that can be shared between the shell and the
+/// code embedding it, so output can be read back after commands ran
+#[derive(Clone, Default)]
+pub struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    pub fn new() -> Self {
+        Buffer::default()
+    }
+
+    /// A copy of everything written so far
+    pub fn contents(&self) -> Vec<u8> {
+        self.lock().clone()
+    }
+
+    /// Everything written so far, decoded as UTF-8 (lossily)
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.lock()).into_owned()
+    }
+
+    /// Remove and return everything written so far
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// Whether both handles share the same bytes, as `2>&1` makes them
+    pub fn same_as(&self, other: &Buffer) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        // A writer that panicked cannot leave the bytes inconsistent
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Io {
+    /// Streams connected to the shell's own stdin, stdout and stderr
+    pub fn inherit() -> Self {
+        Io {
+            stdin: Input::Stdin,
+            stdout: Output::Stdout,
+            stderr: Output::Stderr,
+        }
+    }
+
+    /// Streams a forked copy of the shell can use without touching a lock
+    /// another thread may hold: the shell's own streams become duplicates
+    /// of its descriptors, and buffers the write ends of pipes
+    ///
+    /// Also gives back the read ends of those pipes with their buffers, to
+    /// be handed to [`pump`] after the fork. Streams sharing a buffer share
+    /// a pipe, so their output stays in order.
+    pub(crate) fn for_fork(&self) -> io::Result<(Io, Vec<(io::PipeReader, Buffer)>)> {
+        let mut pipes: Vec<(io::PipeReader, Buffer, Output)> = Vec::new();
+        let mut detach = |output: &Output| -> io::Result<Output> {
+            Ok(match output {
+                Output::Stdout => Output::File(Arc::new(duplicate(io::stdout().as_fd())?)),
+                Output::Stderr => Output::File(Arc::new(duplicate(io::stderr().as_fd())?)),
+                Output::File(file) => Output::File(file.clone()),
+                Output::Buffer(buffer) => {
+                    if let Some((_, _, writer)) = pipes.iter().find(|(_, other, _)| other.same_as(buffer)) {
+                        return Ok(writer.clone());
+                    }
+                    let (reader, writer) = io::pipe()?;
+                    let writer = Output::File(Arc::new(File::from(OwnedFd::from(writer))));
+                    pipes.push((reader, buffer.clone(), writer.clone()));
+                    writer
+                }
+            })
+        };
+
+        let stdin = match &self.stdin {
+            Input::Stdin => Input::File(Arc::new(duplicate(io::stdin().as_fd())?)),
+            Input::File(file) => Input::File(file.clone()),
+        };
+        let stdout = detach(&self.stdout)?;
+        let stderr = detach(&self.stderr)?;
+        let pipes = pipes.into_iter().map(|(reader, buffer, _)| (reader, buffer)).collect();
+        Ok((Io { stdin, stdout, stderr }, pipes))
+    }
+}
+
+/// A new descriptor for the same open file
+fn duplicate(fd: std::os::fd::BorrowedFd) -> io::Result<File> {
+    Ok(File::from(fd.try_clone_to_owned()?))
+}
+
+/// Copy everything that arrives on a pipe into a buffer, on a thread of
+/// its own; join it to be sure every byte has arrived, which is once all
+/// the write ends are closed
+pub(crate) fn pump(reader: io::PipeReader, mut buffer: Buffer) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut reader = reader;
+        let _ = io::copy(&mut reader, &mut buffer);
+    })
+}
+
+impl Input {
+    /// Convert into a child process's stdin
+    pub fn into_stdio(self) -> io::Result<Stdio> {
+        match self {
+            Input::Stdin => Ok(Stdio::inherit()),
+            Input::File(file) => Ok(Stdio::from(unwrap_file(file)?)),
+        }
+    }
+}
+
+impl Output {
+    /// Convert into a stream a child process can write to
+    ///
+    /// A buffer cannot be handed to another process, so for those a pipe is
+    /// created and a thread copies whatever arrives into the buffer. Join the
+    /// returned handle after the child has exited to be sure every byte
+    /// has been copied.
+    pub fn into_stdio(self) -> io::Result<(Stdio, Option<JoinHandle<()>>)> {
+        match self {
+            Output::Stdout => Ok((Stdio::inherit(), None)),
+            Output::Stderr => Ok((Stdio::from(io::stderr()), None)),
+            Output::File(file) => Ok((Stdio::from(unwrap_file(file)?), None)),
+            Output::Buffer(buffer) => {
+                let (reader, writer) = io::pipe()?;
+                Ok((Stdio::from(writer), Some(pump(reader, buffer))))
+            }
+        }
+    }
+}
+
+/// Take the file out of its `Arc`, duplicating the descriptor if the
+/// handle is still shared with another stream
+fn unwrap_file(file: Arc<File>) -> io::Result<File> {
+    Arc::try_unwrap(file).or_else(|shared| shared.try_clone())
+}
+
+impl Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Stdin => io::stdin().read(buf),
+            Input::File(file) => (&**file).read(buf),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Stdout => io::stdout().write(buf),
+            Output::Stderr => io::stderr().write(buf),
+            Output::File(file) => (&**file).write(buf),
+            Output::Buffer(buffer) => buffer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Stdout => io::stdout().flush(),
+            Output::Stderr => io::stderr().flush(),
+            Output::File(file) => (&**file).flush(),
+            Output::Buffer(buffer) => buffer.flush(),
+        }
+    }
+}
+
+/// Create a pipe, returning its (read, write) ends as command streams
+pub fn pipe() -> io::Result<(Input, Output)> {
+    let (reader, writer) = io::pipe()?;
+    let reader = File::from(OwnedFd::from(reader));
+    let writer = File::from(OwnedFd::from(writer));
+    Ok((Input::File(Arc::new(reader)), Output::File(Arc::new(writer))))
+}
+
+/// Describe an I/O error the way Unix tools do, without the
+/// "(os error N)" suffix that the standard library appends
+pub fn describe(error: &io::Error) -> String {
+    let message = error.to_string();
+    match message.find(" (os error") {
+        Some(index) => message[..index].to_string(),
+        None => message,
+    }
+}
+
+/// Whether an error was caused by the reading end of a pipe going away
+/// Builtins stop quietly in that case, like external commands do on SIGPIPE
+pub fn is_broken_pipe(error: &(dyn std::error::Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<io::Error>(),
+        Some(e) if e.kind() == io::ErrorKind::BrokenPipe
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pipe_round_trip() {
+        let (mut reader, mut writer) = pipe().unwrap();
+        writer.write_all(b"through the pipe").unwrap();
+        drop(writer);
+
+        let mut received = String::new();
+        reader.read_to_string(&mut received).unwrap();
+        assert_eq!(received, "through the pipe");
+    }
+
+    #[test]
+    fn test_buffer_is_shared_between_clones() {
+        let buffer = Buffer::new();
+        let mut output = Output::Buffer(buffer.clone());
+        let mut copy = output.clone();
+
+        output.write_all(b"one ").unwrap();
+        copy.write_all(b"two").unwrap();
+        assert_eq!(buffer.to_string_lossy(), "one two");
+
+        assert_eq!(buffer.take(), b"one two");
+        assert!(buffer.contents().is_empty());
+    }
+
+    #[test]
+    fn test_describe_strips_os_error() {
+        let error = io::Error::from_raw_os_error(2);
+        assert_eq!(describe(&error), "No such file or directory");
+
+        let error = io::Error::other("custom failure");
+        assert_eq!(describe(&error), "custom failure");
+    }
+
+    #[test]
+    fn test_broken_pipe_detection() {
+        let (reader, mut writer) = pipe().unwrap();
+        drop(reader);
+
+        let error = writer.write_all(b"nobody listening").unwrap_err();
+        let boxed: Box<dyn std::error::Error> = error.into();
+        assert!(is_broken_pipe(&*boxed));
+
+        let other: Box<dyn std::error::Error> = "Is a directory".into();
+        assert!(!is_broken_pipe(&*other));
+    }
+}
@@ -0,0 +1,341 @@
+//! Hand-written bindings for the few C library calls the shell needs
+//!
+//! The crate has no dependencies, so these are declared here instead of
+//! coming from `libc`. Layouts and constants are those of Linux on x86_64
+//! and aarch64, with glibc or musl; other targets number their signals and
+//! lay out their structs differently, so they are refused instead of
+//! getting silently wrong values.
+
+#[cfg(not(all(target_os = "linux", any(target_arch = "x86_64", target_arch = "aarch64"))))]
+compile_error!("the bindings in sys.rs are only written for Linux on x86_64 and aarch64");
+
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_ushort};
+
+/// `pid_t`
+pub type Pid = c_int;
+
+pub const STDIN_FILENO: c_int = 0;
+pub const STDOUT_FILENO: c_int = 1;
+
+// c_iflag bits
+pub const ICRNL: c_uint = 0o000400;
+pub const IXON: c_uint = 0o002000;
+
+// c_lflag bits
+pub const ISIG: c_uint = 0o000001;
+pub const ICANON: c_uint = 0o000002;
+pub const ECHO: c_uint = 0o000010;
+pub const IEXTEN: c_uint = 0o100000;
+
+// c_cc indices
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+
+// Signal numbers
+pub const SIGINT: c_int = 2;
+pub const SIGQUIT: c_int = 3;
+pub const SIGCONT: c_int = 18;
+pub const SIGTSTP: c_int = 20;
+pub const SIGTTIN: c_int = 21;
+pub const SIGTTOU: c_int = 22;
+
+// waitpid options
+pub const WNOHANG: c_int = 1;
+pub const WUNTRACED: c_int = 2;
+pub const WCONTINUED: c_int = 8;
+
+const SIG_DFL: usize = 0;
+const SIG_IGN: usize = 1;
+
+const TCSADRAIN: c_int = 1;
+const LOCK_EX: c_int = 2;
+const TIOCGWINSZ: c_ulong = 0x5413;
+
+/// `struct termios` from `<termios.h>`
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Termios {
+    pub c_iflag: c_uint,
+    pub c_oflag: c_uint,
+    pub c_cflag: c_uint,
+    pub c_lflag: c_uint,
+    pub c_line: c_uchar,
+    pub c_cc: [c_uchar; 32],
+    pub c_ispeed: c_uint,
+    pub c_ospeed: c_uint,
+}
+
+/// `struct sigaction` as glibc and musl lay it out
+#[repr(C)]
+struct Sigaction {
+    sa_handler: usize,
+    sa_mask: [c_ulong; 128 / std::mem::size_of::<c_ulong>()],
+    sa_flags: c_int,
+    sa_restorer: usize,
+}
+
+/// What happens when a signal arrives
+#[derive(Clone, Copy)]
+pub enum Disposition {
+    Default,
+    Ignore,
+    /// Call a function, which may only do async-signal-safe things
+    Handle(extern "C" fn(c_int)),
+}
+
+/// `struct tm` from `<time.h>`
+#[repr(C)]
+struct Tm {
+    tm_sec: c_int,
+    tm_min: c_int,
+    tm_hour: c_int,
+    tm_mday: c_int,
+    tm_mon: c_int,
+    tm_year: c_int,
+    tm_wday: c_int,
+    tm_yday: c_int,
+    tm_isdst: c_int,
+    tm_gmtoff: c_long,
+    tm_zone: *const c_char,
+}
+
+/// A moment broken down in the local time zone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i32,
+    /// 1 to 12
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    /// 0 for Sunday to 6 for Saturday
+    pub weekday: u32,
+}
+
+/// `struct winsize` from `<sys/ioctl.h>`
+#[repr(C)]
+#[derive(Default)]
+struct Winsize {
+    ws_row: c_ushort,
+    ws_col: c_ushort,
+    ws_xpixel: c_ushort,
+    ws_ypixel: c_ushort,
+}
+
+extern "C" {
+    fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+    fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    fn flock(fd: c_int, operation: c_int) -> c_int;
+    fn sigaction(signal: c_int, action: *const Sigaction, old: *mut Sigaction) -> c_int;
+    fn fork() -> Pid;
+    fn _exit(status: c_int) -> !;
+    fn getpgrp() -> Pid;
+    fn setpgid(pid: Pid, pgid: Pid) -> c_int;
+    fn tcgetpgrp(fd: c_int) -> Pid;
+    fn tcsetpgrp(fd: c_int, pgid: Pid) -> c_int;
+    fn kill(pid: Pid, signal: c_int) -> c_int;
+    fn waitpid(pid: Pid, status: *mut c_int, options: c_int) -> Pid;
+    fn localtime_r(time: *const i64, result: *mut Tm) -> *mut Tm;
+    fn gethostname(name: *mut c_char, length: usize) -> c_int;
+    fn getuid() -> c_uint;
+    fn geteuid() -> c_uint;
+}
+
+/// Turn a C-style `-1` return value into the current `errno`
+fn check(result: c_int) -> io::Result<c_int> {
+    if result == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// The terminal settings of `fd`
+pub fn get_termios(fd: c_int) -> io::Result<Termios> {
+    let mut termios = std::mem::MaybeUninit::<Termios>::uninit();
+    // SAFETY: tcgetattr fills in the whole struct when it succeeds
+    unsafe {
+        check(tcgetattr(fd, termios.as_mut_ptr()))?;
+        Ok(termios.assume_init())
+    }
+}
+
+/// Change the terminal settings of `fd` once pending output is written
+pub fn set_termios(fd: c_int, termios: &Termios) -> io::Result<()> {
+    // SAFETY: the pointer is valid for the duration of the call
+    check(unsafe { tcsetattr(fd, TCSADRAIN, termios) }).map(|_| ())
+}
+
+/// The number of columns of the terminal behind `fd`, if it is one
+pub fn terminal_width(fd: c_int) -> Option<usize> {
+    let mut size = Winsize::default();
+    // SAFETY: TIOCGWINSZ writes a `struct winsize` through the pointer
+    let result = unsafe { ioctl(fd, TIOCGWINSZ, &mut size as *mut Winsize) };
+    (result == 0 && size.ws_col > 0).then_some(size.ws_col as usize)
+}
+
+/// Take an exclusive advisory lock on a file, waiting for other holders
+/// The lock is released when the file is closed
+pub fn lock_exclusive(file: &File) -> io::Result<()> {
+    loop {
+        // SAFETY: flock only takes the descriptor, which `file` keeps open
+        match check(unsafe { flock(file.as_raw_fd(), LOCK_EX) }) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result.map(|_| ()),
+        }
+    }
+}
+
+/// Set how a signal is handled
+///
+/// `SA_RESTART` is deliberately left out, so a blocking read or wait
+/// returns `EINTR` when a handled signal arrives and the caller gets a
+/// chance to look at what the handler recorded. Only async-signal-safe
+/// calls are made, so this may be used between `fork` and `exec`.
+pub fn set_disposition(signal: c_int, disposition: Disposition) -> io::Result<()> {
+    let sa_handler = match disposition {
+        Disposition::Default => SIG_DFL,
+        Disposition::Ignore => SIG_IGN,
+        Disposition::Handle(handler) => handler as usize,
+    };
+    let action = Sigaction {
+        sa_handler,
+        sa_mask: [0; 128 / std::mem::size_of::<c_ulong>()],
+        sa_flags: 0,
+        sa_restorer: 0,
+    };
+    // SAFETY: the action is fully initialized and the old one is not asked for
+    check(unsafe { sigaction(signal, &action, std::ptr::null_mut()) }).map(|_| ())
+}
+
+/// How a waited-for process changed state, decoded from `waitpid`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitStatus {
+    Exited(c_int),
+    Signaled(c_int),
+    Stopped(c_int),
+    Continued,
+}
+
+impl WaitStatus {
+    fn decode(status: c_int) -> WaitStatus {
+        if status == 0xffff {
+            WaitStatus::Continued
+        } else if status & 0xff == 0x7f {
+            WaitStatus::Stopped((status >> 8) & 0xff)
+        } else if status & 0x7f == 0 {
+            WaitStatus::Exited((status >> 8) & 0xff)
+        } else {
+            WaitStatus::Signaled(status & 0x7f)
+        }
+    }
+}
+
+/// Wait for a child, or any child in group `-pid`, to change state as
+/// selected by the `W*` options. Returns which child it was, or `None`
+/// when `WNOHANG` is given and nothing has happened yet.
+pub fn wait_pid(pid: Pid, options: c_int) -> io::Result<Option<(Pid, WaitStatus)>> {
+    let mut status = 0;
+    // SAFETY: the status pointer is valid for the duration of the call
+    let child = check(unsafe { waitpid(pid, &mut status, options) })?;
+    Ok((child != 0).then(|| (child, WaitStatus::decode(status))))
+}
+
+/// Create a child process that is a copy of this one
+/// Returns 0 in the child and the child's pid in the parent.
+///
+/// # Safety
+///
+/// Only the calling thread exists in the child. Nothing another thread
+/// was holding a lock in may be touched there, and the child must end
+/// with [`exit_immediately`] instead of returning.
+pub unsafe fn fork_process() -> io::Result<Pid> {
+    check(fork())
+}
+
+/// End the process at once, without running destructors or exit handlers
+/// Used by forked children, whose copies of the parent's state must not
+/// be cleaned up twice. Flush anything buffered first.
+pub fn exit_immediately(status: c_int) -> ! {
+    // SAFETY: _exit never returns and has no preconditions
+    unsafe { _exit(status) }
+}
+
+pub fn process_group() -> Pid {
+    // SAFETY: getpgrp cannot fail
+    unsafe { getpgrp() }
+}
+
+/// Move process `pid` (0 for this one) into group `pgid` (0 to start a
+/// new group led by it)
+pub fn set_process_group(pid: Pid, pgid: Pid) -> io::Result<()> {
+    // SAFETY: setpgid only takes integers
+    check(unsafe { setpgid(pid, pgid) }).map(|_| ())
+}
+
+/// The process group the terminal behind `fd` sends its signals to
+pub fn foreground_group(fd: c_int) -> io::Result<Pid> {
+    // SAFETY: tcgetpgrp only takes the descriptor
+    check(unsafe { tcgetpgrp(fd) })
+}
+
+/// Make `pgid` the foreground process group of the terminal behind `fd`
+pub fn set_foreground_group(fd: c_int, pgid: Pid) -> io::Result<()> {
+    // SAFETY: tcsetpgrp only takes integers
+    check(unsafe { tcsetpgrp(fd, pgid) }).map(|_| ())
+}
+
+/// Send a signal to a process, or to a process group when `pid` is negative
+pub fn send_signal(pid: Pid, signal: c_int) -> io::Result<()> {
+    // SAFETY: kill only takes integers
+    check(unsafe { kill(pid, signal) }).map(|_| ())
+}
+
+/// Break a time in seconds since the epoch down in the local time zone,
+/// which is taken from `$TZ` or `/etc/localtime`
+pub fn local_time(seconds: i64) -> Option<LocalTime> {
+    let mut tm = std::mem::MaybeUninit::<Tm>::uninit();
+    // SAFETY: localtime_r fills in the whole struct when it succeeds
+    let tm = unsafe {
+        if localtime_r(&seconds, tm.as_mut_ptr()).is_null() {
+            return None;
+        }
+        tm.assume_init()
+    };
+    Some(LocalTime {
+        year: tm.tm_year + 1900,
+        month: (tm.tm_mon + 1) as u32,
+        day: tm.tm_mday as u32,
+        hour: tm.tm_hour as u32,
+        minute: tm.tm_min as u32,
+        second: tm.tm_sec as u32,
+        weekday: tm.tm_wday as u32,
+    })
+}
+
+/// The name of this machine
+pub fn hostname() -> io::Result<String> {
+    let mut buffer = [0u8; 256];
+    // SAFETY: the length passed is that of the buffer; the last byte is
+    // left alone so the name is always terminated
+    check(unsafe { gethostname(buffer.as_mut_ptr() as *mut c_char, buffer.len() - 1) })?;
+    let length = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    Ok(String::from_utf8_lossy(&buffer[..length]).into_owned())
+}
+
+/// The real user id of this process
+pub fn user_id() -> u32 {
+    // SAFETY: getuid cannot fail
+    unsafe { getuid() }
+}
+
+/// The effective user id, which decides what the process may do
+pub fn effective_user_id() -> u32 {
+    // SAFETY: geteuid cannot fail
+    unsafe { geteuid() }
+}